@@ -0,0 +1,452 @@
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use sqlx::{Acquire, Column, Executor, Row, Sqlite, SqliteConnection, SqlitePool, TypeInfo, ValueRef};
+
+use crate::model::{AuditChainReport, AuditLogItem};
+
+// =====================================================
+// AUDIT TRAIL
+// =====================================================
+// Every row carries the hash of the row before it plus a hash of its own
+// contents, so editing, reordering or removing a row breaks the chain from
+// that point on. Triggers stop rows being deleted, or changed once sealed.
+//
+// Rows name the record they concern (entity type + id) and, for changes,
+// carry JSON snapshots of it before and after, so a record's whole history
+// can be pulled up without parsing the free-text details.
+
+/// Records that audit entries can point at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entity {
+    Patient,
+    Medication,
+    Prescription,
+    Supplier,
+    PurchaseOrder,
+    CountSession,
+    User,
+}
+
+impl Entity {
+    pub const ALL: [Entity; 7] = [
+        Entity::Patient, Entity::Medication, Entity::Prescription, Entity::Supplier,
+        Entity::PurchaseOrder, Entity::CountSession, Entity::User,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Entity::Patient => "patient",
+            Entity::Medication => "medication",
+            Entity::Prescription => "prescription",
+            Entity::Supplier => "supplier",
+            Entity::PurchaseOrder => "purchase_order",
+            Entity::CountSession => "count_session",
+            Entity::User => "user",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Entity> {
+        Entity::ALL.into_iter().find(|e| e.as_str() == s)
+    }
+
+    fn table(&self) -> &'static str {
+        match self {
+            Entity::Patient => "patients",
+            Entity::Medication => "medications",
+            Entity::Prescription => "prescriptions",
+            Entity::Supplier => "suppliers",
+            Entity::PurchaseOrder => "purchase_orders",
+            Entity::CountSession => "count_sessions",
+            Entity::User => "users",
+        }
+    }
+}
+
+/// One audit entry. Build with `Event::new` and chain `on`, `before` and
+/// `after` as the action allows.
+pub struct Event<'a> {
+    action: &'a str,
+    details: String,
+    entity: Option<(Entity, i64)>,
+    before: Option<Value>,
+    after: Option<Value>,
+}
+
+impl<'a> Event<'a> {
+    pub fn new(action: &'a str, details: impl Into<String>) -> Self {
+        Event { action, details: details.into(), entity: None, before: None, after: None }
+    }
+
+    /// The record this entry is about.
+    pub fn on(mut self, entity: Entity, id: i64) -> Self {
+        self.entity = Some((entity, id));
+        self
+    }
+
+    pub fn before(mut self, value: impl Into<Option<Value>>) -> Self {
+        self.before = value.into();
+        self
+    }
+
+    pub fn after(mut self, value: impl Into<Option<Value>>) -> Self {
+        self.after = value.into();
+        self
+    }
+}
+
+/// Appends one row to `audit_logs` and links it into the hash chain. Accepts
+/// a pool or an open transaction so the entry can commit atomically with the
+/// change it describes. Every audited action goes through here.
+pub async fn record<'a, A>(conn: A, username: &str, event: Event<'_>) -> Result<(), sqlx::Error>
+where
+    A: Acquire<'a, Database = Sqlite>,
+{
+    let mut tx = conn.begin().await?;
+
+    // The insert takes the write lock before reading the previous hash, so
+    // two writers can't both link to the same row
+    let row = sqlx::query_as::<_, ChainRow>(
+        "INSERT INTO audit_logs (username, action, details, entity_type, entity_id, before_json, after_json, prev_hash)
+         VALUES (?, ?, ?, ?, ?, ?, ?, (SELECT hash FROM audit_logs ORDER BY id DESC LIMIT 1))
+         RETURNING *"
+    )
+    .bind(username)
+    .bind(event.action)
+    .bind(&event.details)
+    .bind(event.entity.map(|(e, _)| e.as_str()))
+    .bind(event.entity.map(|(_, id)| id))
+    .bind(event.before.map(|v| v.to_string()))
+    .bind(event.after.map(|v| v.to_string()))
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query("UPDATE audit_logs SET hash = ? WHERE id = ?")
+        .bind(row_hash(&row))
+        .bind(row.id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await
+}
+
+/// Columns never copied into a snapshot.
+const REDACTED: [&str; 1] = ["password_hash"];
+
+/// The entity's current row as a JSON object (None if it doesn't exist), for
+/// the before/after of an event.
+pub async fn snapshot<'e, E>(executor: E, entity: Entity, id: i64) -> Result<Option<Value>, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    // The table name comes from the fixed Entity mapping, never from input
+    let sql = format!("SELECT * FROM {} WHERE id = ?", entity.table());
+    let Some(row) = sqlx::query(&sql).bind(id).fetch_optional(executor).await? else {
+        return Ok(None);
+    };
+
+    let mut object = Map::new();
+    for column in row.columns() {
+        let name = column.name();
+        if REDACTED.contains(&name) {
+            continue;
+        }
+        let raw = row.try_get_raw(column.ordinal())?;
+        // Decode by what is stored, not the declared type (SQLite columns are loosely typed)
+        let value = if raw.is_null() {
+            Value::Null
+        } else {
+            match raw.type_info().name() {
+                "INTEGER" => Value::from(row.try_get_unchecked::<i64, _>(column.ordinal())?),
+                "REAL" => Value::from(row.try_get_unchecked::<f64, _>(column.ordinal())?),
+                "TEXT" => Value::from(row.try_get_unchecked::<String, _>(column.ordinal())?),
+                _ => continue,
+            }
+        };
+        object.insert(name.to_string(), value);
+    }
+    Ok(Some(Value::Object(object)))
+}
+
+/// Every entry about one record, newest first. A patient's history also takes
+/// in the entries on their prescriptions (fills, refills, holds, voids).
+pub async fn history(conn: &mut SqliteConnection, entity: Entity, id: i64) -> Result<Vec<AuditLogItem>, sqlx::Error> {
+    sqlx::query_as::<_, AuditLogItem>(
+        "SELECT id, username, action, details, timestamp, entity_type, entity_id, before_json, after_json, hash
+         FROM audit_logs
+         WHERE (entity_type = ? AND entity_id = ?)
+            OR (? = 'patient' AND entity_type = 'prescription'
+                AND entity_id IN (SELECT id FROM prescriptions WHERE patient_id = ?))
+         ORDER BY id DESC"
+    )
+    .bind(entity.as_str()).bind(id)
+    .bind(entity.as_str()).bind(id)
+    .fetch_all(&mut *conn)
+    .await
+}
+
+/// Exports are written here, next to the database.
+pub const EXPORT_DIR: &str = "exports";
+
+/// Renders entries as CSV, hashes included so an inspector can re-check the
+/// chain from the export alone.
+pub fn to_csv(rows: &[AuditLogItem]) -> String {
+    let mut out = String::from("id,timestamp,username,action,details,entity_type,entity_id,before,after,hash\r\n");
+    for row in rows {
+        let fields = [
+            row.id.to_string(),
+            row.timestamp.clone(),
+            row.username.clone(),
+            row.action.clone(),
+            row.details.clone().unwrap_or_default(),
+            row.entity_type.clone().unwrap_or_default(),
+            row.entity_id.map(|id| id.to_string()).unwrap_or_default(),
+            row.before_json.clone().unwrap_or_default(),
+            row.after_json.clone().unwrap_or_default(),
+            row.hash.clone().unwrap_or_default(),
+        ];
+        let cells: Vec<String> = fields.iter().map(|f| csv_cell(f)).collect();
+        out.push_str(&cells.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Quotes a cell when needed, and defuses text a spreadsheet would run as a
+/// formula (details can contain anything typed at the counter).
+fn csv_cell(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) { format!("'{}", value) } else { value.to_string() };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn row_hash(row: &ChainRow) -> String {
+    let id = row.id.to_string();
+    let mut fields = vec![
+        row.prev_hash.as_deref().unwrap_or(""),
+        &id,
+        &row.timestamp,
+        &row.username,
+        &row.action,
+        row.details.as_deref().unwrap_or(""),
+    ];
+    // Entity columns came later; rows without them hash exactly as before
+    let entity_id = row.entity_id.map(|id| id.to_string());
+    if row.entity_type.is_some() || row.before_json.is_some() || row.after_json.is_some() {
+        fields.extend([
+            row.entity_type.as_deref().unwrap_or(""),
+            entity_id.as_deref().unwrap_or(""),
+            row.before_json.as_deref().unwrap_or(""),
+            row.after_json.as_deref().unwrap_or(""),
+        ]);
+    }
+
+    let mut hasher = Sha256::new();
+    for field in fields {
+        hasher.update(field.as_bytes());
+        // Unit separator, so adjacent fields can't be shifted into each other
+        hasher.update([0x1f]);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[derive(sqlx::FromRow)]
+struct ChainRow {
+    id: i64,
+    username: String,
+    action: String,
+    details: Option<String>,
+    timestamp: String,
+    entity_type: Option<String>,
+    entity_id: Option<i64>,
+    before_json: Option<String>,
+    after_json: Option<String>,
+    prev_hash: Option<String>,
+    hash: Option<String>,
+}
+
+/// Chains rows written before the hash chain existed. Only runs while no row
+/// has been sealed, so it can't be used to paper over a later break.
+pub async fn seal_unchained(pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let (sealed,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM audit_logs WHERE hash IS NOT NULL")
+        .fetch_one(&mut *tx)
+        .await?;
+    if sealed > 0 {
+        return Ok(0);
+    }
+
+    let rows = sqlx::query_as::<_, ChainRow>("SELECT * FROM audit_logs ORDER BY id")
+        .fetch_all(&mut *tx)
+        .await?;
+
+    let sealed = rows.len() as u64;
+    let mut prev_hash: Option<String> = None;
+    for mut row in rows {
+        row.prev_hash = prev_hash;
+        let hash = row_hash(&row);
+        sqlx::query("UPDATE audit_logs SET prev_hash = ?, hash = ? WHERE id = ?")
+            .bind(&row.prev_hash)
+            .bind(&hash)
+            .bind(row.id)
+            .execute(&mut *tx)
+            .await?;
+        prev_hash = Some(hash);
+    }
+
+    tx.commit().await?;
+    Ok(sealed)
+}
+
+/// Walks the chain from the first row and reports the first row that doesn't
+/// link to its predecessor or whose contents no longer match its hash.
+pub async fn verify_chain(conn: &mut SqliteConnection) -> Result<AuditChainReport, sqlx::Error> {
+    let rows = sqlx::query_as::<_, ChainRow>("SELECT * FROM audit_logs ORDER BY id")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut report = AuditChainReport { rows_checked: 0, intact: true, first_broken_id: None, problem: None, latest_hash: None };
+    let mut expected_prev: Option<String> = None;
+
+    for row in rows {
+        let problem = match &row.hash {
+            None => Some("was never sealed".to_string()),
+            Some(_) if row.prev_hash != expected_prev => {
+                Some("does not link to the row before it (a row was removed, inserted or altered)".to_string())
+            },
+            Some(hash) => (row_hash(&row) != *hash).then(|| "has been altered since it was written".to_string()),
+        };
+        if let Some(problem) = problem {
+            report.intact = false;
+            report.first_broken_id = Some(row.id);
+            report.problem = Some(format!("Entry #{} {}", row.id, problem));
+            return Ok(report);
+        }
+        report.rows_checked += 1;
+        expected_prev = row.hash;
+    }
+
+    report.latest_hash = expected_prev;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::test_pool;
+
+    async fn chained_pool(entries: usize) -> SqlitePool {
+        let pool = test_pool().await;
+        for i in 1..=entries {
+            record(&pool, "tech", Event::new("TEST", format!("entry {}", i))).await.unwrap();
+        }
+        pool
+    }
+
+    async fn verify(pool: &SqlitePool) -> AuditChainReport {
+        verify_chain(&mut pool.acquire().await.unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn intact_chain_verifies() {
+        let pool = chained_pool(3).await;
+        let report = verify(&pool).await;
+
+        assert!(report.intact);
+        assert_eq!(report.rows_checked, 3);
+        let (last,): (Option<String>,) = sqlx::query_as("SELECT hash FROM audit_logs ORDER BY id DESC LIMIT 1")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(report.latest_hash, last);
+    }
+
+    #[tokio::test]
+    async fn edited_entry_is_reported() {
+        let pool = chained_pool(3).await;
+        sqlx::raw_sql("DROP TRIGGER audit_logs_seal_only; UPDATE audit_logs SET details = 'nothing to see' WHERE id = 2")
+            .execute(&pool).await.unwrap();
+
+        let report = verify(&pool).await;
+        assert!(!report.intact);
+        assert_eq!(report.first_broken_id, Some(2));
+        assert!(report.problem.unwrap().contains("altered since it was written"));
+    }
+
+    #[tokio::test]
+    async fn removed_entry_breaks_the_link() {
+        let pool = chained_pool(3).await;
+        sqlx::raw_sql("DROP TRIGGER audit_logs_no_delete; DELETE FROM audit_logs WHERE id = 2")
+            .execute(&pool).await.unwrap();
+
+        let report = verify(&pool).await;
+        assert_eq!(report.first_broken_id, Some(3));
+        assert!(report.problem.unwrap().contains("does not link"));
+    }
+
+    #[tokio::test]
+    async fn reordered_entries_break_the_link() {
+        let pool = chained_pool(3).await;
+        sqlx::raw_sql(
+            "DROP TRIGGER audit_logs_seal_only;
+             UPDATE audit_logs SET id = 100 WHERE id = 2;
+             UPDATE audit_logs SET id = 2 WHERE id = 3;
+             UPDATE audit_logs SET id = 3 WHERE id = 100;"
+        )
+        .execute(&pool).await.unwrap();
+
+        let report = verify(&pool).await;
+        assert_eq!(report.first_broken_id, Some(2));
+        assert!(report.problem.unwrap().contains("does not link"));
+    }
+
+    #[tokio::test]
+    async fn seals_legacy_rows_only_once() {
+        let pool = chained_pool(0).await;
+        sqlx::raw_sql("INSERT INTO audit_logs (username, action, details) VALUES ('admin', 'LOGIN', 'a'), ('admin', 'LOGOUT', 'b')")
+            .execute(&pool).await.unwrap();
+
+        assert_eq!(seal_unchained(&pool).await.unwrap(), 2);
+        assert!(verify(&pool).await.intact);
+
+        // Once anything is sealed, a later unsealed row stays a break
+        sqlx::query("INSERT INTO audit_logs (username, action, details) VALUES ('admin', 'LOGIN', 'c')")
+            .execute(&pool).await.unwrap();
+        assert_eq!(seal_unchained(&pool).await.unwrap(), 0);
+
+        let report = verify(&pool).await;
+        assert_eq!(report.first_broken_id, Some(3));
+        assert!(report.problem.unwrap().contains("never sealed"));
+    }
+
+    #[test]
+    fn rows_without_entity_columns_hash_as_before() {
+        let row = ChainRow {
+            id: 7,
+            username: "pharm".to_string(),
+            action: "LOGIN".to_string(),
+            details: Some("User logged in successfully".to_string()),
+            timestamp: "2024-05-01 09:30:00".to_string(),
+            entity_type: None,
+            entity_id: None,
+            before_json: None,
+            after_json: None,
+            prev_hash: Some("abc123".to_string()),
+            hash: None,
+        };
+
+        // The pre-entity layout: six fields, each followed by a unit separator
+        let mut hasher = Sha256::new();
+        for field in ["abc123", "7", "2024-05-01 09:30:00", "pharm", "LOGIN", "User logged in successfully"] {
+            hasher.update(field.as_bytes());
+            hasher.update([0x1f]);
+        }
+        let legacy = format!("{:x}", hasher.finalize());
+        assert_eq!(row_hash(&row), legacy);
+
+        // Naming a record does change the hash
+        let with_entity = ChainRow { entity_type: Some("user".to_string()), entity_id: Some(3), ..row };
+        assert_ne!(row_hash(&with_entity), legacy);
+    }
+}
@@ -0,0 +1,276 @@
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use sqlx::SqlitePool;
+use std::sync::LazyLock;
+
+use crate::audit::{self, Entity, Event};
+use crate::error::AppError;
+use crate::model::UserCredentials;
+
+// =====================================================
+// PASSWORDS & LOCKOUT
+// =====================================================
+
+/// Consecutive bad passwords before an account is locked.
+pub const MAX_FAILED_ATTEMPTS: i64 = 5;
+/// How long a locked account stays locked.
+pub const LOCKOUT_MINUTES: i64 = 15;
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+/// The one answer for every refused login, whatever the reason, so the
+/// response doesn't reveal which usernames exist.
+pub const INVALID_CREDENTIALS: &str = "Invalid credentials";
+
+/// Checked against when there is no real hash to check, so a refusal costs
+/// the same Argon2 work as a wrong password.
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| hash_blocking("not-a-password").unwrap_or_default());
+
+/// Argon2id hash in PHC string format (salt and parameters are embedded).
+/// Runs on the blocking pool, since hashing is deliberately slow.
+pub async fn hash_password(plain: &str) -> Result<String, AppError> {
+    let plain = plain.to_string();
+    tokio::task::spawn_blocking(move || hash_blocking(&plain))
+        .await
+        .map_err(|_| AppError::Internal)?
+}
+
+fn hash_blocking(plain: &str) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(plain.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| {
+            eprintln!("❌ Password hashing failed: {}", e);
+            AppError::Internal
+        })
+}
+
+/// Rows written before hashing was introduced still hold the raw password.
+pub fn is_legacy_plaintext(stored: &str) -> bool {
+    PasswordHash::new(stored).is_err()
+}
+
+/// Checks `plain` against a stored hash, or against a legacy plaintext row.
+/// Callers should re-hash legacy rows after a successful check. With no
+/// stored hash it does the same work and always fails. Runs on the blocking pool.
+pub async fn verify_password(stored: Option<&str>, plain: &str) -> bool {
+    let stored = stored.map(str::to_string);
+    let plain = plain.to_string();
+    tokio::task::spawn_blocking(move || match stored {
+        Some(stored) => verify_blocking(&stored, &plain),
+        None => {
+            verify_blocking(&DUMMY_HASH, &plain);
+            false
+        },
+    })
+    .await
+    .unwrap_or(false)
+}
+
+fn verify_blocking(stored: &str, plain: &str) -> bool {
+    match PasswordHash::new(stored) {
+        Ok(parsed) => Argon2::default().verify_password(plain.as_bytes(), &parsed).is_ok(),
+        Err(_) => stored == plain,
+    }
+}
+
+/// Counts a wrong password against `user_id` and audits it as `action`, or as
+/// ACCOUNT_LOCKED when this was the last allowed attempt. `actor` is whoever
+/// typed the password (the account owner at login). Every password check
+/// that can be retried goes through here, so none of them escapes the lockout.
+pub async fn record_failed_password(pool: &SqlitePool, user_id: i64, username: &str, actor: &str, action: &str) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+
+    // One statement, so parallel attempts can't all read the same count.
+    // Locking resets the count, giving a fresh set of attempts once it expires.
+    let (attempts,): (i64,) = sqlx::query_as(
+        "UPDATE users SET
+            failed_attempts = CASE WHEN failed_attempts + 1 >= ? THEN 0 ELSE failed_attempts + 1 END,
+            locked_until = CASE WHEN failed_attempts + 1 >= ? THEN datetime('now', '+' || ? || ' minutes') ELSE locked_until END
+         WHERE id = ?
+         RETURNING failed_attempts"
+    )
+    .bind(MAX_FAILED_ATTEMPTS).bind(MAX_FAILED_ATTEMPTS).bind(LOCKOUT_MINUTES).bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let event = if attempts == 0 {
+        Event::new("ACCOUNT_LOCKED", format!(
+            "'{}' locked for {} minutes after {} failed attempts", username, LOCKOUT_MINUTES, MAX_FAILED_ATTEMPTS
+        ))
+    } else {
+        Event::new(action, format!("Wrong password for '{}' (attempt {} of {})", username, attempts, MAX_FAILED_ATTEMPTS))
+    };
+    audit::record(&mut *tx, actor, event.on(Entity::User, user_id)).await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub const USER_CREDENTIALS_SQL: &str =
+    "SELECT id, password_hash, role, must_change_password,
+            COALESCE(locked_until > datetime('now'), 0) AS is_locked, active
+     FROM users WHERE username = ?";
+
+/// Checks a login. Success clears the failed-attempt count, upgrades a legacy
+/// plaintext password to a hash and is audited as LOGIN, all in one
+/// transaction. Unknown, disabled and locked accounts get the same answer
+/// after the same Argon2 work as a wrong password; only the audit log says
+/// which it was.
+pub async fn authenticate(pool: &SqlitePool, username: &str, password: &str) -> Result<UserCredentials, AppError> {
+    let user = sqlx::query_as::<_, UserCredentials>(USER_CREDENTIALS_SQL)
+        .bind(username)
+        .fetch_optional(pool)
+        .await?;
+
+    let user = match user {
+        Some(user) if user.active && !user.is_locked => user,
+        other => {
+            verify_password(None, password).await;
+            let event = match &other {
+                None => Event::new("LOGIN_FAILED", "Unknown username"),
+                Some(u) if !u.active => Event::new("LOGIN_FAILED", "Attempt on disabled account").on(Entity::User, u.id),
+                Some(u) => Event::new("LOGIN_FAILED", "Attempt while account locked").on(Entity::User, u.id),
+            };
+            audit::record(pool, username, event).await?;
+            return Err(AppError::Unauthorized(INVALID_CREDENTIALS.to_string()));
+        },
+    };
+
+    if !verify_password(Some(&user.password_hash), password).await {
+        record_failed_password(pool, user.id, username, username, "LOGIN_FAILED").await?;
+        return Err(AppError::Unauthorized(INVALID_CREDENTIALS.to_string()));
+    }
+
+    // Transparent upgrade of rows that predate hashing
+    let upgraded_hash = if is_legacy_plaintext(&user.password_hash) {
+        Some(hash_password(password).await?)
+    } else {
+        None
+    };
+
+    let mut tx = pool.begin().await?;
+
+    if let Some(hash) = &upgraded_hash {
+        sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+            .bind(hash).bind(user.id)
+            .execute(&mut *tx)
+            .await?;
+        audit::record(&mut *tx, username, Event::new("PASSWORD_UPGRADED", "Plaintext password replaced with Argon2 hash").on(Entity::User, user.id)).await?;
+    }
+
+    sqlx::query("UPDATE users SET failed_attempts = 0, locked_until = NULL WHERE id = ?")
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(&mut *tx, username, Event::new("LOGIN", "User logged in successfully").on(Entity::User, user.id)).await?;
+
+    tx.commit().await?;
+    Ok(user)
+}
+
+/// Minimal policy for new passwords. Returns a user-facing reason on failure;
+/// callers attach it to whichever form field held the password.
+pub fn check_password_policy(username: &str, new_password: &str) -> Result<(), String> {
+    if new_password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(format!("Password must be at least {} characters", MIN_PASSWORD_LEN));
+    }
+    if new_password.eq_ignore_ascii_case(username) {
+        return Err("Password cannot be the same as the username".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::test_pool;
+
+    // A legacy plaintext row: only a successful login pays for Argon2.
+    async fn user_pool() -> SqlitePool {
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO users (username, password_hash, role) VALUES ('tech', 'correct-horse', 'tech')")
+            .execute(&pool).await.unwrap();
+        pool
+    }
+
+    async fn login(pool: &SqlitePool, password: &str) -> bool {
+        match authenticate(pool, "tech", password).await {
+            Ok(_) => true,
+            Err(AppError::Unauthorized(message)) => {
+                assert_eq!(message, INVALID_CREDENTIALS);
+                false
+            },
+            Err(other) => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    async fn lockout_state(pool: &SqlitePool) -> (i64, bool) {
+        sqlx::query_as("SELECT failed_attempts, COALESCE(locked_until > datetime('now'), 0) FROM users WHERE username = 'tech'")
+            .fetch_one(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn locks_after_too_many_wrong_passwords() {
+        let pool = user_pool().await;
+        for attempt in 1..MAX_FAILED_ATTEMPTS {
+            assert!(!login(&pool, "wrong").await);
+            assert_eq!(lockout_state(&pool).await, (attempt, false));
+        }
+        assert!(!login(&pool, "wrong").await);
+        assert_eq!(lockout_state(&pool).await, (0, true));
+
+        // The right password is refused the same way while locked
+        assert!(!login(&pool, "correct-horse").await);
+        let (last,): (String,) = sqlx::query_as("SELECT details FROM audit_logs ORDER BY id DESC LIMIT 1").fetch_one(&pool).await.unwrap();
+        assert_eq!(last, "Attempt while account locked");
+    }
+
+    #[tokio::test]
+    async fn lock_expires() {
+        let pool = user_pool().await;
+        sqlx::query("UPDATE users SET locked_until = datetime('now', '-1 minute')").execute(&pool).await.unwrap();
+        assert!(login(&pool, "correct-horse").await);
+
+        let (locked_until,): (Option<String>,) = sqlx::query_as("SELECT locked_until FROM users").fetch_one(&pool).await.unwrap();
+        assert_eq!(locked_until, None);
+    }
+
+    #[tokio::test]
+    async fn success_resets_the_count() {
+        let pool = user_pool().await;
+        assert!(!login(&pool, "wrong").await);
+        assert!(!login(&pool, "wrong").await);
+        assert_eq!(lockout_state(&pool).await, (2, false));
+
+        assert!(login(&pool, "correct-horse").await);
+        assert_eq!(lockout_state(&pool).await, (0, false));
+    }
+
+    #[tokio::test]
+    async fn legacy_plaintext_is_upgraded_on_login() {
+        let pool = user_pool().await;
+        assert!(is_legacy_plaintext("correct-horse"));
+        assert!(login(&pool, "correct-horse").await);
+
+        let (hash,): (String,) = sqlx::query_as("SELECT password_hash FROM users").fetch_one(&pool).await.unwrap();
+        assert!(!is_legacy_plaintext(&hash));
+        assert!(verify_password(Some(&hash), "correct-horse").await);
+        // The plaintext itself no longer gets in
+        assert!(!verify_password(Some(&hash), &hash).await);
+
+        let actions: Vec<(String,)> = sqlx::query_as("SELECT action FROM audit_logs ORDER BY id").fetch_all(&pool).await.unwrap();
+        assert_eq!(actions, vec![("PASSWORD_UPGRADED".to_string(),), ("LOGIN".to_string(),)]);
+    }
+
+    #[tokio::test]
+    async fn unknown_and_disabled_accounts_get_the_same_answer() {
+        let pool = user_pool().await;
+        assert!(!verify_password(None, "anything").await);
+        assert!(matches!(authenticate(&pool, "nobody", "correct-horse").await, Err(AppError::Unauthorized(m)) if m == INVALID_CREDENTIALS));
+
+        sqlx::query("UPDATE users SET active = 0").execute(&pool).await.unwrap();
+        assert!(!login(&pool, "correct-horse").await);
+        assert_eq!(lockout_state(&pool).await, (0, false));
+    }
+}
@@ -0,0 +1,384 @@
+use serde::Serialize;
+use sqlx::SqliteConnection;
+
+use crate::audit::{self, Entity, Event};
+use crate::error::AppError;
+
+// =====================================================
+// CLINICAL SCREENING
+// =====================================================
+// Checks run against a new fill before stock moves. Each problem becomes a
+// ClinicalAlert; blocking alerts stop the fill until someone supplies an
+// override reason, and the override is written to the audit trail.
+// Hard stops can only be overridden by a pharmacist (the rx.verify permission).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertLevel {
+    /// Shown to the user, never blocks.
+    Warn,
+    /// Blocks until an override reason is given.
+    Override,
+    /// Blocks until a pharmacist gives an override reason.
+    HardStop,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClinicalAlert {
+    pub kind: &'static str,
+    pub level: AlertLevel,
+    pub message: String,
+}
+
+pub const ALLERGY_SEVERITIES: [&str; 4] = ["mild", "moderate", "severe", "unknown"];
+
+pub const INTERACTION_SEVERITIES: [&str; 4] = ["contraindicated", "major", "moderate", "minor"];
+
+/// A prescription counts as active therapy until this many days after its
+/// next refill was due, to cover patients who refill a little late.
+pub const ACTIVE_THERAPY_GRACE_DAYS: i64 = 7;
+
+fn interaction_level(severity: &str) -> AlertLevel {
+    match severity {
+        "contraindicated" => AlertLevel::HardStop,
+        "major" => AlertLevel::Override,
+        _ => AlertLevel::Warn,
+    }
+}
+
+/// Splits the free-text allergy box ("Peanuts, Latex") into allergens,
+/// dropping "None"-style placeholders.
+pub fn parse_allergy_text(text: &str) -> Vec<String> {
+    text.split([',', ';'])
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .filter(|a| !matches!(a.to_ascii_lowercase().as_str(), "none" | "nka" | "nkda" | "no known allergies"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Keeps `patients.allergies` as a readable summary of the structured rows,
+/// for list views and older screens that still show the text column.
+pub async fn refresh_allergy_summary(conn: &mut SqliteConnection, patient_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE patients SET allergies = (
+            SELECT GROUP_CONCAT(allergen, ', ') FROM patient_allergies WHERE patient_id = ?
+         ) WHERE id = ?"
+    )
+    .bind(patient_id).bind(patient_id)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Inserts free-text allergens as structured rows (severity unknown) and refreshes the summary.
+pub async fn record_allergies_from_text(conn: &mut SqliteConnection, patient_id: i64, text: &str) -> Result<(), sqlx::Error> {
+    for allergen in parse_allergy_text(text) {
+        sqlx::query("INSERT INTO patient_allergies (patient_id, allergen) VALUES (?, ?)")
+            .bind(patient_id).bind(&allergen)
+            .execute(&mut *conn)
+            .await?;
+    }
+    refresh_allergy_summary(conn, patient_id).await
+}
+
+#[derive(sqlx::FromRow)]
+struct AllergyMatch {
+    allergen: String,
+    reaction: Option<String>,
+    severity: String,
+    medication: String,
+    matched: String,
+}
+
+/// Matches the patient's allergens against the medication's ingredient/class
+/// tags, case-insensitively. A drug with no tags falls back to its name,
+/// matched on whole words with LIKE wildcards escaped, so a short allergen
+/// ("am") or one with a `%` or `_` in it can't match unrelated drugs.
+pub async fn allergy_alerts(conn: &mut SqliteConnection, patient_id: i64, medication_id: i64) -> Result<Vec<ClinicalAlert>, sqlx::Error> {
+    let matches = sqlx::query_as::<_, AllergyMatch>(
+        "WITH allergens AS (
+            SELECT allergen, reaction, severity, LOWER(TRIM(allergen)) AS term,
+                   REPLACE(REPLACE(REPLACE(LOWER(TRIM(allergen)), '\\', '\\\\'), '%', '\\%'), '_', '\\_') AS pattern
+            FROM patient_allergies WHERE patient_id = ?
+         )
+         SELECT a.allergen, a.reaction, a.severity, m.name AS medication, COALESCE(i.name, m.name) AS matched
+         FROM allergens a
+         JOIN medications m ON m.id = ?
+         LEFT JOIN medication_ingredients i ON i.medication_id = m.id AND LOWER(i.name) = a.term
+         WHERE a.term != ''
+           AND (i.id IS NOT NULL
+                OR (NOT EXISTS (SELECT 1 FROM medication_ingredients WHERE medication_id = m.id)
+                    AND ' ' || REPLACE(REPLACE(LOWER(m.name), '/', ' '), '-', ' ') || ' ' LIKE '% ' || a.pattern || ' %' ESCAPE '\\'))"
+    )
+    .bind(patient_id)
+    .bind(medication_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(matches.into_iter().map(|m| ClinicalAlert {
+        kind: "allergy",
+        level: AlertLevel::Override,
+        message: format!(
+            "Patient is allergic to {} ({} severity{}); {} matches '{}'",
+            m.allergen,
+            m.severity,
+            m.reaction.map(|r| format!(", reaction: {}", r)).unwrap_or_default(),
+            m.medication,
+            m.matched
+        ),
+    }).collect())
+}
+
+#[derive(sqlx::FromRow)]
+struct InteractionMatch {
+    severity: String,
+    monograph: String,
+    new_term: String,
+    other_term: String,
+    other_drug: String,
+}
+
+/// Checks the new medication's ingredient/class tags against the tags of every
+/// other drug in the patient's active therapy.
+pub async fn interaction_alerts(conn: &mut SqliteConnection, patient_id: i64, medication_id: i64) -> Result<Vec<ClinicalAlert>, sqlx::Error> {
+    let matches = sqlx::query_as::<_, InteractionMatch>(
+        "WITH active AS (
+            SELECT DISTINCT medication_id FROM prescriptions
+            WHERE patient_id = ? AND medication_id != ? AND next_refill_date >= date('now', ?)
+              AND status NOT IN ('cancelled', 'transferred_out')
+         )
+         SELECT DISTINCT d.severity, d.monograph, n.name AS new_term, t.name AS other_term, m.name AS other_drug
+         FROM drug_interactions d
+         JOIN medication_ingredients n ON n.medication_id = ? AND n.name IN (d.term_a, d.term_b)
+         JOIN medication_ingredients t
+            ON t.medication_id IN (SELECT medication_id FROM active)
+           AND t.name = CASE WHEN n.name = d.term_a THEN d.term_b ELSE d.term_a END
+         JOIN medications m ON m.id = t.medication_id
+         ORDER BY CASE d.severity WHEN 'contraindicated' THEN 0 WHEN 'major' THEN 1 WHEN 'moderate' THEN 2 ELSE 3 END"
+    )
+    .bind(patient_id)
+    .bind(medication_id)
+    .bind(format!("-{} days", ACTIVE_THERAPY_GRACE_DAYS))
+    .bind(medication_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(matches.into_iter().map(|m| ClinicalAlert {
+        kind: "interaction",
+        level: interaction_level(&m.severity),
+        message: format!(
+            "Interaction with {} ({} + {}), {}: {}",
+            m.other_drug, m.new_term, m.other_term, m.severity, m.monograph
+        ),
+    }).collect())
+}
+
+/// Every check, allergies first.
+pub async fn screen(conn: &mut SqliteConnection, patient_id: i64, medication_id: i64) -> Result<Vec<ClinicalAlert>, sqlx::Error> {
+    let mut alerts = allergy_alerts(conn, patient_id, medication_id).await?;
+    alerts.extend(interaction_alerts(conn, patient_id, medication_id).await?);
+    Ok(alerts)
+}
+
+/// Fails with the full alert list when any blocking alert lacks an override
+/// reason, and refuses to let a non-pharmacist override a hard stop.
+pub fn enforce(alerts: &[ClinicalAlert], override_reason: Option<&str>, can_override_hard_stop: bool) -> Result<(), AppError> {
+    let blocking = alerts.iter().any(|a| a.level != AlertLevel::Warn);
+    let has_reason = override_reason.is_some_and(|r| !r.trim().is_empty());
+    if blocking && !has_reason {
+        return Err(AppError::ClinicalAlert(alerts.to_vec()));
+    }
+    if has_reason && !can_override_hard_stop && alerts.iter().any(|a| a.level == AlertLevel::HardStop) {
+        return Err(AppError::Forbidden("A contraindication can only be overridden by a pharmacist".to_string()));
+    }
+    Ok(())
+}
+
+/// Audits an accepted override, one entry per alert kind (ALLERGY_OVERRIDE,
+/// INTERACTION_OVERRIDE). Runs in the fill's transaction so an override is
+/// never committed without its record.
+pub async fn record_overrides(
+    conn: &mut SqliteConnection,
+    username: &str,
+    patient_id: i64,
+    medication_id: i64,
+    alerts: &[ClinicalAlert],
+    override_reason: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let Some(reason) = override_reason.map(str::trim).filter(|r| !r.is_empty()) else {
+        return Ok(());
+    };
+    for kind in ["allergy", "interaction"] {
+        let messages: Vec<&str> = alerts.iter()
+            .filter(|a| a.kind == kind && a.level != AlertLevel::Warn)
+            .map(|a| a.message.as_str())
+            .collect();
+        if messages.is_empty() {
+            continue;
+        }
+        let action = format!("{}_OVERRIDE", kind.to_uppercase());
+        let event = Event::new(&action, format!(
+            "Patient ID {} (Med ID {}): {} | Reason: {}",
+            patient_id, medication_id, messages.join("; "), reason
+        ));
+        audit::record(&mut *conn, username, event.on(Entity::Patient, patient_id)).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::test_pool;
+    use sqlx::SqlitePool;
+
+    /// Drugs 1-3 carry no tags, so they're matched on their names; drug 4 is tagged.
+    async fn clinical_pool() -> SqlitePool {
+        let pool = test_pool().await;
+        sqlx::raw_sql(
+            "INSERT INTO patients (name, birth_date, phone, address, city, state, postal_code, health_card_num) VALUES ('John Smith', '1985-04-12', '416-555-0199', '123 Maple Dr', 'Toronto', 'ON', 'M5V 2T6', '123-456-789-AB');
+             INSERT INTO medications (name, din, stock, price, expiration) VALUES
+                ('Penicillin V 300mg', '00000001', 0, 1.0, '2099-12-31'),
+                ('Penicillinase 10 units', '00000002', 0, 1.0, '2099-12-31'),
+                ('Lidocaine 2% Gel', '00000003', 0, 1.0, '2099-12-31'),
+                ('Amoxil 500mg', '00000004', 0, 1.0, '2099-12-31');
+             INSERT INTO medication_ingredients (medication_id, name, kind) VALUES (4, 'amoxicillin', 'ingredient'), (4, 'penicillin', 'class');"
+        )
+        .execute(&pool).await.unwrap();
+        pool
+    }
+
+    async fn allergic_to(pool: &SqlitePool, allergen: &str) {
+        sqlx::query("DELETE FROM patient_allergies").execute(pool).await.unwrap();
+        sqlx::query("INSERT INTO patient_allergies (patient_id, allergen, severity) VALUES (1, ?, 'severe')")
+            .bind(allergen).execute(pool).await.unwrap();
+    }
+
+    /// Ids of the drugs that raise an allergy alert.
+    async fn flagged(pool: &SqlitePool) -> Vec<i64> {
+        let mut conn = pool.acquire().await.unwrap();
+        let mut ids = Vec::new();
+        for medication_id in 1..=4 {
+            if !allergy_alerts(&mut conn, 1, medication_id).await.unwrap().is_empty() {
+                ids.push(medication_id);
+            }
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn allergy_matches_tags_before_names() {
+        let pool = clinical_pool().await;
+
+        allergic_to(&pool, "Penicillin").await;
+        assert_eq!(flagged(&pool).await, vec![1, 4]);
+
+        // Drug 4's name says nothing about its ingredient
+        allergic_to(&pool, " AMOXICILLIN ").await;
+        assert_eq!(flagged(&pool).await, vec![4]);
+        let alerts = allergy_alerts(&mut pool.acquire().await.unwrap(), 1, 4).await.unwrap();
+        assert_eq!(alerts[0].level, AlertLevel::Override);
+        assert!(alerts[0].message.ends_with("matches 'amoxicillin'"), "unexpected message: {}", alerts[0].message);
+
+        // Tagged drugs aren't matched on their names
+        allergic_to(&pool, "Amoxil").await;
+        assert!(flagged(&pool).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn allergy_names_match_whole_words_only() {
+        let pool = clinical_pool().await;
+
+        allergic_to(&pool, "penicillinase").await;
+        assert_eq!(flagged(&pool).await, vec![2]);
+        allergic_to(&pool, "Lidocaine").await;
+        assert_eq!(flagged(&pool).await, vec![3]);
+        allergic_to(&pool, "cillin").await;
+        assert!(flagged(&pool).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn allergy_wildcards_are_literal() {
+        let pool = clinical_pool().await;
+
+        for allergen in ["%", "_", "Penicillin_V", "Penicillin%"] {
+            allergic_to(&pool, allergen).await;
+            assert!(flagged(&pool).await.is_empty(), "'{}' matched", allergen);
+        }
+        allergic_to(&pool, "2%").await;
+        assert_eq!(flagged(&pool).await, vec![3]);
+    }
+
+    fn alert(level: AlertLevel) -> ClinicalAlert {
+        ClinicalAlert { kind: "interaction", level, message: String::new() }
+    }
+
+    #[test]
+    fn interaction_severity_sets_the_level() {
+        assert_eq!(interaction_level("contraindicated"), AlertLevel::HardStop);
+        assert_eq!(interaction_level("major"), AlertLevel::Override);
+        assert_eq!(interaction_level("moderate"), AlertLevel::Warn);
+        assert_eq!(interaction_level("minor"), AlertLevel::Warn);
+    }
+
+    #[test]
+    fn warnings_never_block() {
+        assert!(enforce(&[], None, false).is_ok());
+        assert!(enforce(&[alert(AlertLevel::Warn)], None, false).is_ok());
+    }
+
+    #[test]
+    fn blocking_alerts_need_a_reason() {
+        let alerts = [alert(AlertLevel::Warn), alert(AlertLevel::Override)];
+        for reason in [None, Some(""), Some("   ")] {
+            match enforce(&alerts, reason, true) {
+                Err(AppError::ClinicalAlert(list)) => assert_eq!(list.len(), 2),
+                other => panic!("expected the alert list, got {:?}", other),
+            }
+        }
+        assert!(enforce(&alerts, Some("Discussed with prescriber"), false).is_ok());
+    }
+
+    #[test]
+    fn only_a_pharmacist_overrides_a_hard_stop() {
+        let alerts = [alert(AlertLevel::HardStop)];
+        assert!(matches!(enforce(&alerts, None, true), Err(AppError::ClinicalAlert(_))));
+        assert!(matches!(enforce(&alerts, Some("Benefit outweighs risk"), false), Err(AppError::Forbidden(_))));
+        assert!(enforce(&alerts, Some("Benefit outweighs risk"), true).is_ok());
+    }
+
+    #[tokio::test]
+    async fn interactions_only_count_active_therapy() {
+        let pool = clinical_pool().await;
+        sqlx::raw_sql(
+            "INSERT INTO medications (name, din, stock, price, expiration) VALUES ('Warfarin 5mg', '00000005', 0, 1.0, '2099-12-31');
+             INSERT INTO medication_ingredients (medication_id, name, kind) VALUES (5, 'warfarin', 'ingredient');
+             INSERT INTO drug_interactions (term_a, term_b, severity, monograph) VALUES ('penicillin', 'warfarin', 'major', 'May raise INR');
+             INSERT INTO prescriptions (patient_id, medication_id, prescriber, sig, quantity, refills, days_supply, date_filled, next_refill_date)
+                VALUES (1, 5, 'Dr. Nick', 'Take 1 tablet daily', 30, 5, 30, '2024-01-01', '2024-01-31');"
+        )
+        .execute(&pool).await.unwrap();
+
+        async fn levels(pool: &SqlitePool, next_refill: &str, status: &str) -> Vec<AlertLevel> {
+            sqlx::query("UPDATE prescriptions SET next_refill_date = date('now', ?), status = ? WHERE id = 1")
+                .bind(next_refill).bind(status)
+                .execute(pool).await.unwrap();
+            let alerts = interaction_alerts(&mut pool.acquire().await.unwrap(), 1, 4).await.unwrap();
+            alerts.into_iter().map(|a| a.level).collect()
+        }
+
+        assert_eq!(levels(&pool, "+10 days", "active").await, [AlertLevel::Override]);
+        // Within the grace period after the refill was due
+        assert_eq!(levels(&pool, "-7 days", "active").await, [AlertLevel::Override]);
+        assert_eq!(levels(&pool, "-5 days", "on_hold").await, [AlertLevel::Override]);
+        assert_eq!(levels(&pool, "-5 days", "completed").await, [AlertLevel::Override]);
+        assert!(levels(&pool, "-8 days", "active").await.is_empty());
+        assert!(levels(&pool, "+10 days", "cancelled").await.is_empty());
+        assert!(levels(&pool, "+10 days", "transferred_out").await.is_empty());
+
+        // The drug being screened isn't checked against itself
+        let own = interaction_alerts(&mut pool.acquire().await.unwrap(), 1, 5).await.unwrap();
+        assert!(own.is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::audit::{self, Event};
+use crate::auth;
+use crate::error::AppError;
+use crate::model::WitnessDto;
+use crate::permissions::{Capability, Role};
+use crate::session::Session;
+
+// =====================================================
+// CONTROLLED SUBSTANCES
+// =====================================================
+// Scheduled drugs get a perpetual register: one row per stock movement with
+// the running balance, written by trigger from `stock_movements`. Taking
+// scheduled stock off the shelf by hand needs a second person to sign with
+// their own password, and one of the two must be a pharmacist.
+
+/// CDSA schedules (code, label).
+pub const SCHEDULES: [(&str, &str); 3] = [
+    ("narcotic", "Narcotic"),
+    ("controlled", "Controlled drug"),
+    ("targeted", "Targeted substance"),
+];
+
+pub fn is_schedule(code: &str) -> bool {
+    SCHEDULES.iter().any(|(c, _)| *c == code)
+}
+
+pub async fn schedule_of(conn: &mut SqliteConnection, medication_id: i64) -> Result<Option<String>, AppError> {
+    let (schedule,): (Option<String>,) = sqlx::query_as("SELECT schedule FROM medications WHERE id = ?")
+        .bind(medication_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AppError::not_found("Drug"))?;
+    Ok(schedule)
+}
+
+/// Checks a witness's credentials for a sign-off by `actor` and returns the
+/// witness's username. The witness must be another active, unlocked account,
+/// and at least one of the two must be able to approve inventory (a
+/// pharmacist). A wrong password counts toward the witness's lockout exactly
+/// as a failed login does. Call on the pool, before the caller's transaction,
+/// so failed attempts are kept when the caller rolls back.
+pub async fn verify_witness(pool: &SqlitePool, witness: &WitnessDto, actor: &Session) -> Result<String, AppError> {
+    let username = witness.username.trim();
+    if username.is_empty() {
+        return Err(AppError::validation("witness", "A witness is required"));
+    }
+    if username.eq_ignore_ascii_case(&actor.username) {
+        return Err(AppError::validation("witness", "The witness must be someone other than you"));
+    }
+
+    let row: Option<(i64, String, String, bool, bool)> = sqlx::query_as(
+        "SELECT id, password_hash, role, active, COALESCE(locked_until > datetime('now'), 0) FROM users WHERE username = ?"
+    )
+    .bind(username)
+    .fetch_optional(pool)
+    .await?;
+
+    let invalid = || AppError::Unauthorized("Witness credentials are invalid".to_string());
+    let role = match row {
+        Some((id, hash, role, true, false)) => {
+            if !auth::verify_password(Some(&hash), &witness.password).await {
+                auth::record_failed_password(pool, id, username, &actor.username, "WITNESS_FAILED").await?;
+                return Err(invalid());
+            }
+            role
+        },
+        other => {
+            auth::verify_password(None, &witness.password).await;
+            let reason = match &other {
+                None => "unknown user",
+                Some((_, _, _, false, _)) => "account disabled",
+                Some(_) => "account locked",
+            };
+            let event = Event::new("WITNESS_FAILED", format!("Witness '{}' refused: {}", username, reason));
+            let event = match &other {
+                Some((id, ..)) => event.on(audit::Entity::User, *id),
+                None => event,
+            };
+            audit::record(pool, &actor.username, event).await?;
+            return Err(invalid());
+        },
+    };
+
+    let witness_role = Role::parse(&role).ok_or(AppError::Internal)?;
+    if !actor.can(Capability::InventoryApprove) && !witness_role.can(Capability::InventoryApprove) {
+        return Err(AppError::Forbidden("A pharmacist must either perform or witness this".to_string()));
+    }
+
+    Ok(username.to_string())
+}
+
+/// Starts (or restarts) a drug's register at its current stock when it
+/// becomes scheduled. Any gap since the last register balance is recorded as
+/// a reopening adjustment rather than silently absorbed.
+pub async fn open_register(conn: &mut SqliteConnection, medication_id: i64, username: &str) -> Result<(), AppError> {
+    let (stock,): (i64,) = sqlx::query_as("SELECT stock FROM medications WHERE id = ?")
+        .bind(medication_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    let last: Option<(i64,)> = sqlx::query_as("SELECT balance FROM controlled_register WHERE medication_id = ? ORDER BY id DESC LIMIT 1")
+        .bind(medication_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    let (reason, previous) = match last {
+        Some((balance,)) => ("register_reopened", balance),
+        None => ("opening_balance", 0),
+    };
+
+    sqlx::query(
+        "INSERT INTO controlled_register (medication_id, kind, quantity, balance, reason_code, recorded_by)
+         VALUES (?, 'adjust', ?, ?, ?, ?)"
+    )
+    .bind(medication_id).bind(stock - previous).bind(stock).bind(reason).bind(username)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Narcotics can't be refilled; every fill needs a new prescription.
+pub async fn check_prescription(conn: &mut SqliteConnection, medication_id: i64, refills: i32) -> Result<(), AppError> {
+    if refills > 0 && schedule_of(conn, medication_id).await?.as_deref() == Some("narcotic") {
+        return Err(AppError::validation("refills", "Narcotic prescriptions cannot carry refills"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::MAX_FAILED_ATTEMPTS;
+    use crate::dispense::{self, NewFill};
+    use crate::inventory::{self, Movement};
+    use crate::migrations::test_pool;
+    use crate::session::SessionStore;
+
+    // Legacy plaintext passwords keep these tests clear of Argon2's cost.
+    async fn register_pool() -> SqlitePool {
+        let pool = test_pool().await;
+        sqlx::raw_sql(
+            "INSERT INTO users (username, password_hash, role) VALUES ('tech', 'tech-pass', 'tech'), ('pharm', 'pharm-pass', 'pharmacist'), ('tech2', 'tech2-pass', 'tech');
+             INSERT INTO patients (name, birth_date, phone, address, city, state, postal_code, health_card_num) VALUES ('John Smith', '1985-04-12', '416-555-0199', '123 Maple Dr', 'Toronto', 'ON', 'M5V 2T6', '123-456-789-AB');
+             INSERT INTO medications (name, din, stock, price, expiration, schedule) VALUES ('Oxycodone 5mg', '02240000', 0, 0.5, '2099-12-31', 'narcotic');
+             INSERT INTO prescriptions (patient_id, medication_id, prescriber, sig, quantity, refills, days_supply, date_filled, next_refill_date)
+                VALUES (1, 1, 'Dr. Nick', 'Take 1 tablet q6h prn', 30, 0, 7, '2024-01-01', '2024-01-08');"
+        )
+        .execute(&pool).await.unwrap();
+        pool
+    }
+
+    async fn receive(pool: &SqlitePool, medication_id: i64, quantity: i32) {
+        let movement = Movement { kind: "receive", reason_code: "direct_receipt", reference_id: None, username: "pharm", note: None, witness: None };
+        let mut conn = pool.acquire().await.unwrap();
+        inventory::receive_into_lot(&mut conn, medication_id, "L1", "2099-12-31", quantity, &movement).await.unwrap();
+    }
+
+    async fn register(pool: &SqlitePool) -> Vec<(String, i32, i64, String, Option<String>)> {
+        sqlx::query_as("SELECT kind, quantity, balance, reason_code, witness FROM controlled_register ORDER BY id")
+            .fetch_all(pool).await.unwrap()
+    }
+
+    fn witness(username: &str, password: &str) -> WitnessDto {
+        WitnessDto { username: username.to_string(), password: password.to_string() }
+    }
+
+    #[tokio::test]
+    async fn register_keeps_a_running_balance() {
+        let pool = register_pool().await;
+        receive(&pool, 1, 100).await;
+
+        let mut conn = pool.acquire().await.unwrap();
+        let fill = NewFill {
+            prescription_id: 1, medication_id: 1, fill_number: 0, quantity: 30, days_supply: 7,
+            date_filled: "2024-01-01", filled_by: "tech", early_fill_code: None,
+        };
+        let fill_id = dispense::record_fill(&mut conn, &fill).await.unwrap();
+        dispense::void_fill(&mut conn, fill_id, "Wrong patient", "pharm", Some("tech")).await.unwrap();
+        drop(conn);
+
+        assert_eq!(register(&pool).await, vec![
+            ("receive".to_string(), 100, 100, "direct_receipt".to_string(), None),
+            ("fill".to_string(), -30, 70, "dispensed".to_string(), None),
+            ("return".to_string(), 30, 100, "fill_voided".to_string(), Some("tech".to_string())),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn reopening_records_the_gap() {
+        let pool = register_pool().await;
+        sqlx::query("UPDATE medications SET schedule = NULL").execute(&pool).await.unwrap();
+        receive(&pool, 1, 50).await;
+
+        let mut conn = pool.acquire().await.unwrap();
+        sqlx::query("UPDATE medications SET schedule = 'controlled'").execute(&mut *conn).await.unwrap();
+        open_register(&mut conn, 1, "pharm").await.unwrap();
+        sqlx::query("UPDATE medications SET schedule = NULL").execute(&mut *conn).await.unwrap();
+        drop(conn);
+
+        // Received while unscheduled, so the register never saw it
+        receive(&pool, 1, 20).await;
+        let mut conn = pool.acquire().await.unwrap();
+        sqlx::query("UPDATE medications SET schedule = 'controlled'").execute(&mut *conn).await.unwrap();
+        open_register(&mut conn, 1, "pharm").await.unwrap();
+        drop(conn);
+
+        assert_eq!(register(&pool).await, vec![
+            ("adjust".to_string(), 50, 50, "opening_balance".to_string(), None),
+            ("adjust".to_string(), 20, 70, "register_reopened".to_string(), None),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn actor_cannot_witness_themselves() {
+        let pool = register_pool().await;
+        let tech = SessionStore::default().start(1, "tech", "tech", false);
+
+        let err = verify_witness(&pool, &witness(" TECH ", "tech-pass"), &tech).await.unwrap_err();
+        assert!(matches!(err, AppError::Validation(_)), "unexpected error: {:?}", err);
+    }
+
+    #[tokio::test]
+    async fn wrong_witness_password_counts_toward_lockout() {
+        let pool = register_pool().await;
+        let tech = SessionStore::default().start(1, "tech", "tech", false);
+
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            let err = verify_witness(&pool, &witness("pharm", "guess"), &tech).await.unwrap_err();
+            assert!(matches!(err, AppError::Unauthorized(_)), "unexpected error: {:?}", err);
+        }
+
+        // Locked now, so even the right password is refused
+        let err = verify_witness(&pool, &witness("pharm", "pharm-pass"), &tech).await.unwrap_err();
+        assert!(matches!(err, AppError::Unauthorized(_)), "unexpected error: {:?}", err);
+
+        let (locked,): (bool,) = sqlx::query_as("SELECT locked_until > datetime('now') FROM users WHERE username = 'pharm'")
+            .fetch_one(&pool).await.unwrap();
+        assert!(locked);
+        let actions: Vec<(String,)> = sqlx::query_as("SELECT action FROM audit_logs WHERE username = 'tech' ORDER BY id")
+            .fetch_all(&pool).await.unwrap();
+        let actions: Vec<&str> = actions.iter().map(|(a,)| a.as_str()).collect();
+        assert_eq!(actions, ["WITNESS_FAILED", "WITNESS_FAILED", "WITNESS_FAILED", "WITNESS_FAILED", "ACCOUNT_LOCKED", "WITNESS_FAILED"]);
+    }
+
+    #[tokio::test]
+    async fn a_pharmacist_must_be_one_of_the_pair() {
+        let pool = register_pool().await;
+        let tech = SessionStore::default().start(1, "tech", "tech", false);
+
+        let err = verify_witness(&pool, &witness("tech2", "tech2-pass"), &tech).await.unwrap_err();
+        assert!(matches!(err, AppError::Forbidden(_)), "unexpected error: {:?}", err);
+        assert_eq!(verify_witness(&pool, &witness("pharm", "pharm-pass"), &tech).await.unwrap(), "pharm");
+
+        let pharm = SessionStore::default().start(2, "pharm", "pharmacist", false);
+        assert_eq!(verify_witness(&pool, &witness("tech2", "tech2-pass"), &pharm).await.unwrap(), "tech2");
+    }
+}
@@ -0,0 +1,217 @@
+use sqlx::SqliteConnection;
+
+use crate::error::AppError;
+use crate::inventory::{self, Movement};
+
+// =====================================================
+// PHYSICAL COUNTS
+// =====================================================
+// A count session snapshots the on-hand quantity of every lot in scope (the
+// whole store, or one shelf location taken from `medications.description`).
+// Staff enter what they find; nothing touches stock until a pharmacist posts
+// the session, which writes every variance to the ledger in one transaction.
+// Variance is counted minus the snapshot, so fills made while the count is
+// in progress are not undone by posting it.
+
+/// Opens a session with one line per lot of every drug in scope, empty lots
+/// included so stock found in one can be counted as a surplus. `location`
+/// narrows it to drugs shelved there (matched case-insensitively). Must run
+/// inside the caller's transaction.
+pub async fn start_session(conn: &mut SqliteConnection, location: Option<&str>, started_by: &str) -> Result<(i64, u64), AppError> {
+    let session_id = sqlx::query("INSERT INTO count_sessions (location, started_by) VALUES (?, ?)")
+        .bind(location).bind(started_by)
+        .execute(&mut *conn)
+        .await?
+        .last_insert_rowid();
+
+    let lines = sqlx::query(
+        "INSERT INTO count_lines (session_id, lot_id, expected)
+         SELECT ?, l.id, l.quantity
+         FROM medication_lots l JOIN medications m ON m.id = l.medication_id
+         WHERE ? IS NULL OR LOWER(TRIM(m.description)) = LOWER(TRIM(?))"
+    )
+    .bind(session_id).bind(location).bind(location)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+
+    if lines == 0 {
+        return Err(AppError::NotFound(match location {
+            Some(loc) => format!("No lots on file at {}", loc),
+            None => "No lots on file to count".to_string(),
+        }));
+    }
+
+    Ok((session_id, lines))
+}
+
+/// Totals from posting a session, for the audit trail.
+pub struct PostedCount {
+    pub adjusted_lines: usize,
+    pub net_units: i64,
+    pub net_value: f64,
+}
+
+#[derive(sqlx::FromRow)]
+struct VarianceLine {
+    lot_id: i64,
+    lot_number: String,
+    on_hand: i32,
+    variance: i32,
+    price: f64,
+    scheduled: bool,
+}
+
+/// Writes every non-zero variance to the stock ledger and closes the session.
+/// Every line expected to hold stock must have been counted; an empty lot left
+/// blank is taken as still empty. Variances on a scheduled drug go into its
+/// register and need a `witness` (already verified by the caller). Must run
+/// inside the caller's transaction.
+pub async fn post_session(conn: &mut SqliteConnection, session_id: i64, approved_by: &str, witness: Option<&str>) -> Result<PostedCount, AppError> {
+    let (status,): (String,) = sqlx::query_as("SELECT status FROM count_sessions WHERE id = ?")
+        .bind(session_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AppError::not_found("Count session"))?;
+    if status != "open" {
+        return Err(AppError::Conflict(format!("Count session is already {}", status)));
+    }
+
+    let (uncounted,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM count_lines WHERE session_id = ? AND counted IS NULL AND expected != 0")
+        .bind(session_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    if uncounted > 0 {
+        return Err(AppError::validation("counted", format!("{} line(s) have not been counted yet", uncounted)));
+    }
+
+    let lines = sqlx::query_as::<_, VarianceLine>(
+        "SELECT c.lot_id, l.lot_number, l.quantity AS on_hand, c.counted - c.expected AS variance, m.price,
+                m.schedule IS NOT NULL AS scheduled
+         FROM count_lines c
+         JOIN medication_lots l ON l.id = c.lot_id
+         JOIN medications m ON m.id = l.medication_id
+         WHERE c.session_id = ? AND c.counted != c.expected"
+    )
+    .bind(session_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    if witness.is_none() && lines.iter().any(|l| l.scheduled) {
+        return Err(AppError::validation("witness", "Posting variances on a scheduled drug needs a witness"));
+    }
+
+    let movement = Movement { kind: "adjust", reason_code: "cycle_count", reference_id: Some(session_id), username: approved_by, note: None, witness };
+    let mut posted = PostedCount { adjusted_lines: lines.len(), net_units: 0, net_value: 0.0 };
+    for line in &lines {
+        if line.on_hand + line.variance < 0 {
+            return Err(AppError::Conflict(format!(
+                "Lot {} now has {} on hand, less than the {} shortfall counted; recount it", line.lot_number, line.on_hand, -line.variance
+            )));
+        }
+        inventory::record_movement(conn, line.lot_id, line.variance, &movement).await?;
+        posted.net_units += line.variance as i64;
+        posted.net_value += line.variance as f64 * line.price;
+    }
+
+    sqlx::query("UPDATE count_sessions SET status = 'posted', posted_at = CURRENT_TIMESTAMP, approved_by = ? WHERE id = ?")
+        .bind(approved_by).bind(session_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(posted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::test_pool;
+    use sqlx::SqlitePool;
+
+    async fn counts_pool() -> SqlitePool {
+        let pool = test_pool().await;
+        sqlx::raw_sql(
+            "INSERT INTO medications (name, din, stock, price, expiration, description)
+                VALUES ('Amoxicillin 500mg', '02238888', 0, 0.5, '2099-12-31', 'Shelf 1');
+             INSERT INTO medications (name, din, stock, price, expiration, description, schedule)
+                VALUES ('Oxycodone 5mg', '02240132', 0, 1.0, '2099-12-31', 'Safe', 'narcotic');"
+        )
+        .execute(&pool).await.unwrap();
+
+        let movement = Movement { kind: "receive", reason_code: "direct_receipt", reference_id: None, username: "pharm", note: None, witness: None };
+        let mut conn = pool.acquire().await.unwrap();
+        for (medication_id, lot, quantity) in [(1, "A", 30), (1, "B", 20), (2, "C", 10)] {
+            inventory::receive_into_lot(&mut conn, medication_id, lot, "2099-01-01", quantity, &movement).await.unwrap();
+        }
+        pool
+    }
+
+    async fn count(conn: &mut SqliteConnection, session_id: i64, lot_number: &str, counted: i32) {
+        sqlx::query("UPDATE count_lines SET counted = ? WHERE session_id = ? AND lot_id = (SELECT id FROM medication_lots WHERE lot_number = ?)")
+            .bind(counted).bind(session_id).bind(lot_number)
+            .execute(&mut *conn).await.unwrap();
+    }
+
+    /// Stock leaving a lot while the count is under way, e.g. a fill.
+    async fn take(conn: &mut SqliteConnection, lot_id: i64, quantity: i32) {
+        let movement = Movement { kind: "fill", reason_code: "fill", reference_id: None, username: "tech", note: None, witness: None };
+        inventory::record_movement(conn, lot_id, -quantity, &movement).await.unwrap();
+    }
+
+    async fn lots(conn: &mut SqliteConnection) -> Vec<(String, i32)> {
+        sqlx::query_as("SELECT lot_number, quantity FROM medication_lots ORDER BY id").fetch_all(&mut *conn).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn posts_counted_minus_expected() {
+        let pool = counts_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        let (session_id, lines) = start_session(&mut conn, Some(" shelf 1"), "tech").await.unwrap();
+        assert_eq!(lines, 2);
+        count(&mut conn, session_id, "A", 28).await;
+        assert!(matches!(post_session(&mut conn, session_id, "pharm", None).await, Err(AppError::Validation(_))), "lot B is uncounted");
+        count(&mut conn, session_id, "B", 23).await;
+
+        // A fill during the count is kept: only the variance is applied
+        take(&mut conn, 1, 5).await;
+        let posted = post_session(&mut conn, session_id, "pharm", None).await.unwrap();
+        assert_eq!((posted.adjusted_lines, posted.net_units, posted.net_value), (2, 1, 0.5));
+        assert_eq!(lots(&mut conn).await, [("A".to_string(), 23), ("B".to_string(), 23), ("C".to_string(), 10)]);
+
+        assert!(matches!(post_session(&mut conn, session_id, "pharm", None).await, Err(AppError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn shortfall_beyond_on_hand_needs_a_recount() {
+        let pool = counts_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        let (session_id, _) = start_session(&mut conn, Some("Shelf 1"), "tech").await.unwrap();
+        count(&mut conn, session_id, "A", 0).await;
+        count(&mut conn, session_id, "B", 20).await;
+        take(&mut conn, 1, 10).await;
+
+        assert!(matches!(post_session(&mut conn, session_id, "pharm", None).await, Err(AppError::Conflict(_))));
+        assert_eq!(lots(&mut conn).await[0], ("A".to_string(), 20));
+    }
+
+    #[tokio::test]
+    async fn scheduled_variance_needs_a_witness() {
+        let pool = counts_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        let (session_id, _) = start_session(&mut conn, None, "tech").await.unwrap();
+        for (lot, counted) in [("A", 30), ("B", 20), ("C", 9)] {
+            count(&mut conn, session_id, lot, counted).await;
+        }
+        assert!(matches!(post_session(&mut conn, session_id, "pharm", None).await, Err(AppError::Validation(_))));
+
+        post_session(&mut conn, session_id, "pharm", Some("pharm2")).await.unwrap();
+        let register: (String, i32, i32, Option<String>) = sqlx::query_as(
+            "SELECT reason_code, quantity, balance, witness FROM controlled_register ORDER BY id DESC LIMIT 1"
+        )
+        .fetch_one(&mut *conn).await.unwrap();
+        assert_eq!(register, ("cycle_count".to_string(), -1, 9, Some("pharm2".to_string())));
+    }
+}
@@ -0,0 +1,658 @@
+use chrono::{Duration, NaiveDate};
+use sqlx::SqliteConnection;
+
+use crate::controlled;
+use crate::error::AppError;
+use crate::inventory::{self, Movement};
+use crate::rx_status::RxStatus;
+use crate::settings;
+use crate::validation::{parse_date, today};
+
+// =====================================================
+// DISPENSING
+// =====================================================
+// A prescription is the prescriber's order (drug, sig, authorized refills,
+// expiry); each time product leaves the shelf against it is a fill.
+// Fill #0 is the original, #1 the first refill, and so on.
+
+/// Prescriptions can be filled for this long after the original fill.
+pub const RX_VALID_DAYS: i64 = 365;
+
+/// Accepted reasons for filling before the too-soon date (code, label).
+pub const EARLY_FILL_REASONS: [(&str, &str); 5] = [
+    ("lost_stolen", "Medication lost or stolen"),
+    ("dose_change", "Dose increased by prescriber"),
+    ("vacation", "Vacation / travel supply"),
+    ("prescriber_authorized", "Early fill authorized by prescriber"),
+    ("insurer_approved", "Insurer approved early fill"),
+];
+
+pub struct NewFill<'a> {
+    pub prescription_id: i64,
+    pub medication_id: i64,
+    pub fill_number: i64,
+    pub quantity: i32,
+    pub days_supply: i32,
+    pub date_filled: &'a str,
+    pub filled_by: &'a str,
+    /// Set when the fill went out before the too-soon date.
+    pub early_fill_code: Option<&'a str>,
+}
+
+/// Writes the fill, deducts stock from the in-date lots first-expiring-first-out
+/// (recording which lots it came from) and moves the prescription's next
+/// refill date forward. Expired lots are never dispensed. Must run inside the
+/// caller's transaction.
+pub async fn record_fill(conn: &mut SqliteConnection, fill: &NewFill<'_>) -> Result<i64, AppError> {
+    sqlx::query("SELECT id FROM medications WHERE id = ?")
+        .bind(fill.medication_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AppError::not_found("Medication"))?;
+
+    let lots: Vec<(i64, i32, String)> = sqlx::query_as(
+        "SELECT id, quantity, expiration FROM medication_lots
+         WHERE medication_id = ? AND quantity > 0
+         ORDER BY expiration, received_at, id"
+    )
+    .bind(fill.medication_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    // Product is checked against the day it leaves the shelf, even when the fill is backdated
+    let today = today().format("%Y-%m-%d").to_string();
+    let (lots, expired): (Vec<_>, Vec<_>) = lots.into_iter().partition(|(_, _, exp)| *exp >= today);
+    let available: i32 = lots.iter().map(|(_, q, _)| q).sum();
+    if available < fill.quantity {
+        let expired_qty: i32 = expired.iter().map(|(_, q, _)| q).sum();
+        if available + expired_qty >= fill.quantity {
+            return Err(AppError::ExpiredStock(format!(
+                "Only {} in date; {} on hand is expired and must be quarantined, not dispensed", available, expired_qty
+            )));
+        }
+        return Err(AppError::InsufficientStock { available: available as i64, requested: fill.quantity as i64 });
+    }
+
+    let fill_id = sqlx::query(
+        "INSERT INTO fills (prescription_id, fill_number, quantity, days_supply, date_filled, next_refill_date, filled_by, early_fill_code)
+         VALUES (?, ?, ?, ?, ?, date(?, '+' || ? || ' days'), ?, ?)"
+    )
+    .bind(fill.prescription_id).bind(fill.fill_number).bind(fill.quantity).bind(fill.days_supply)
+    .bind(fill.date_filled).bind(fill.date_filled).bind(fill.days_supply).bind(fill.filled_by)
+    .bind(fill.early_fill_code)
+    .execute(&mut *conn)
+    .await?
+    .last_insert_rowid();
+
+    let movement = Movement { kind: "fill", reason_code: "dispensed", reference_id: Some(fill_id), username: fill.filled_by, note: None, witness: None };
+    let mut remaining = fill.quantity;
+    for (lot_id, on_hand, _) in lots {
+        if remaining == 0 {
+            break;
+        }
+        let take = remaining.min(on_hand);
+        inventory::record_movement(conn, lot_id, -take, &movement).await?;
+        sqlx::query("INSERT INTO fill_lots (fill_id, lot_id, quantity) VALUES (?, ?, ?)")
+            .bind(fill_id).bind(lot_id).bind(take)
+            .execute(&mut *conn)
+            .await?;
+        remaining -= take;
+    }
+
+    sqlx::query("UPDATE prescriptions SET next_refill_date = date(?, '+' || ? || ' days') WHERE id = ?")
+        .bind(fill.date_filled).bind(fill.days_supply).bind(fill.prescription_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(fill_id)
+}
+
+#[derive(sqlx::FromRow)]
+pub struct RefillTarget {
+    pub prescription_id: i64,
+    pub patient_id: i64,
+    pub medication_id: i64,
+    pub quantity: i32,
+    pub days_supply: i32,
+    pub refills_remaining: i32,
+    expires_on: Option<String>,
+    expired: bool,
+    status: String,
+    pub next_fill_number: i64,
+}
+
+/// Loads a prescription for its next refill, refusing one that is not
+/// active, has expired or has no refills left.
+pub async fn refill_target(conn: &mut SqliteConnection, prescription_id: i64) -> Result<RefillTarget, AppError> {
+    let rx = sqlx::query_as::<_, RefillTarget>(
+        "SELECT id AS prescription_id, patient_id, medication_id, quantity, days_supply, refills_remaining, expires_on,
+            COALESCE(expires_on < date('now'), 0) AS expired, status,
+            (SELECT COALESCE(MAX(fill_number), -1) + 1 FROM fills WHERE prescription_id = prescriptions.id) AS next_fill_number
+         FROM prescriptions WHERE id = ?"
+    )
+    .bind(prescription_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(AppError::not_found("Prescription"))?;
+
+    let status = RxStatus::parse(&rx.status).unwrap_or(RxStatus::Active);
+    if status != RxStatus::Active {
+        return Err(AppError::NotRefillable(format!("Prescription is {} and cannot be refilled.", status.label())));
+    }
+    if rx.expired {
+        return Err(AppError::NotRefillable(format!(
+            "Prescription expired on {}. A new prescription is needed.", rx.expires_on.as_deref().unwrap_or_default()
+        )));
+    }
+    if rx.refills_remaining <= 0 {
+        return Err(AppError::NotRefillable("No refills remaining. A new prescription is needed.".to_string()));
+    }
+    Ok(rx)
+}
+
+/// Dispenses a refill today and consumes one of the prescription's remaining
+/// refills; the last one completes it. Returns the refills left. Must run
+/// inside the caller's transaction.
+pub async fn record_refill(conn: &mut SqliteConnection, rx: &RefillTarget, filled_by: &str, early_fill_code: Option<&str>) -> Result<i32, AppError> {
+    let today = today().format("%Y-%m-%d").to_string();
+    record_fill(conn, &NewFill {
+        prescription_id: rx.prescription_id,
+        medication_id: rx.medication_id,
+        fill_number: rx.next_fill_number,
+        quantity: rx.quantity,
+        days_supply: rx.days_supply,
+        date_filled: &today,
+        filled_by,
+        early_fill_code,
+    }).await?;
+
+    sqlx::query(
+        "UPDATE prescriptions SET refills_remaining = refills_remaining - 1,
+            status = CASE WHEN refills_remaining - 1 <= 0 THEN ? ELSE status END
+         WHERE id = ?"
+    )
+    .bind(RxStatus::Completed.as_str())
+    .bind(rx.prescription_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(rx.refills_remaining - 1)
+}
+
+#[derive(sqlx::FromRow)]
+struct LastFill {
+    date_filled: String,
+    days_supply: i32,
+    controlled: bool,
+}
+
+/// Refuses a fill of `medication_id` for `patient_id` on `fill_date` when too
+/// little of the patient's last fill of the same drug (under any prescription)
+/// has been used, unless an early-fill reason code is given.
+/// Returns a description of the early fill for the audit trail when it was overridden.
+pub async fn check_too_soon(
+    conn: &mut SqliteConnection,
+    patient_id: i64,
+    medication_id: i64,
+    fill_date: NaiveDate,
+    early_fill_code: Option<&str>,
+) -> Result<Option<String>, AppError> {
+    let last = sqlx::query_as::<_, LastFill>(
+        "SELECT f.date_filled, f.days_supply, m.controlled
+         FROM fills f
+         JOIN prescriptions p ON p.id = f.prescription_id
+         JOIN medications m ON m.id = p.medication_id
+         WHERE p.patient_id = ? AND p.medication_id = ? AND f.voided_at IS NULL
+         ORDER BY f.date_filled DESC, f.id DESC LIMIT 1"
+    )
+    .bind(patient_id)
+    .bind(medication_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(last) = last else { return Ok(None) };
+    let Some(last_date) = parse_date(&last.date_filled) else { return Ok(None) };
+
+    let key = if last.controlled { settings::CONTROLLED_TOO_SOON_PCT } else { settings::TOO_SOON_PCT };
+    let pct = settings::get_i64(conn, key).await?;
+    // Round up so e.g. 80% of a 10-day supply waits the full 8 days
+    let min_days = (last.days_supply as i64 * pct + 99) / 100;
+    let earliest = last_date + Duration::days(min_days);
+    if fill_date >= earliest {
+        return Ok(None);
+    }
+
+    let message = format!(
+        "Too soon: last filled {} for {} days; {}% must be used{}, so the earliest fill date is {}",
+        last.date_filled, last.days_supply, pct, if last.controlled { " (controlled substance)" } else { "" }, earliest
+    );
+    match early_fill_code.map(str::trim).filter(|c| !c.is_empty()) {
+        None => Err(AppError::TooSoon(message)),
+        Some(code) if EARLY_FILL_REASONS.iter().any(|(c, _)| *c == code) => Ok(Some(format!("{} | Reason code: {}", message, code))),
+        Some(code) => Err(AppError::validation("early_fill_code", format!("Unknown early-fill reason code '{}'", code))),
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub struct VoidedFill {
+    pub prescription_id: i64,
+    pub fill_number: i64,
+    pub patient_id: i64,
+    pub medication_id: i64,
+    pub quantity: i32,
+    pub date_filled: String,
+    pub filled_by: Option<String>,
+    voided_by: Option<String>,
+}
+
+/// Undoes a fill: marks it voided, puts the quantity back into the lots it came from, gives
+/// back the refill it used and rolls the next refill date back to the latest
+/// remaining fill. A completed prescription that gets a refill back becomes
+/// active again. The original fill used no refill; voiding it (with nothing
+/// else dispensed) cancels the prescription, which must be entered again.
+/// Stock of a scheduled drug only goes back with a `witness` (already
+/// verified by the caller). Must run inside the caller's transaction.
+pub async fn void_fill(conn: &mut SqliteConnection, fill_id: i64, reason: &str, voided_by: &str, witness: Option<&str>) -> Result<VoidedFill, AppError> {
+    let fill = sqlx::query_as::<_, VoidedFill>(
+        "SELECT f.prescription_id, f.fill_number, p.patient_id, p.medication_id, f.quantity, f.date_filled, f.filled_by, f.voided_by
+         FROM fills f JOIN prescriptions p ON p.id = f.prescription_id
+         WHERE f.id = ?"
+    )
+    .bind(fill_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(AppError::not_found("Fill"))?;
+
+    if let Some(by) = &fill.voided_by {
+        return Err(AppError::Conflict(format!("Fill already voided by {}", by)));
+    }
+    if witness.is_none() && controlled::schedule_of(conn, fill.medication_id).await?.is_some() {
+        return Err(AppError::validation("witness", "Voiding a fill of a scheduled drug needs a witness"));
+    }
+
+    sqlx::query("UPDATE fills SET voided_at = CURRENT_TIMESTAMP, voided_by = ?, void_reason = ? WHERE id = ?")
+        .bind(voided_by).bind(reason).bind(fill_id)
+        .execute(&mut *conn)
+        .await?;
+
+    let movement = Movement { kind: "return", reason_code: "fill_voided", reference_id: Some(fill_id), username: voided_by, note: Some(reason), witness };
+    let drawn: Vec<(i64, i32)> = sqlx::query_as("SELECT lot_id, quantity FROM fill_lots WHERE fill_id = ?")
+        .bind(fill_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    for (lot_id, quantity) in &drawn {
+        inventory::record_movement(conn, *lot_id, *quantity, &movement).await?;
+    }
+
+    // Fills from before lots were tracked go back into the lot expiring last
+    if drawn.is_empty() {
+        let lot: Option<(i64,)> = sqlx::query_as(
+            "SELECT id FROM medication_lots WHERE medication_id = ? ORDER BY expiration DESC, id DESC LIMIT 1"
+        )
+        .bind(fill.medication_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+        let lot_id = match lot {
+            Some((lot_id,)) => lot_id,
+            None => {
+                let (expiration,): (String,) = sqlx::query_as("SELECT expiration FROM medications WHERE id = ?")
+                    .bind(fill.medication_id)
+                    .fetch_one(&mut *conn)
+                    .await?;
+                inventory::find_or_create_lot(conn, fill.medication_id, "LEGACY", &expiration).await?
+            }
+        };
+        inventory::record_movement(conn, lot_id, fill.quantity, &movement).await?;
+    }
+
+    if fill.fill_number > 0 {
+        sqlx::query(
+            "UPDATE prescriptions SET refills_remaining = refills_remaining + 1,
+                status = CASE WHEN status = 'completed' THEN 'active' ELSE status END
+             WHERE id = ?"
+        )
+        .bind(fill.prescription_id)
+        .execute(&mut *conn)
+        .await?;
+    } else {
+        sqlx::query(
+            "UPDATE prescriptions SET status = 'cancelled', status_reason = ?,
+                status_changed_at = CURRENT_TIMESTAMP, status_changed_by = ?
+             WHERE id = ? AND NOT EXISTS (
+                SELECT 1 FROM fills WHERE prescription_id = prescriptions.id AND voided_at IS NULL
+             )"
+        )
+        .bind(format!("Original fill voided: {}", reason))
+        .bind(voided_by)
+        .bind(fill.prescription_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    sqlx::query(
+        "UPDATE prescriptions SET next_refill_date = COALESCE((
+            SELECT next_refill_date FROM fills
+            WHERE prescription_id = prescriptions.id AND voided_at IS NULL
+            ORDER BY fill_number DESC LIMIT 1
+         ), date_filled)
+         WHERE id = ?"
+    )
+    .bind(fill.prescription_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(fill)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::test_pool;
+    use sqlx::SqlitePool;
+
+    /// One patient with an active prescription for a drug held in three lots:
+    /// A (10, expired), C (50, expiring last) and B (20, expiring first in date),
+    /// received in that order.
+    async fn dispense_pool() -> SqlitePool {
+        let pool = test_pool().await;
+        sqlx::raw_sql(
+            "INSERT INTO patients (name, birth_date, phone, address, city, state, postal_code, health_card_num) VALUES ('John Smith', '1985-04-12', '416-555-0199', '123 Maple Dr', 'Toronto', 'ON', 'M5V 2T6', '123-456-789-AB');
+             INSERT INTO medications (name, din, stock, price, expiration) VALUES ('Amoxicillin 500mg', '02238888', 0, 0.5, '2099-12-31');
+             INSERT INTO prescriptions (patient_id, medication_id, prescriber, sig, quantity, refills, days_supply, date_filled, next_refill_date, refills_remaining)
+                VALUES (1, 1, 'Dr. Hibbert', 'Take 1 capsule TID', 30, 2, 10, '2024-01-01', '2024-01-01', 2);"
+        )
+        .execute(&pool).await.unwrap();
+
+        let movement = Movement { kind: "receive", reason_code: "direct_receipt", reference_id: None, username: "pharm", note: None, witness: None };
+        let mut conn = pool.acquire().await.unwrap();
+        for (lot, expiration, quantity) in [("A", "2020-01-01", 10), ("C", "2099-01-01", 50), ("B", "2098-01-01", 20)] {
+            inventory::receive_into_lot(&mut conn, 1, lot, expiration, quantity, &movement).await.unwrap();
+        }
+        pool
+    }
+
+    async fn fill(pool: &SqlitePool, fill_number: i64, quantity: i32) -> Result<i64, AppError> {
+        let fill = NewFill {
+            prescription_id: 1, medication_id: 1, fill_number, quantity, days_supply: 10,
+            date_filled: "2024-01-01", filled_by: "tech", early_fill_code: None,
+        };
+        record_fill(&mut pool.acquire().await.unwrap(), &fill).await
+    }
+
+    async fn lots(pool: &SqlitePool) -> Vec<(String, i32)> {
+        sqlx::query_as("SELECT lot_number, quantity FROM medication_lots ORDER BY lot_number")
+            .fetch_all(pool).await.unwrap()
+    }
+
+    async fn stock(pool: &SqlitePool) -> i64 {
+        let (stock,): (i64,) = sqlx::query_as("SELECT stock FROM medications WHERE id = 1").fetch_one(pool).await.unwrap();
+        stock
+    }
+
+    #[tokio::test]
+    async fn draws_in_date_lots_first_expiring_first() {
+        let pool = dispense_pool().await;
+        let fill_id = fill(&pool, 0, 30).await.unwrap();
+
+        let drawn: Vec<(String, i32)> = sqlx::query_as(
+            "SELECT l.lot_number, fl.quantity FROM fill_lots fl JOIN medication_lots l ON l.id = fl.lot_id
+             WHERE fl.fill_id = ? ORDER BY l.expiration"
+        )
+        .bind(fill_id)
+        .fetch_all(&pool).await.unwrap();
+        assert_eq!(drawn, vec![("B".to_string(), 20), ("C".to_string(), 10)]);
+
+        assert_eq!(lots(&pool).await, vec![("A".to_string(), 10), ("B".to_string(), 0), ("C".to_string(), 40)]);
+        assert_eq!(stock(&pool).await, 50);
+    }
+
+    #[tokio::test]
+    async fn expired_stock_is_never_dispensed() {
+        let pool = dispense_pool().await;
+
+        // 70 in date; the expired 10 would only just cover 75
+        let err = fill(&pool, 0, 75).await.unwrap_err();
+        assert!(matches!(err, AppError::ExpiredStock(_)), "unexpected error: {:?}", err);
+        let err = fill(&pool, 0, 81).await.unwrap_err();
+        assert!(matches!(err, AppError::InsufficientStock { available: 70, requested: 81 }), "unexpected error: {:?}", err);
+
+        assert_eq!(lots(&pool).await, vec![("A".to_string(), 10), ("B".to_string(), 20), ("C".to_string(), 50)]);
+        assert_eq!(stock(&pool).await, 80);
+    }
+
+    async fn void(pool: &SqlitePool, fill_id: i64, witness: Option<&str>) -> Result<(), AppError> {
+        void_fill(&mut pool.acquire().await.unwrap(), fill_id, "Wrong patient", "pharm", witness).await.map(|_| ())
+    }
+
+    async fn prescription(pool: &SqlitePool) -> (i64, String, String) {
+        sqlx::query_as("SELECT refills_remaining, status, next_refill_date FROM prescriptions WHERE id = 1")
+            .fetch_one(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn void_returns_stock_to_the_lots_it_came_from() {
+        let pool = dispense_pool().await;
+        let fill_id = fill(&pool, 0, 30).await.unwrap();
+        void(&pool, fill_id, None).await.unwrap();
+
+        assert_eq!(lots(&pool).await, vec![("A".to_string(), 10), ("B".to_string(), 20), ("C".to_string(), 50)]);
+        assert_eq!(stock(&pool).await, 80);
+        let (voided_by,): (Option<String>,) = sqlx::query_as("SELECT voided_by FROM fills WHERE id = ?")
+            .bind(fill_id).fetch_one(&pool).await.unwrap();
+        assert_eq!(voided_by.as_deref(), Some("pharm"));
+    }
+
+    #[tokio::test]
+    async fn legacy_fill_goes_back_to_the_last_expiring_lot() {
+        let pool = dispense_pool().await;
+        sqlx::raw_sql(
+            "INSERT INTO fills (prescription_id, fill_number, quantity, days_supply, date_filled, next_refill_date) VALUES (1, 0, 12, 10, '2023-12-01', '2023-12-11');
+             INSERT INTO medications (name, din, stock, price, expiration) VALUES ('Atorvastatin 20mg', '02245555', 0, 1.0, '2030-06-30');
+             INSERT INTO prescriptions (patient_id, medication_id, prescriber, sig, quantity, refills, days_supply, date_filled, next_refill_date)
+                VALUES (1, 2, 'Dr. Nick', 'Take 1 tablet daily', 5, 0, 5, '2023-12-01', '2023-12-06');
+             INSERT INTO fills (prescription_id, fill_number, quantity, days_supply, date_filled, next_refill_date) VALUES (2, 0, 5, 5, '2023-12-01', '2023-12-06');"
+        )
+        .execute(&pool).await.unwrap();
+
+        void(&pool, 1, None).await.unwrap();
+        assert_eq!(lots(&pool).await, vec![("A".to_string(), 10), ("B".to_string(), 20), ("C".to_string(), 62)]);
+
+        // No lots on file at all: a LEGACY lot is opened at the drug's expiry
+        void(&pool, 2, None).await.unwrap();
+        let legacy: (String, String, i32) = sqlx::query_as("SELECT lot_number, expiration, quantity FROM medication_lots WHERE medication_id = 2")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(legacy, ("LEGACY".to_string(), "2030-06-30".to_string(), 5));
+    }
+
+    #[tokio::test]
+    async fn voiding_a_refill_gives_it_back() {
+        let pool = dispense_pool().await;
+        fill(&pool, 0, 10).await.unwrap();
+        let refill = NewFill {
+            prescription_id: 1, medication_id: 1, fill_number: 1, quantity: 10, days_supply: 10,
+            date_filled: "2024-01-11", filled_by: "tech", early_fill_code: None,
+        };
+        let refill_id = record_fill(&mut pool.acquire().await.unwrap(), &refill).await.unwrap();
+        sqlx::query("UPDATE prescriptions SET refills_remaining = 0, status = 'completed' WHERE id = 1")
+            .execute(&pool).await.unwrap();
+
+        void(&pool, refill_id, None).await.unwrap();
+        assert_eq!(prescription(&pool).await, (1, "active".to_string(), "2024-01-11".to_string()));
+    }
+
+    #[tokio::test]
+    async fn a_fill_can_only_be_voided_once() {
+        let pool = dispense_pool().await;
+        let fill_id = fill(&pool, 0, 30).await.unwrap();
+        void(&pool, fill_id, None).await.unwrap();
+
+        let err = void(&pool, fill_id, None).await.unwrap_err();
+        assert!(matches!(err, AppError::Conflict(_)), "unexpected error: {:?}", err);
+        assert_eq!(stock(&pool).await, 80);
+    }
+
+    #[tokio::test]
+    async fn voiding_the_original_fill_cancels_the_prescription() {
+        let pool = dispense_pool().await;
+        let fill_id = fill(&pool, 0, 30).await.unwrap();
+        void(&pool, fill_id, None).await.unwrap();
+
+        let (refills_remaining, status, _) = prescription(&pool).await;
+        assert_eq!((refills_remaining, status.as_str()), (2, "cancelled"));
+        let (reason,): (String,) = sqlx::query_as("SELECT status_reason FROM prescriptions WHERE id = 1")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(reason, "Original fill voided: Wrong patient");
+    }
+
+    #[tokio::test]
+    async fn voiding_a_scheduled_fill_needs_a_witness() {
+        let pool = dispense_pool().await;
+        sqlx::query("UPDATE medications SET schedule = 'narcotic' WHERE id = 1").execute(&pool).await.unwrap();
+        let fill_id = fill(&pool, 0, 30).await.unwrap();
+
+        match void(&pool, fill_id, None).await.unwrap_err() {
+            AppError::Validation(errors) => assert_eq!(errors[0].field, "witness"),
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq!(stock(&pool).await, 50);
+
+        void(&pool, fill_id, Some("pharm2")).await.unwrap();
+        let (witness,): (Option<String>,) = sqlx::query_as("SELECT witness FROM controlled_register ORDER BY id DESC LIMIT 1")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(witness.as_deref(), Some("pharm2"));
+    }
+
+    async fn too_soon(pool: &SqlitePool, date: &str, code: Option<&str>) -> Result<Option<String>, AppError> {
+        let date = parse_date(date).unwrap();
+        check_too_soon(&mut pool.acquire().await.unwrap(), 1, 1, date, code).await
+    }
+
+    fn is_too_soon(result: Result<Option<String>, AppError>) -> bool {
+        match result {
+            Ok(None) => false,
+            Err(AppError::TooSoon(_)) => true,
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn too_soon_rounds_the_wait_up() {
+        let pool = dispense_pool().await;
+        sqlx::query("INSERT INTO fills (prescription_id, fill_number, quantity, days_supply, date_filled, next_refill_date) VALUES (1, 0, 30, 10, '2024-01-01', '2024-01-11')")
+            .execute(&pool).await.unwrap();
+
+        // 80% of 10 days
+        assert!(is_too_soon(too_soon(&pool, "2024-01-08", None).await));
+        assert!(!is_too_soon(too_soon(&pool, "2024-01-09", None).await));
+
+        // 85% of 10 days is 8.5, so the 9th day must have passed
+        sqlx::query("UPDATE settings SET value = '85' WHERE key = ?").bind(settings::TOO_SOON_PCT).execute(&pool).await.unwrap();
+        assert!(is_too_soon(too_soon(&pool, "2024-01-09", None).await));
+        assert!(!is_too_soon(too_soon(&pool, "2024-01-10", None).await));
+    }
+
+    #[tokio::test]
+    async fn controlled_drugs_use_their_own_threshold() {
+        let pool = dispense_pool().await;
+        sqlx::raw_sql(
+            "UPDATE medications SET controlled = 1 WHERE id = 1;
+             INSERT INTO fills (prescription_id, fill_number, quantity, days_supply, date_filled, next_refill_date) VALUES (1, 0, 30, 10, '2024-01-01', '2024-01-11');"
+        )
+        .execute(&pool).await.unwrap();
+
+        // 90% of 10 days
+        assert!(is_too_soon(too_soon(&pool, "2024-01-09", None).await));
+        assert!(!is_too_soon(too_soon(&pool, "2024-01-10", None).await));
+    }
+
+    #[tokio::test]
+    async fn voided_fills_are_not_the_last_fill() {
+        let pool = dispense_pool().await;
+        sqlx::raw_sql(
+            "INSERT INTO fills (prescription_id, fill_number, quantity, days_supply, date_filled, next_refill_date) VALUES (1, 0, 30, 10, '2024-01-01', '2024-01-11');
+             INSERT INTO fills (prescription_id, fill_number, quantity, days_supply, date_filled, next_refill_date, voided_at, voided_by)
+                VALUES (1, 1, 30, 10, '2024-01-09', '2024-01-19', CURRENT_TIMESTAMP, 'pharm');"
+        )
+        .execute(&pool).await.unwrap();
+
+        assert!(!is_too_soon(too_soon(&pool, "2024-01-10", None).await));
+    }
+
+    #[tokio::test]
+    async fn early_fill_needs_a_known_reason_code() {
+        let pool = dispense_pool().await;
+        sqlx::query("INSERT INTO fills (prescription_id, fill_number, quantity, days_supply, date_filled, next_refill_date) VALUES (1, 0, 30, 10, '2024-01-01', '2024-01-11')")
+            .execute(&pool).await.unwrap();
+
+        assert!(is_too_soon(too_soon(&pool, "2024-01-05", None).await));
+        assert!(is_too_soon(too_soon(&pool, "2024-01-05", Some("  ")).await));
+
+        let note = too_soon(&pool, "2024-01-05", Some(" vacation ")).await.unwrap().unwrap();
+        assert!(note.ends_with("| Reason code: vacation"), "unexpected note: {}", note);
+
+        match too_soon(&pool, "2024-01-05", Some("felt_like_it")).await.unwrap_err() {
+            AppError::Validation(errors) => assert_eq!(errors[0].field, "early_fill_code"),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    async fn refill(pool: &SqlitePool) -> Result<i32, AppError> {
+        let mut conn = pool.acquire().await.unwrap();
+        let rx = refill_target(&mut conn, 1).await?;
+        record_refill(&mut conn, &rx, "tech", None).await
+    }
+
+    async fn not_refillable(pool: &SqlitePool) -> String {
+        match refill(pool).await {
+            Err(AppError::NotRefillable(message)) => message,
+            other => panic!("expected NotRefillable, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn refills_are_consumed_until_the_prescription_completes() {
+        let pool = dispense_pool().await;
+        fill(&pool, 0, 10).await.unwrap();
+
+        assert_eq!(refill(&pool).await.unwrap(), 1);
+        assert_eq!(prescription(&pool).await.0, 1);
+        assert_eq!(prescription(&pool).await.1, "active");
+
+        assert_eq!(refill(&pool).await.unwrap(), 0);
+        assert_eq!(prescription(&pool).await.1, "completed");
+        let numbers: Vec<(i64,)> = sqlx::query_as("SELECT fill_number FROM fills WHERE prescription_id = 1 ORDER BY id")
+            .fetch_all(&pool).await.unwrap();
+        assert_eq!(numbers, vec![(0,), (1,), (2,)]);
+
+        assert!(not_refillable(&pool).await.contains("completed"));
+    }
+
+    #[tokio::test]
+    async fn refill_is_refused_with_none_left() {
+        let pool = dispense_pool().await;
+        sqlx::query("UPDATE prescriptions SET refills_remaining = 0 WHERE id = 1").execute(&pool).await.unwrap();
+
+        assert!(not_refillable(&pool).await.contains("No refills remaining"));
+        assert_eq!(stock(&pool).await, 80);
+    }
+
+    #[tokio::test]
+    async fn refill_is_refused_after_expiry() {
+        let pool = dispense_pool().await;
+        sqlx::query("UPDATE prescriptions SET expires_on = '2020-01-01' WHERE id = 1").execute(&pool).await.unwrap();
+
+        assert!(not_refillable(&pool).await.contains("expired on 2020-01-01"));
+    }
+
+    #[tokio::test]
+    async fn only_active_prescriptions_are_refilled() {
+        let pool = dispense_pool().await;
+        for (status, label) in [("on_hold", "on hold"), ("cancelled", "cancelled"), ("transferred_out", "transferred out")] {
+            sqlx::query("UPDATE prescriptions SET status = ? WHERE id = 1").bind(status).execute(&pool).await.unwrap();
+            assert!(not_refillable(&pool).await.contains(label), "{} was refilled", status);
+        }
+
+        let err = refill_target(&mut pool.acquire().await.unwrap(), 99).await.err().unwrap();
+        assert!(matches!(err, AppError::NotFound(_)), "unexpected error: {:?}", err);
+    }
+}
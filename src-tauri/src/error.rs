@@ -0,0 +1,150 @@
+use std::fmt;
+
+use serde::ser::{SerializeStruct, Serializer};
+use serde::Serialize;
+
+use crate::clinical::ClinicalAlert;
+use crate::session::AuthError;
+
+// =====================================================
+// COMMAND ERRORS
+// =====================================================
+// Every command returns `Result<_, AppError>`. The frontend receives
+// `{ code, message, fields?, alerts? }` and can branch on `code`, which is stable.
+// Raw database and internal errors are logged where they occur and never
+// sent to the UI.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    Validation(Vec<FieldError>),
+    InsufficientStock { available: i64, requested: i64 },
+    Conflict(String),
+    ClinicalAlert(Vec<ClinicalAlert>),
+    /// The prescription can't be refilled (no refills left, expired).
+    NotRefillable(String),
+    /// Filled before the too-soon date without an early-fill reason code.
+    TooSoon(String),
+    /// Enough on hand only if expired lots were counted.
+    ExpiredStock(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Database,
+    Internal,
+}
+
+impl AppError {
+    /// Shorthand for a single-field validation failure.
+    pub fn validation(field: &str, message: impl Into<String>) -> Self {
+        AppError::Validation(vec![FieldError { field: field.to_string(), message: message.into() }])
+    }
+
+    pub fn not_found(what: &str) -> Self {
+        AppError::NotFound(format!("{} not found", what))
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "NOT_FOUND",
+            AppError::Validation(_) => "VALIDATION",
+            AppError::InsufficientStock { .. } => "INSUFFICIENT_STOCK",
+            AppError::Conflict(_) => "CONFLICT",
+            AppError::ClinicalAlert(_) => "CLINICAL_ALERT",
+            AppError::NotRefillable(_) => "NOT_REFILLABLE",
+            AppError::TooSoon(_) => "TOO_SOON",
+            AppError::ExpiredStock(_) => "EXPIRED_STOCK",
+            AppError::Unauthorized(_) => "UNAUTHORIZED",
+            AppError::Forbidden(_) => "FORBIDDEN",
+            AppError::Database => "DATABASE",
+            AppError::Internal => "INTERNAL",
+        }
+    }
+
+    /// Human-readable text, safe to show in the UI.
+    pub fn message(&self) -> String {
+        match self {
+            AppError::NotFound(m)
+            | AppError::Conflict(m)
+            | AppError::NotRefillable(m)
+            | AppError::TooSoon(m)
+            | AppError::ExpiredStock(m)
+            | AppError::Unauthorized(m)
+            | AppError::Forbidden(m) => m.clone(),
+            AppError::Validation(fields) => match fields.as_slice() {
+                [only] => only.message.clone(),
+                _ => format!("{} fields need attention", fields.len()),
+            },
+            AppError::ClinicalAlert(alerts) => match alerts.as_slice() {
+                [only] => only.message.clone(),
+                _ => format!("{} clinical alerts need review", alerts.len()),
+            },
+            AppError::InsufficientStock { available, requested } => {
+                format!("Insufficient stock! Current: {}, Requested: {}", available, requested)
+            }
+            AppError::Database => "A database error occurred. Please try again.".to_string(),
+            AppError::Internal => "An unexpected error occurred. Please try again.".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 4)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.message())?;
+        match self {
+            AppError::Validation(fields) => state.serialize_field("fields", fields)?,
+            _ => state.skip_field("fields")?,
+        }
+        match self {
+            AppError::ClinicalAlert(alerts) => state.serialize_field("alerts", alerts)?,
+            _ => state.skip_field("alerts")?,
+        }
+        state.end()
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("Record not found".to_string()),
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+                AppError::Conflict("A record with these details already exists".to_string())
+            }
+            other => {
+                eprintln!("❌ Database error: {}", other);
+                AppError::Database
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        eprintln!("❌ File error: {}", e);
+        AppError::Internal
+    }
+}
+
+impl From<AuthError> for AppError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::Forbidden { .. } => AppError::Forbidden(e.to_string()),
+            _ => AppError::Unauthorized(e.to_string()),
+        }
+    }
+}
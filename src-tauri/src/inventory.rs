@@ -0,0 +1,309 @@
+use sqlx::SqliteConnection;
+
+use crate::error::AppError;
+use crate::model::LowStockItem;
+use crate::settings;
+
+// =====================================================
+// LOTS & STOCK LEDGER
+// =====================================================
+// Stock is held in lots (lot number + expiry) under each medication. Every
+// change to a lot's quantity is a row in the append-only `stock_movements`
+// ledger; a trigger applies each row to its lot, and further triggers keep
+// `medications.stock` and `medications.expiration` in step with the lots.
+// Nothing writes a quantity directly.
+
+/// Reason codes for manual adjustments (code, movement kind, label).
+pub const ADJUSTMENT_REASONS: [(&str, &str, &str); 6] = [
+    ("count_correction", "adjust", "Count correction"),
+    ("damaged", "waste", "Damaged or broken"),
+    ("contaminated", "waste", "Spilled or contaminated"),
+    ("return_to_supplier", "return", "Returned to supplier"),
+    ("transfer_out", "transfer", "Transferred to another store"),
+    ("transfer_in", "transfer", "Received from another store"),
+];
+
+/// Checks a manual adjustment's reason code against the direction of the
+/// change and returns the movement kind to record it under.
+pub fn adjustment_kind(reason_code: &str, change: i32) -> Result<&'static str, AppError> {
+    let Some((_, kind, label)) = ADJUSTMENT_REASONS.iter().find(|(c, ..)| *c == reason_code) else {
+        return Err(AppError::validation("reason_code", format!("Unknown adjustment reason '{}'", reason_code)));
+    };
+    if change == 0 {
+        return Err(AppError::validation("change", "Quantity change cannot be zero"));
+    }
+    let inbound = reason_code == "transfer_in";
+    if reason_code != "count_correction" && (change > 0) != inbound {
+        let direction = if inbound { "add" } else { "remove" };
+        return Err(AppError::validation("change", format!("{} can only {} stock", label, direction)));
+    }
+    Ok(kind)
+}
+
+/// Who moved stock and why, recorded with each ledger row.
+pub struct Movement<'a> {
+    /// fill, receive, adjust, return, waste or transfer. The sign of the
+    /// quantity says which way stock went.
+    pub kind: &'a str,
+    pub reason_code: &'a str,
+    /// Fill, purchase order or quarantine entry the movement belongs to.
+    pub reference_id: Option<i64>,
+    pub username: &'a str,
+    pub note: Option<&'a str>,
+    /// Second signer, required for waste of scheduled drugs.
+    pub witness: Option<&'a str>,
+}
+
+/// Appends a ledger row moving `quantity` (signed) into or out of a lot.
+pub async fn record_movement(conn: &mut SqliteConnection, lot_id: i64, quantity: i32, movement: &Movement<'_>) -> Result<(), AppError> {
+    sqlx::query(
+        "INSERT INTO stock_movements (lot_id, kind, quantity, reason_code, reference_id, username, note, witness) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(lot_id).bind(movement.kind).bind(quantity).bind(movement.reason_code)
+    .bind(movement.reference_id).bind(movement.username).bind(movement.note).bind(movement.witness)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Finds the medication's lot, creating it empty if it is new.
+/// A lot number already on file must come with the same expiry.
+pub async fn find_or_create_lot(conn: &mut SqliteConnection, medication_id: i64, lot_number: &str, expiration: &str) -> Result<i64, AppError> {
+    let existing: Option<(i64, String)> = sqlx::query_as(
+        "SELECT id, expiration FROM medication_lots WHERE medication_id = ? AND lot_number = ?"
+    )
+    .bind(medication_id)
+    .bind(lot_number)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match existing {
+        Some((_, on_file)) if on_file != expiration => Err(AppError::Conflict(format!(
+            "Lot {} is on file with expiry {}, not {}", lot_number, on_file, expiration
+        ))),
+        Some((lot_id, _)) => Ok(lot_id),
+        None => {
+            let lot_id = sqlx::query(
+                "INSERT INTO medication_lots (medication_id, lot_number, expiration, quantity) VALUES (?, ?, ?, 0)"
+            )
+            .bind(medication_id).bind(lot_number).bind(expiration)
+            .execute(&mut *conn)
+            .await?
+            .last_insert_rowid();
+            Ok(lot_id)
+        }
+    }
+}
+
+/// Adds `quantity` to the medication's lot, creating the lot if it is new.
+pub async fn receive_into_lot(
+    conn: &mut SqliteConnection,
+    medication_id: i64,
+    lot_number: &str,
+    expiration: &str,
+    quantity: i32,
+    movement: &Movement<'_>,
+) -> Result<i64, AppError> {
+    let lot_id = find_or_create_lot(conn, medication_id, lot_number, expiration).await?;
+    record_movement(conn, lot_id, quantity, movement).await?;
+    Ok(lot_id)
+}
+
+/// Moves everything left in a lot off the shelf into quarantine.
+/// Returns the quantity moved (0 if the lot was already empty).
+pub async fn quarantine_lot(conn: &mut SqliteConnection, lot_id: i64, reason: &str, username: &str) -> Result<i32, AppError> {
+    let (quantity,): (i32,) = sqlx::query_as("SELECT quantity FROM medication_lots WHERE id = ?")
+        .bind(lot_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AppError::not_found("Lot"))?;
+
+    if quantity == 0 {
+        return Ok(0);
+    }
+
+    let quarantine_id = sqlx::query("INSERT INTO quarantined_stock (lot_id, quantity, reason, quarantined_by) VALUES (?, ?, ?, ?)")
+        .bind(lot_id).bind(quantity).bind(reason).bind(username)
+        .execute(&mut *conn)
+        .await?
+        .last_insert_rowid();
+    let movement = Movement { kind: "transfer", reason_code: "quarantine", reference_id: Some(quarantine_id), username, note: Some(reason), witness: None };
+    record_movement(conn, lot_id, -quantity, &movement).await?;
+
+    Ok(quantity)
+}
+
+// =====================================================
+// REORDERING
+// =====================================================
+
+#[derive(sqlx::FromRow)]
+struct StockLevel {
+    id: i64,
+    name: String,
+    din: String,
+    stock: i32,
+    reorder_point: i32,
+    reorder_qty: i32,
+    auto_reorder: bool,
+    /// Units dispensed (non-voided fills) within the velocity window.
+    dispensed: i64,
+}
+
+/// Drugs at or below their reorder point, with days of cover and a suggested
+/// order quantity (at least the reorder quantity, and enough to get back above
+/// the reorder point).
+pub async fn low_stock_items(conn: &mut SqliteConnection) -> Result<Vec<LowStockItem>, AppError> {
+    let window = settings::get_i64(conn, settings::VELOCITY_WINDOW_DAYS).await?;
+    let lead_days = settings::get_i64(conn, settings::REORDER_LEAD_DAYS).await?;
+    let cover_days = settings::get_i64(conn, settings::REORDER_COVER_DAYS).await?;
+
+    let rows = sqlx::query_as::<_, StockLevel>(
+        "SELECT m.id, m.name, m.din, m.stock, m.reorder_point, m.reorder_qty, m.auto_reorder,
+            COALESCE((
+                SELECT SUM(f.quantity) FROM fills f JOIN prescriptions p ON p.id = f.prescription_id
+                WHERE p.medication_id = m.id AND f.voided_at IS NULL
+                AND f.date_filled >= date('now', '-' || ? || ' days')
+            ), 0) AS dispensed
+         FROM medications m
+         ORDER BY m.name"
+    )
+    .bind(window)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let items = rows
+        .into_iter()
+        .filter_map(|m| {
+            let daily_usage = m.dispensed as f64 / window as f64;
+            // Without any recent dispensing there is nothing to compute from
+            let (reorder_point, reorder_qty) = if m.auto_reorder && m.dispensed > 0 {
+                ((daily_usage * lead_days as f64).ceil() as i32, (daily_usage * cover_days as f64).ceil() as i32)
+            } else {
+                (m.reorder_point, m.reorder_qty)
+            };
+            if m.stock > reorder_point {
+                return None;
+            }
+            Some(LowStockItem {
+                medication_id: m.id,
+                name: m.name,
+                din: m.din,
+                stock: m.stock,
+                reorder_point,
+                reorder_qty,
+                auto_reorder: m.auto_reorder,
+                daily_usage,
+                days_of_cover: (daily_usage > 0.0).then(|| m.stock as f64 / daily_usage),
+                suggested_qty: reorder_qty.max(reorder_point - m.stock + 1),
+            })
+        })
+        .collect();
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::test_pool;
+    use sqlx::SqlitePool;
+
+    async fn inventory_pool() -> SqlitePool {
+        let pool = test_pool().await;
+        sqlx::raw_sql("INSERT INTO medications (name, din, stock, price, expiration) VALUES ('Amoxicillin 500mg', '02238888', 0, 0.5, '2099-12-31');")
+            .execute(&pool).await.unwrap();
+
+        let movement = Movement { kind: "receive", reason_code: "direct_receipt", reference_id: None, username: "pharm", note: None, witness: None };
+        let mut conn = pool.acquire().await.unwrap();
+        for (lot, expiration, quantity) in [("A", "2020-01-01", 25), ("B", "2099-01-01", 10)] {
+            receive_into_lot(&mut conn, 1, lot, expiration, quantity, &movement).await.unwrap();
+        }
+        pool
+    }
+
+    async fn stock(conn: &mut SqliteConnection) -> i32 {
+        let (stock,): (i32,) = sqlx::query_as("SELECT stock FROM medications WHERE id = 1").fetch_one(&mut *conn).await.unwrap();
+        stock
+    }
+
+    #[tokio::test]
+    async fn quarantine_takes_the_whole_lot() {
+        let pool = inventory_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        assert_eq!(quarantine_lot(&mut conn, 1, "Expired", "pharm").await.unwrap(), 25);
+        let (lot,): (i32,) = sqlx::query_as("SELECT quantity FROM medication_lots WHERE id = 1").fetch_one(&mut *conn).await.unwrap();
+        assert_eq!(lot, 0);
+        assert_eq!(stock(&mut conn).await, 10);
+
+        let held: (i64, i32, String, String) = sqlx::query_as("SELECT lot_id, quantity, reason, disposition FROM quarantined_stock")
+            .fetch_one(&mut *conn).await.unwrap();
+        assert_eq!(held, (1, 25, "Expired".to_string(), "pending".to_string()));
+        let moved: (String, i32, Option<i64>) = sqlx::query_as(
+            "SELECT kind, quantity, reference_id FROM stock_movements WHERE reason_code = 'quarantine'"
+        )
+        .fetch_one(&mut *conn).await.unwrap();
+        assert_eq!(moved, ("transfer".to_string(), -25, Some(1)));
+
+        // An emptied lot has nothing left to move
+        assert_eq!(quarantine_lot(&mut conn, 1, "Expired", "pharm").await.unwrap(), 0);
+        let (entries,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM quarantined_stock").fetch_one(&mut *conn).await.unwrap();
+        assert_eq!(entries, 1);
+
+        assert!(matches!(quarantine_lot(&mut conn, 99, "Expired", "pharm").await, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn reorder_quantities_follow_dispensing_velocity() {
+        let pool = inventory_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        quarantine_lot(&mut conn, 1, "Expired", "pharm").await.unwrap();
+        sqlx::raw_sql(
+            "UPDATE medications SET auto_reorder = 1 WHERE id = 1;
+             INSERT INTO medications (name, din, stock, price, expiration, reorder_point, reorder_qty, auto_reorder)
+                VALUES ('Zopiclone 7.5mg', '02246534', 0, 0.3, '2099-12-31', 20, 5, 1),
+                       ('Ibuprofen 200mg', '02242345', 0, 0.1, '2099-12-31', 30, 50, 0);
+             INSERT INTO patients (name, birth_date, phone, address, city, state, postal_code, health_card_num)
+                VALUES ('John Smith', '1985-04-12', '416-555-0199', '123 Maple Dr', 'Toronto', 'ON', 'M5V 2T6', '123-456-789-AB');
+             INSERT INTO prescriptions (patient_id, medication_id, prescriber, sig, quantity, refills, days_supply, date_filled, next_refill_date)
+                VALUES (1, 1, 'Dr. Hibbert', 'Take 1 capsule TID', 90, 0, 30, date('now'), date('now'));
+             INSERT INTO fills (prescription_id, fill_number, quantity, days_supply, date_filled, next_refill_date, voided_at)
+                VALUES (1, 0, 900, 300, date('now', '-200 days'), date('now'), NULL),
+                       (1, 1, 90, 30, date('now', '-40 days'), date('now'), NULL),
+                       (1, 2, 90, 30, date('now', '-10 days'), date('now'), NULL),
+                       (1, 3, 90, 30, date('now', '-5 days'), date('now'), CURRENT_TIMESTAMP);"
+        )
+        .execute(&mut *conn).await.unwrap();
+        let movement = Movement { kind: "receive", reason_code: "direct_receipt", reference_id: None, username: "pharm", note: None, witness: None };
+        receive_into_lot(&mut conn, 3, "C", "2099-01-01", 40, &movement).await.unwrap();
+
+        let items = low_stock_items(&mut conn).await.unwrap();
+        let summary: Vec<_> = items.iter()
+            .map(|i| (i.name.as_str(), i.stock, i.reorder_point, i.reorder_qty, i.suggested_qty, i.days_of_cover))
+            .collect();
+
+        // 180 units over the 90-day window is 2 a day: 7 days of lead time and
+        // 30 of cover. The old fill and the voided one don't count. Zopiclone
+        // has no history, so its own figures stand; Ibuprofen isn't low.
+        assert_eq!(summary, [
+            ("Amoxicillin 500mg", 10, 14, 60, 60, Some(5.0)),
+            ("Zopiclone 7.5mg", 0, 20, 5, 21, None),
+        ]);
+        assert_eq!(items[0].daily_usage, 2.0);
+    }
+
+    #[tokio::test]
+    async fn ledger_is_append_only() {
+        let pool = inventory_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        for sql in ["UPDATE stock_movements SET quantity = 99 WHERE id = 1", "DELETE FROM stock_movements WHERE id = 1"] {
+            let err = sqlx::query(sql).execute(&mut *conn).await.unwrap_err();
+            assert!(err.to_string().contains("stock_movements is append-only"), "unexpected error: {:?}", err);
+        }
+        let (count, quantity): (i64, i32) = sqlx::query_as("SELECT COUNT(*), (SELECT quantity FROM stock_movements WHERE id = 1) FROM stock_movements")
+            .fetch_one(&mut *conn).await.unwrap();
+        assert_eq!((count, quantity), (2, 25));
+        assert_eq!(stock(&mut conn).await, 35);
+    }
+}
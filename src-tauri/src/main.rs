@@ -1,13 +1,42 @@
+mod audit;
+mod auth;
+mod clinical;
+mod controlled;
+mod counts;
+mod dispense;
+mod error;
+mod inventory;
+mod migrations;
 mod model;
+mod patients;
+mod permissions;
+mod purchasing;
+mod rx_status;
+mod search;
 mod seed;
+mod session;
+mod settings;
+mod validation;
 
 use tauri::{State, Manager};
 use sqlx::sqlite::{SqlitePool, SqlitePoolOptions, SqliteConnectOptions};
+use audit::{Entity, Event};
+use serde_json::json;
+use error::AppError;
+use inventory::Movement;
+use permissions::{Capability, Role};
+use rx_status::RxStatus;
+use session::{AuthError, SessionStore};
 use model::{
-    CreatePatientDto, Patient, PatientHistoryItem,
-    CreateMedicationDto, UpdateMedicationDto, Medication,
-    CreatePrescriptionDto, DashboardStats, DueRxItem,
-    LoginDto, AuthResponse, AuditLogItem
+    CreatePatientDto, UpdatePatientDto, Patient, PatientHistoryItem, PatientSearchFilter, DuplicatePatientPair,
+    PatientAllergy, CreateAllergyDto,
+    CreateMedicationDto, UpdateMedicationDto, Medication, IngredientTag, MedicationLot, ReceiveLotDto, LotFillItem,
+    ExpiringLotItem, QuarantineItem, LowStockItem, StockMovement, StockDiscrepancy, AdjustmentReason,
+    CountSession, CountLineItem, WitnessDto, ScheduleOption, RegisterEntry, RegisterReconciliation,
+    Supplier, CreateSupplierDto, PoLineDto, CreatePurchaseOrderDto, PurchaseOrderSummary, PurchaseOrderLine, ReceivePoLineDto,
+    CreatePrescriptionDto, PendingVerificationItem, InteractionRecord, EarlyFillReason, DashboardStats, DueRxItem,
+    LoginDto, AuthResponse, ChangePasswordDto, UserCredentials, AuditLogFilter, AuditLogItem, AuditLogPage, AuditChainReport,
+    CreateUserDto, UserSummary, Setting
 };
 
 // =====================================================
@@ -15,283 +44,1502 @@ use model::{
 // =====================================================
 
 #[tauri::command]
-async fn add_patient(pool: State<'_, SqlitePool>, data: CreatePatientDto) -> Result<String, String> {
-    let result = sqlx::query(
+async fn add_patient(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, data: CreatePatientDto) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::PatientsWrite).await?;
+    validation::validate_patient(&data)?;
+
+    let mut tx = pool.begin().await?;
+
+    let patient_id = sqlx::query(
         "INSERT INTO patients (
             name, birth_date, phone, email, address, city, state, postal_code, 
-            health_card_num, allergies, insurance_provider, insurance_id
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+            health_card_num, insurance_provider, insurance_id
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
     )
     .bind(&data.name).bind(&data.birth_date).bind(&data.phone).bind(&data.email)
     .bind(&data.address).bind(&data.city).bind(&data.state).bind(&data.postal_code)
-    .bind(&data.health_card_num).bind(&data.allergies).bind(&data.insurance_provider).bind(&data.insurance_id)
-    .execute(pool.inner())
+    .bind(&data.health_card_num).bind(&data.insurance_provider).bind(&data.insurance_id)
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+
+    clinical::record_allergies_from_text(&mut tx, patient_id, data.allergies.as_deref().unwrap_or_default()).await?;
+
+    let after = audit::snapshot(&mut *tx, Entity::Patient, patient_id).await?;
+    audit::record(&mut *tx, &user.username, Event::new("ADD_PATIENT", format!("Created profile for: {}", data.name))
+        .on(Entity::Patient, patient_id).after(after)).await?;
+
+    tx.commit().await?;
+    Ok("Patient saved successfully!".to_string())
+}
+
+/// "field: old -> new" for every field that differs, for the audit trail.
+fn patient_changes(old: &Patient, new: &CreatePatientDto) -> Vec<String> {
+    let opt = |v: &Option<String>| v.clone().unwrap_or_default();
+    let fields = [
+        ("name", old.name.clone(), new.name.clone()),
+        ("birth_date", old.birth_date.clone(), new.birth_date.clone()),
+        ("phone", old.phone.clone(), new.phone.clone()),
+        ("email", opt(&old.email), opt(&new.email)),
+        ("address", old.address.clone(), new.address.clone()),
+        ("city", old.city.clone(), new.city.clone()),
+        ("state", old.state.clone(), new.state.clone()),
+        ("postal_code", old.postal_code.clone(), new.postal_code.clone()),
+        ("health_card_num", old.health_card_num.clone(), new.health_card_num.clone()),
+        ("insurance_provider", opt(&old.insurance_provider), opt(&new.insurance_provider)),
+        ("insurance_id", opt(&old.insurance_id), opt(&new.insurance_id)),
+    ];
+    fields.into_iter()
+        .filter(|(_, before, after)| before != after)
+        .map(|(field, before, after)| format!("{}: '{}' -> '{}'", field, before, after))
+        .collect()
+}
+
+#[tauri::command]
+async fn update_patient(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, data: UpdatePatientDto) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::PatientsWrite).await?;
+    validation::validate_patient(&data.fields)?;
+
+    let mut tx = pool.begin().await?;
+
+    // Read inside the transaction so the diff and the audit's "before" are
+    // the row this update actually replaces
+    let old = sqlx::query_as::<_, Patient>("SELECT * FROM patients WHERE id = ?")
+        .bind(data.id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::not_found("Patient"))?;
+
+    let changes = patient_changes(&old, &data.fields);
+    if changes.is_empty() {
+        return Ok("No changes to save.".to_string());
+    }
+
+    let p = &data.fields;
+    sqlx::query(
+        "UPDATE patients SET
+            name = ?, birth_date = ?, phone = ?, email = ?, address = ?, city = ?, state = ?, postal_code = ?,
+            health_card_num = ?, insurance_provider = ?, insurance_id = ?
+         WHERE id = ?"
+    )
+    .bind(&p.name).bind(&p.birth_date).bind(&p.phone).bind(&p.email)
+    .bind(&p.address).bind(&p.city).bind(&p.state).bind(&p.postal_code)
+    .bind(&p.health_card_num).bind(&p.insurance_provider).bind(&p.insurance_id)
+    .bind(data.id)
+    .execute(&mut *tx)
+    .await?;
+
+    let after = audit::snapshot(&mut *tx, Entity::Patient, data.id).await?;
+    audit::record(&mut *tx, &user.username, Event::new("UPDATE_PATIENT", format!("Patient ID {}: {}", data.id, changes.join("; ")))
+        .on(Entity::Patient, data.id).before(serde_json::to_value(&old).ok()).after(after)).await?;
+
+    tx.commit().await?;
+    Ok("Patient updated.".to_string())
+}
+
+#[tauri::command]
+async fn find_duplicate_patients(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<DuplicatePatientPair>, AppError> {
+    sessions.authorize(pool.inner(), Capability::PatientsRead).await?;
+    let mut conn = pool.acquire().await?;
+    Ok(patients::find_duplicates(&mut conn).await?)
+}
+
+/// Folds `duplicate_id` into `survivor_id` and records the merge on both
+/// profiles. All or nothing.
+#[tauri::command]
+async fn merge_patients(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, survivor_id: i64, duplicate_id: i64) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::PatientsMerge).await?;
+
+    let mut tx = pool.begin().await?;
+    let patients::Merged { survivor, duplicate, prescriptions_moved: moved } = patients::merge(&mut tx, survivor_id, duplicate_id).await?;
+
+    // Recorded against both profiles, so each one's history shows the merge
+    let details = format!(
+        "Merged Patient ID {} ({}, DOB {}, card {}) into Patient ID {} ({}); {} prescription(s) moved",
+        duplicate.id, duplicate.name, duplicate.birth_date, duplicate.health_card_num,
+        survivor.id, survivor.name, moved
+    );
+    let after = audit::snapshot(&mut *tx, Entity::Patient, survivor_id).await?;
+    audit::record(&mut *tx, &user.username, Event::new("MERGE_PATIENT", details.clone())
+        .on(Entity::Patient, survivor_id).before(serde_json::to_value(&survivor).ok()).after(after)).await?;
+    audit::record(&mut *tx, &user.username, Event::new("MERGE_PATIENT", details)
+        .on(Entity::Patient, duplicate_id).before(serde_json::to_value(&duplicate).ok())).await?;
+
+    tx.commit().await?;
+    Ok(format!("Merged. {} prescription(s) moved to {}.", moved, survivor.name))
+}
+
+#[tauri::command]
+async fn get_patients(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, search: Option<String>, filter: Option<PatientSearchFilter>) -> Result<Vec<Patient>, AppError> {
+    sessions.authorize(pool.inner(), Capability::PatientsRead).await?;
+
+    // `search` is the plain search box; `filter` carries the typed fields
+    let mut filter = filter.unwrap_or_default();
+    if filter.text.is_none() {
+        filter.text = search;
+    }
+
+    let patients = search::search_patients(pool.inner(), &filter)
+        .await?;
+    Ok(patients)
+}
+
+#[tauri::command]
+async fn get_patient_history(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, patient_id: i64) -> Result<Vec<PatientHistoryItem>, AppError> {
+    sessions.authorize(pool.inner(), Capability::PatientsRead).await?;
+
+    let history = sqlx::query_as::<_, PatientHistoryItem>(
+        "SELECT f.id, f.prescription_id, f.fill_number, p.status, m.name as drug_name, m.schedule, p.sig, f.quantity, f.date_filled, f.next_refill_date, f.voided_at
+         FROM fills f
+         JOIN prescriptions p ON f.prescription_id = p.id
+         JOIN medications m ON p.medication_id = m.id
+         WHERE p.patient_id = ? ORDER BY f.date_filled DESC, f.id DESC"
+    )
+    .bind(patient_id)
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(history)
+}
+
+#[tauri::command]
+async fn get_patient_allergies(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, patient_id: i64) -> Result<Vec<PatientAllergy>, AppError> {
+    sessions.authorize(pool.inner(), Capability::PatientsRead).await?;
+
+    let allergies = sqlx::query_as::<_, PatientAllergy>(
+        "SELECT * FROM patient_allergies WHERE patient_id = ? ORDER BY allergen ASC"
+    )
+    .bind(patient_id)
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(allergies)
+}
+
+#[tauri::command]
+async fn add_patient_allergy(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, data: CreateAllergyDto) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::PatientsWrite).await?;
+    validation::validate_allergy(&data)?;
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("SELECT id FROM patients WHERE id = ?")
+        .bind(data.patient_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::not_found("Patient"))?;
+
+    let allergy = sqlx::query_as::<_, PatientAllergy>(
+        "INSERT INTO patient_allergies (patient_id, allergen, reaction, severity) VALUES (?, ?, ?, ?) RETURNING *"
+    )
+    .bind(data.patient_id)
+    .bind(data.allergen.trim())
+    .bind(data.reaction.as_deref().map(str::trim).filter(|r| !r.is_empty()))
+    .bind(&data.severity)
+    .fetch_one(&mut *tx)
+    .await?;
+    clinical::refresh_allergy_summary(&mut tx, data.patient_id).await?;
+
+    audit::record(&mut *tx, &user.username, Event::new("ADD_ALLERGY", format!("Patient ID {}: {} ({})", data.patient_id, allergy.allergen, data.severity))
+        .on(Entity::Patient, data.patient_id).after(serde_json::to_value(&allergy).ok())).await?;
+
+    tx.commit().await?;
+    Ok("Allergy recorded.".to_string())
+}
+
+#[tauri::command]
+async fn remove_patient_allergy(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, allergy_id: i64) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::PatientsWrite).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let allergy = sqlx::query_as::<_, PatientAllergy>("SELECT * FROM patient_allergies WHERE id = ?")
+        .bind(allergy_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::not_found("Allergy"))?;
+
+    sqlx::query("DELETE FROM patient_allergies WHERE id = ?")
+        .bind(allergy_id)
+        .execute(&mut *tx)
+        .await?;
+    clinical::refresh_allergy_summary(&mut tx, allergy.patient_id).await?;
+
+    audit::record(&mut *tx, &user.username, Event::new("REMOVE_ALLERGY", format!("Patient ID {}: {}", allergy.patient_id, allergy.allergen))
+        .on(Entity::Patient, allergy.patient_id).before(serde_json::to_value(&allergy).ok())).await?;
+
+    tx.commit().await?;
+    Ok("Allergy removed.".to_string())
+}
+
+// =====================================================
+// COMMANDS: INVENTORY
+// =====================================================
+
+#[tauri::command]
+async fn add_medication(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, data: CreateMedicationDto) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+    validation::validate_new_medication(&data)?;
+    let schedule = data.schedule.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    // Stored as validated, so " 02238888" can't slip past the unique DIN
+    let din = data.din.trim();
+    let ndc = data.ndc.as_deref().map(str::trim).filter(|n| !n.is_empty());
+
+    let mut tx = pool.begin().await?;
+
+    // Stock starts at zero; the opening lot brings it up via the lot triggers.
+    // A scheduled drug's opening receipt is the first line of its register.
+    let result = sqlx::query(
+        "INSERT INTO medications (name, din, ndc, description, stock, price, expiration, controlled, reorder_point, reorder_qty, auto_reorder, schedule) 
+         VALUES (?, ?, ?, ?, 0, ?, ?, ?, COALESCE(?, 100), COALESCE(?, 0), ?, ?)"
+    )
+    .bind(&data.name).bind(din).bind(ndc).bind(&data.description)
+    .bind(data.price).bind(&data.expiration).bind(data.controlled || schedule.is_some())
+    .bind(data.reorder_point).bind(data.reorder_qty).bind(data.auto_reorder).bind(schedule)
+    .execute(&mut *tx)
     .await;
 
-    match result {
-        Ok(_) => {
-            let _ = sqlx::query("INSERT INTO audit_logs (username, action, details) VALUES (?, ?, ?)")
-                .bind(&data.logged_in_user)
-                .bind("ADD_PATIENT")
-                .bind(format!("Created profile for: {}", data.name))
-                .execute(pool.inner())
-                .await;
-            Ok("Patient saved successfully!".to_string())
+    let medication_id = match result {
+        Ok(r) => r.last_insert_rowid(),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Err(AppError::Conflict(format!("A medication with DIN {} already exists", din)));
         },
-        Err(e) => Err(format!("Failed to save: {}", e))
+        Err(e) => return Err(e.into())
+    };
+
+    let lot_number = data.lot_number.as_deref().map(str::trim).filter(|l| !l.is_empty()).unwrap_or("OPENING");
+    if data.stock > 0 {
+        let movement = Movement { kind: "receive", reason_code: "opening_stock", reference_id: None, username: &user.username, note: None, witness: None };
+        inventory::receive_into_lot(&mut tx, medication_id, lot_number, &data.expiration, data.stock, &movement).await?;
+    }
+
+    let after = audit::snapshot(&mut *tx, Entity::Medication, medication_id).await?;
+    audit::record(&mut *tx, &user.username, Event::new("ADD_INVENTORY", format!("Added drug: {} (Stock: {}, Lot {})", data.name, data.stock, lot_number))
+        .on(Entity::Medication, medication_id).after(after)).await?;
+
+    tx.commit().await?;
+    Ok("Medication added.".to_string())
+}
+
+#[tauri::command]
+async fn update_medication(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, data: UpdateMedicationDto) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+    validation::validate_medication_update(&data)?;
+
+    let mut tx = pool.begin().await?;
+
+    let before = audit::snapshot(&mut *tx, Entity::Medication, data.id).await?;
+    let current = controlled::schedule_of(&mut tx, data.id).await?;
+    let schedule = match data.schedule.as_deref().map(str::trim) {
+        None => current.clone(),
+        Some("") => None,
+        Some(code) => Some(code.to_string()),
+    };
+    if schedule != current {
+        if !user.can(Capability::InventoryApprove) {
+            return Err(AppError::Forbidden("Only a pharmacist can change a drug's schedule".to_string()));
+        }
+        if schedule.is_some() {
+            controlled::open_register(&mut tx, data.id, &user.username).await?;
+        }
+        let event = Event::new("CHANGE_SCHEDULE", format!(
+            "Med ID {}: {} -> {}", data.id, current.as_deref().unwrap_or("unscheduled"), schedule.as_deref().unwrap_or("unscheduled")
+        ));
+        audit::record(&mut *tx, &user.username, event.on(Entity::Medication, data.id)
+            .before(json!({ "schedule": current })).after(json!({ "schedule": schedule }))).await?;
+    }
+    // Scheduled drugs stay flagged controlled
+    let controlled = if schedule.is_some() { Some(true) } else { data.controlled };
+
+    sqlx::query(
+        "UPDATE medications SET price = ?, description = ?, controlled = COALESCE(?, controlled), schedule = ?,
+            reorder_point = COALESCE(?, reorder_point), reorder_qty = COALESCE(?, reorder_qty), auto_reorder = COALESCE(?, auto_reorder)
+         WHERE id = ?"
+    )
+    .bind(data.price)
+    .bind(&data.description)
+    .bind(controlled)
+    .bind(&schedule)
+    .bind(data.reorder_point)
+    .bind(data.reorder_qty)
+    .bind(data.auto_reorder)
+    .bind(data.id)
+    .execute(&mut *tx)
+    .await?;
+
+    let after = audit::snapshot(&mut *tx, Entity::Medication, data.id).await?;
+    audit::record(&mut *tx, &user.username, Event::new("UPDATE_INVENTORY", format!("Updated Med ID {}: Price ${}", data.id, data.price))
+        .on(Entity::Medication, data.id).before(before).after(after)).await?;
+
+    tx.commit().await?;
+    Ok("Inventory updated successfully.".to_string())
+}
+
+#[tauri::command]
+async fn get_medications(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<Medication>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let meds = sqlx::query_as::<_, Medication>("SELECT * FROM medications ORDER BY name ASC")
+        .fetch_all(pool.inner())
+        .await?;
+    Ok(meds)
+}
+
+#[tauri::command]
+async fn get_medication_ingredients(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, medication_id: i64) -> Result<Vec<IngredientTag>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let tags = sqlx::query_as::<_, IngredientTag>(
+        "SELECT name, kind FROM medication_ingredients WHERE medication_id = ? ORDER BY kind, name"
+    )
+    .bind(medication_id)
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(tags)
+}
+
+/// Replaces the medication's ingredient/class tags with `tags`.
+#[tauri::command]
+async fn set_medication_ingredients(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, medication_id: i64, tags: Vec<IngredientTag>) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+    validation::validate_ingredients(&tags)?;
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("SELECT id FROM medications WHERE id = ?")
+        .bind(medication_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::not_found("Drug"))?;
+
+    let before = sqlx::query_as::<_, IngredientTag>(
+        "SELECT name, kind FROM medication_ingredients WHERE medication_id = ? ORDER BY kind, name"
+    )
+    .bind(medication_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM medication_ingredients WHERE medication_id = ?")
+        .bind(medication_id)
+        .execute(&mut *tx)
+        .await?;
+    for tag in &tags {
+        sqlx::query("INSERT OR IGNORE INTO medication_ingredients (medication_id, name, kind) VALUES (?, ?, ?)")
+            .bind(medication_id)
+            .bind(tag.name.trim().to_lowercase())
+            .bind(&tag.kind)
+            .execute(&mut *tx)
+            .await?;
     }
+
+    let names: Vec<&str> = tags.iter().map(|t| t.name.trim()).collect();
+    audit::record(&mut *tx, &user.username, Event::new("UPDATE_INGREDIENTS", format!("Med ID {}: {}", medication_id, names.join(", ")))
+        .on(Entity::Medication, medication_id).before(serde_json::to_value(&before).ok()).after(serde_json::to_value(&tags).ok())).await?;
+
+    tx.commit().await?;
+    Ok("Ingredients updated.".to_string())
+}
+
+#[tauri::command]
+async fn get_medication_lots(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, medication_id: i64) -> Result<Vec<MedicationLot>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let lots = sqlx::query_as::<_, MedicationLot>(
+        "SELECT id, medication_id, lot_number, expiration, quantity, received_at
+         FROM medication_lots WHERE medication_id = ?
+         ORDER BY expiration, received_at, id"
+    )
+    .bind(medication_id)
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(lots)
+}
+
+/// Adds received stock to a lot (a new lot, or more of one already on file).
+#[tauri::command]
+async fn receive_lot(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, data: ReceiveLotDto) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+    validation::validate_lot(&data)?;
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("SELECT id FROM medications WHERE id = ?")
+        .bind(data.medication_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::not_found("Drug"))?;
+
+    let lot_number = data.lot_number.trim();
+    let movement = Movement { kind: "receive", reason_code: "direct_receipt", reference_id: None, username: &user.username, note: None, witness: None };
+    let lot_id = inventory::receive_into_lot(&mut tx, data.medication_id, lot_number, &data.expiration, data.quantity, &movement).await?;
+
+    audit::record(&mut *tx, &user.username, Event::new("RECEIVE_LOT",
+        format!("Med ID {}: received {} of lot {} (exp {})", data.medication_id, data.quantity, lot_number, data.expiration))
+        .on(Entity::Medication, data.medication_id)
+        .after(json!({ "lot_id": lot_id, "lot_number": lot_number, "expiration": data.expiration, "received": data.quantity }))
+    ).await?;
+
+    tx.commit().await?;
+    Ok(format!("Received {} into lot {}.", data.quantity, lot_number))
+}
+
+#[tauri::command]
+async fn get_adjustment_reasons(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<AdjustmentReason>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    Ok(inventory::ADJUSTMENT_REASONS.iter().map(|(code, _, label)| AdjustmentReason { code, label }).collect())
+}
+
+/// Adds or removes stock from a lot outside of dispensing and receiving
+/// (count corrections, breakage, returns, store transfers). `change` is signed.
+/// Taking a scheduled drug off the shelf (any negative change) needs a
+/// witness; a witness given for any other adjustment is checked and recorded too.
+#[tauri::command]
+async fn adjust_lot(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, lot_id: i64, change: i32, reason_code: String, note: Option<String>, witness: Option<WitnessDto>) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+    let kind = inventory::adjustment_kind(&reason_code, change)?;
+    let note = note.as_deref().map(str::trim).filter(|n| !n.is_empty());
+    let witness = match &witness {
+        Some(w) => Some(controlled::verify_witness(pool.inner(), w, &user).await?),
+        None => None,
+    };
+
+    let mut tx = pool.begin().await?;
+
+    let (medication_id, lot_number, on_hand): (i64, String, i32) = sqlx::query_as(
+        "SELECT medication_id, lot_number, quantity FROM medication_lots WHERE id = ?"
+    )
+    .bind(lot_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::not_found("Lot"))?;
+
+    if on_hand + change < 0 {
+        return Err(AppError::validation("change", format!("Lot {} only has {} on hand", lot_number, on_hand)));
+    }
+
+    let scheduled = controlled::schedule_of(&mut tx, medication_id).await?.is_some();
+    if scheduled && change < 0 && witness.is_none() {
+        return Err(AppError::validation("witness", "Removing stock of a scheduled drug needs a witness"));
+    }
+
+    let movement = Movement { kind, reason_code: &reason_code, reference_id: None, username: &user.username, note, witness: witness.as_deref() };
+    inventory::record_movement(&mut tx, lot_id, change, &movement).await?;
+
+    audit::record(&mut *tx, &user.username, Event::new("ADJUST_LOT", format!(
+        "Med ID {} lot {}: {} -> {} | Reason: {}{}{}", medication_id, lot_number, on_hand, on_hand + change, reason_code,
+        note.map(|n| format!(" ({})", n)).unwrap_or_default(),
+        witness.as_deref().map(|w| format!(" | Witness: {}", w)).unwrap_or_default()
+    ))
+        .on(Entity::Medication, medication_id)
+        .before(json!({ "lot_id": lot_id, "lot_number": lot_number, "quantity": on_hand }))
+        .after(json!({ "lot_id": lot_id, "lot_number": lot_number, "quantity": on_hand + change, "reason_code": reason_code, "witness": witness }))
+    ).await?;
+
+    tx.commit().await?;
+    Ok("Lot adjusted.".to_string())
+}
+
+/// The drug's stock ledger, newest first, with the running balance after each movement.
+#[tauri::command]
+async fn get_stock_movements(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, medication_id: i64) -> Result<Vec<StockMovement>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let movements = sqlx::query_as::<_, StockMovement>(
+        "SELECT sm.id, sm.lot_id, l.lot_number, sm.kind, sm.quantity, sm.reason_code, sm.reference_id, sm.username, sm.note, sm.witness, sm.created_at,
+                SUM(sm.quantity) OVER (ORDER BY sm.id) AS balance
+         FROM stock_movements sm JOIN medication_lots l ON l.id = sm.lot_id
+         WHERE l.medication_id = ?
+         ORDER BY sm.id DESC"
+    )
+    .bind(medication_id)
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(movements)
+}
+
+/// Lots whose on-hand quantity doesn't match the sum of their ledger rows.
+/// Empty when everything reconciles.
+#[tauri::command]
+async fn reconcile_stock(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<StockDiscrepancy>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let rows = sqlx::query_as::<_, StockDiscrepancy>(
+        "SELECT l.medication_id, m.name AS medication_name, l.id AS lot_id, l.lot_number,
+                l.quantity AS on_hand, COALESCE(SUM(sm.quantity), 0) AS ledger
+         FROM medication_lots l
+         JOIN medications m ON m.id = l.medication_id
+         LEFT JOIN stock_movements sm ON sm.lot_id = l.id
+         GROUP BY l.id
+         HAVING l.quantity != COALESCE(SUM(sm.quantity), 0)
+         ORDER BY m.name, l.lot_number"
+    )
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(rows)
+}
+
+/// Every fill dispensed from a lot, with patient contact details, for recalls.
+#[tauri::command]
+async fn get_lot_fills(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, lot_id: i64) -> Result<Vec<LotFillItem>, AppError> {
+    sessions.authorize(pool.inner(), Capability::RxRead).await?;
+
+    let fills = sqlx::query_as::<_, LotFillItem>(
+        "SELECT f.id AS fill_id, f.prescription_id, pt.id AS patient_id, pt.name AS patient_name, pt.phone,
+                fl.quantity, f.date_filled, f.voided_at
+         FROM fill_lots fl
+         JOIN fills f ON f.id = fl.fill_id
+         JOIN prescriptions p ON p.id = f.prescription_id
+         JOIN patients pt ON pt.id = p.patient_id
+         WHERE fl.lot_id = ?
+         ORDER BY f.date_filled DESC, f.id DESC"
+    )
+    .bind(lot_id)
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(fills)
+}
+
+#[tauri::command]
+async fn get_low_stock_items(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<LowStockItem>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let mut conn = pool.acquire().await?;
+    inventory::low_stock_items(&mut conn).await
+}
+
+/// Lots with stock left that are expired or expire within the warning window, soonest first.
+#[tauri::command]
+async fn get_expiring_stock(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<ExpiringLotItem>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let mut conn = pool.acquire().await?;
+    let window = settings::get_i64(&mut conn, settings::EXPIRY_WARNING_DAYS).await?;
+
+    let items = sqlx::query_as::<_, ExpiringLotItem>(
+        "SELECT l.id AS lot_id, l.medication_id, m.name AS medication_name, m.din, l.lot_number, l.expiration, l.quantity,
+                l.expiration < date('now') AS expired
+         FROM medication_lots l JOIN medications m ON m.id = l.medication_id
+         WHERE l.quantity > 0 AND l.expiration <= date('now', '+' || ? || ' days')
+         ORDER BY l.expiration, m.name"
+    )
+    .bind(window)
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(items)
+}
+
+/// Pulls every expired lot off the shelf into quarantine.
+#[tauri::command]
+async fn quarantine_expired_stock(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let lots: Vec<(i64, i64, String, String, String)> = sqlx::query_as(
+        "SELECT l.id, m.id, m.name, l.lot_number, l.expiration
+         FROM medication_lots l JOIN medications m ON m.id = l.medication_id
+         WHERE l.quantity > 0 AND l.expiration < date('now')"
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut total = 0;
+    for (lot_id, medication_id, name, lot_number, expiration) in &lots {
+        let moved = inventory::quarantine_lot(&mut tx, *lot_id, "Expired", &user.username).await?;
+        total += moved;
+        audit::record(&mut *tx, &user.username, Event::new("QUARANTINE_LOT",
+            format!("{} lot {} (exp {}): {} moved to quarantine | Reason: Expired", name, lot_number, expiration, moved))
+            .on(Entity::Medication, *medication_id)
+            .before(json!({ "lot_id": lot_id, "lot_number": lot_number, "quantity": moved }))
+            .after(json!({ "lot_id": lot_id, "lot_number": lot_number, "quantity": 0 }))
+        ).await?;
+    }
+
+    tx.commit().await?;
+    Ok(format!("Quarantined {} units from {} expired lot(s).", total, lots.len()))
+}
+
+#[tauri::command]
+async fn get_quarantined_stock(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<QuarantineItem>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let items = sqlx::query_as::<_, QuarantineItem>(
+        "SELECT q.id, q.lot_id, m.name AS medication_name, l.lot_number, l.expiration, q.quantity, q.reason,
+                q.quarantined_at, q.quarantined_by, q.disposition, q.resolved_at, q.resolved_by, q.witness
+         FROM quarantined_stock q
+         JOIN medication_lots l ON l.id = q.lot_id
+         JOIN medications m ON m.id = l.medication_id
+         ORDER BY q.disposition != 'pending', q.quarantined_at DESC"
+    )
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(items)
+}
+
+/// Records what happened to quarantined stock: sent back to the supplier or destroyed.
+#[tauri::command]
+async fn resolve_quarantine(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, quarantine_id: i64, disposition: String, witness: Option<WitnessDto>) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+    if disposition != "returned" && disposition != "destroyed" {
+        return Err(AppError::validation("disposition", "Disposition must be returned or destroyed"));
+    }
+    let witness = match &witness {
+        Some(w) => Some(controlled::verify_witness(pool.inner(), w, &user).await?),
+        None => None,
+    };
+
+    let mut tx = pool.begin().await?;
+
+    let (quantity, current, lot_number, medication_id): (i32, String, String, i64) = sqlx::query_as(
+        "SELECT q.quantity, q.disposition, l.lot_number, l.medication_id FROM quarantined_stock q JOIN medication_lots l ON l.id = q.lot_id WHERE q.id = ?"
+    )
+    .bind(quarantine_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::not_found("Quarantine entry"))?;
+
+    if current != "pending" {
+        return Err(AppError::Conflict(format!("Already marked {}", current)));
+    }
+
+    // Destroying a scheduled drug is witnessed
+    let scheduled = controlled::schedule_of(&mut tx, medication_id).await?.is_some();
+    if scheduled && disposition == "destroyed" && witness.is_none() {
+        return Err(AppError::validation("witness", "Destroying a scheduled drug needs a witness"));
+    }
+
+    sqlx::query("UPDATE quarantined_stock SET disposition = ?, resolved_at = CURRENT_TIMESTAMP, resolved_by = ?, witness = ? WHERE id = ?")
+        .bind(&disposition).bind(&user.username).bind(&witness).bind(quarantine_id)
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(&mut *tx, &user.username, Event::new("RESOLVE_QUARANTINE", format!(
+        "Lot {}: {} units {}{}", lot_number, quantity, disposition,
+        witness.as_deref().map(|w| format!(" | Witness: {}", w)).unwrap_or_default()
+    ))
+        .on(Entity::Medication, medication_id)
+        .before(json!({ "quarantine_id": quarantine_id, "lot_number": lot_number, "quantity": quantity, "disposition": current }))
+        .after(json!({ "quarantine_id": quarantine_id, "lot_number": lot_number, "quantity": quantity, "disposition": disposition, "witness": witness }))
+    ).await?;
+
+    tx.commit().await?;
+    Ok(format!("Marked as {}.", disposition))
+}
+
+// =====================================================
+// COMMANDS: INVENTORY COUNTS
+// =====================================================
+
+/// Shelf locations (from medication descriptions) that can be counted on their own.
+#[tauri::command]
+async fn get_count_locations(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<String>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let rows: Vec<(String,)> = sqlx::query_as(
+        "SELECT DISTINCT TRIM(description) FROM medications
+         WHERE description IS NOT NULL AND TRIM(description) != ''
+         ORDER BY 1"
+    )
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(rows.into_iter().map(|(loc,)| loc).collect())
+}
+
+/// Starts a count of the whole store, or of one shelf location.
+#[tauri::command]
+async fn start_count_session(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, location: Option<String>) -> Result<i64, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+    let location = location.as_deref().map(str::trim).filter(|l| !l.is_empty());
+
+    let mut tx = pool.begin().await?;
+    let (session_id, lines) = counts::start_session(&mut tx, location, &user.username).await?;
+    let after = audit::snapshot(&mut *tx, Entity::CountSession, session_id).await?;
+    audit::record(&mut *tx, &user.username, Event::new("START_COUNT", format!(
+        "Count #{} ({}): {} lots", session_id, location.unwrap_or("full store"), lines
+    ))
+        .on(Entity::CountSession, session_id)
+        .after(after)
+    ).await?;
+    tx.commit().await?;
+
+    Ok(session_id)
+}
+
+#[tauri::command]
+async fn get_count_sessions(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<CountSession>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let rows = sqlx::query_as::<_, CountSession>(
+        "SELECT s.id, s.location, s.status, s.started_at, s.started_by, s.posted_at, s.approved_by,
+                COUNT(c.id) AS line_count, COUNT(c.counted) AS counted_lines
+         FROM count_sessions s LEFT JOIN count_lines c ON c.session_id = s.id
+         GROUP BY s.id
+         ORDER BY s.id DESC"
+    )
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(rows)
+}
+
+/// The session's lines with expected vs counted quantities: the variance report.
+#[tauri::command]
+async fn get_count_lines(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, session_id: i64) -> Result<Vec<CountLineItem>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let lines = sqlx::query_as::<_, CountLineItem>(
+        "SELECT c.id, c.lot_id, m.id AS medication_id, m.name AS medication_name, m.din, m.schedule, m.description AS location,
+                l.lot_number, l.expiration, c.expected, c.counted,
+                c.counted - c.expected AS variance, (c.counted - c.expected) * m.price AS variance_value, c.counted_by
+         FROM count_lines c
+         JOIN medication_lots l ON l.id = c.lot_id
+         JOIN medications m ON m.id = l.medication_id
+         WHERE c.session_id = ?
+         ORDER BY m.description, m.name, l.expiration"
+    )
+    .bind(session_id)
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(lines)
+}
+
+#[tauri::command]
+async fn record_count(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, line_id: i64, counted: i32) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+    if counted < 0 {
+        return Err(AppError::validation("counted", "Counted quantity cannot be negative"));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let (session_id, lot_number, expected, previous, previous_by): (i64, String, i32, Option<i32>, Option<String>) = sqlx::query_as(
+        "SELECT c.session_id, l.lot_number, c.expected, c.counted, c.counted_by
+         FROM count_lines c
+         JOIN medication_lots l ON l.id = c.lot_id
+         JOIN count_sessions s ON s.id = c.session_id
+         WHERE c.id = ? AND s.status = 'open'"
+    )
+    .bind(line_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::NotFound("Count line not found or its session is closed".to_string()))?;
+
+    sqlx::query("UPDATE count_lines SET counted = ?, counted_by = ?, counted_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(counted).bind(&user.username).bind(line_id)
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(&mut *tx, &user.username, Event::new("RECORD_COUNT", format!(
+        "Count #{} lot {}: counted {} (expected {}){}", session_id, lot_number, counted, expected,
+        previous.map(|p| format!(" | Was {}", p)).unwrap_or_default()
+    ))
+        .on(Entity::CountSession, session_id)
+        .before(previous.map(|p| json!({ "line_id": line_id, "lot_number": lot_number, "counted": p, "counted_by": previous_by })))
+        .after(json!({ "line_id": line_id, "lot_number": lot_number, "counted": counted, "counted_by": user.username }))
+    ).await?;
+
+    tx.commit().await?;
+    Ok("Count saved.".to_string())
+}
+
+/// Pharmacist sign-off: posts every variance in the session to the stock ledger at once.
+/// A variance on a scheduled drug needs a witness.
+#[tauri::command]
+async fn post_count_session(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, session_id: i64, witness: Option<WitnessDto>) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryApprove).await?;
+    let witness = match &witness {
+        Some(w) => Some(controlled::verify_witness(pool.inner(), w, &user).await?),
+        None => None,
+    };
+
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut *tx, Entity::CountSession, session_id).await?;
+    let posted = counts::post_session(&mut tx, session_id, &user.username, witness.as_deref()).await?;
+    let after = audit::snapshot(&mut *tx, Entity::CountSession, session_id).await?;
+    audit::record(&mut *tx, &user.username, Event::new("POST_COUNT", format!(
+        "Count #{}: {} lot(s) adjusted, net {:+} units (${:.2}){}", session_id, posted.adjusted_lines, posted.net_units, posted.net_value,
+        witness.as_deref().map(|w| format!(" | Witness: {}", w)).unwrap_or_default()
+    ))
+        .on(Entity::CountSession, session_id)
+        .before(before)
+        .after(after)
+    ).await?;
+    tx.commit().await?;
+
+    Ok(format!("Count posted: {} lot(s) adjusted, net {:+} units.", posted.adjusted_lines, posted.net_units))
+}
+
+#[tauri::command]
+async fn cancel_count_session(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, session_id: i64) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut *tx, Entity::CountSession, session_id).await?;
+    let result = sqlx::query("UPDATE count_sessions SET status = 'cancelled' WHERE id = ? AND status = 'open'")
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("No open count session with that id".to_string()));
+    }
+
+    let after = audit::snapshot(&mut *tx, Entity::CountSession, session_id).await?;
+    audit::record(&mut *tx, &user.username, Event::new("CANCEL_COUNT", format!("Count #{} cancelled", session_id))
+        .on(Entity::CountSession, session_id)
+        .before(before)
+        .after(after)
+    ).await?;
+    tx.commit().await?;
+    Ok("Count cancelled.".to_string())
+}
+
+// =====================================================
+// COMMANDS: CONTROLLED SUBSTANCES
+// =====================================================
+
+#[tauri::command]
+async fn get_schedules(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<ScheduleOption>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    Ok(controlled::SCHEDULES.iter().map(|(code, label)| ScheduleOption { code, label }).collect())
+}
+
+/// A scheduled drug's perpetual register, newest first.
+#[tauri::command]
+async fn get_controlled_register(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, medication_id: i64) -> Result<Vec<RegisterEntry>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let entries = sqlx::query_as::<_, RegisterEntry>(
+        "SELECT id, movement_id, kind, quantity, balance, lot_number, reason_code, reference_id, recorded_by, witness, created_at
+         FROM controlled_register WHERE medication_id = ?
+         ORDER BY id DESC"
+    )
+    .bind(medication_id)
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(entries)
+}
+
+/// Every scheduled drug's register balance against its stock, discrepancies first.
+#[tauri::command]
+async fn get_controlled_reconciliation(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<RegisterReconciliation>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let rows = sqlx::query_as::<_, RegisterReconciliation>(
+        "SELECT m.id AS medication_id, m.name, m.din, m.schedule, m.stock,
+                r.balance AS register_balance, r.created_at AS last_entry_at,
+                COALESCE(r.balance, 0) != m.stock AS discrepancy
+         FROM medications m
+         LEFT JOIN controlled_register r ON r.id = (SELECT MAX(id) FROM controlled_register WHERE medication_id = m.id)
+         WHERE m.schedule IS NOT NULL
+         ORDER BY discrepancy DESC, m.name"
+    )
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(rows)
+}
+
+// =====================================================
+// COMMANDS: PURCHASING
+// =====================================================
+
+#[tauri::command]
+async fn get_suppliers(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<Supplier>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let suppliers = sqlx::query_as::<_, Supplier>("SELECT id, name, contact, phone, email, active FROM suppliers ORDER BY name")
+        .fetch_all(pool.inner())
+        .await?;
+    Ok(suppliers)
+}
+
+#[tauri::command]
+async fn add_supplier(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, data: CreateSupplierDto) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+    validation::validate_supplier(&data)?;
+
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query("INSERT INTO suppliers (name, contact, phone, email) VALUES (?, ?, ?, ?)")
+        .bind(data.name.trim()).bind(&data.contact).bind(&data.phone).bind(&data.email)
+        .execute(&mut *tx)
+        .await;
+
+    let id = match result {
+        Ok(r) => r.last_insert_rowid(),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Err(AppError::Conflict(format!("Supplier {} already exists", data.name.trim())));
+        },
+        Err(e) => return Err(e.into()),
+    };
+
+    let after = audit::snapshot(&mut *tx, Entity::Supplier, id).await?;
+    audit::record(&mut *tx, &user.username, Event::new("ADD_SUPPLIER", format!("Added supplier: {}", data.name.trim()))
+        .on(Entity::Supplier, id)
+        .after(after)
+    ).await?;
+
+    tx.commit().await?;
+    Ok("Supplier added.".to_string())
+}
+
+#[tauri::command]
+async fn get_purchase_orders(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<PurchaseOrderSummary>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let orders = sqlx::query_as::<_, PurchaseOrderSummary>(
+        "SELECT po.id, po.supplier_id, s.name AS supplier_name, po.status, po.notes, po.created_at, po.created_by, po.ordered_at,
+                COUNT(l.id) AS line_count,
+                COALESCE(SUM(l.quantity_ordered), 0) AS units_ordered,
+                COALESCE(SUM(l.quantity_received), 0) AS units_received
+         FROM purchase_orders po
+         JOIN suppliers s ON s.id = po.supplier_id
+         LEFT JOIN purchase_order_lines l ON l.purchase_order_id = po.id
+         GROUP BY po.id
+         ORDER BY po.id DESC"
+    )
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(orders)
+}
+
+#[tauri::command]
+async fn get_purchase_order_lines(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, purchase_order_id: i64) -> Result<Vec<PurchaseOrderLine>, AppError> {
+    sessions.authorize(pool.inner(), Capability::InventoryRead).await?;
+
+    let lines = sqlx::query_as::<_, PurchaseOrderLine>(
+        "SELECT l.id, l.purchase_order_id, l.medication_id, m.name AS medication_name, m.din,
+                l.quantity_ordered, l.quantity_received, l.unit_cost
+         FROM purchase_order_lines l JOIN medications m ON m.id = l.medication_id
+         WHERE l.purchase_order_id = ?
+         ORDER BY m.name"
+    )
+    .bind(purchase_order_id)
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(lines)
+}
+
+#[tauri::command]
+async fn create_purchase_order(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, data: CreatePurchaseOrderDto) -> Result<i64, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+    validation::validate_purchase_order(&data)?;
+
+    let mut tx = pool.begin().await?;
+    let po_id = purchasing::create_order(&mut tx, data.supplier_id, data.notes.as_deref(), &data.lines, &user.username).await?;
+    let after = audit::snapshot(&mut *tx, Entity::PurchaseOrder, po_id).await?;
+    audit::record(&mut *tx, &user.username, Event::new("CREATE_PO",
+        format!("PO #{} for supplier ID {} ({} lines)", po_id, data.supplier_id, data.lines.len()))
+        .on(Entity::PurchaseOrder, po_id)
+        .after(after)
+    ).await?;
+    tx.commit().await?;
+
+    Ok(po_id)
+}
+
+/// Drafts a PO for everything at or below its reorder point, using the suggested quantities.
+#[tauri::command]
+async fn create_reorder_purchase_order(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, supplier_id: i64) -> Result<i64, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let lines: Vec<PoLineDto> = inventory::low_stock_items(&mut tx)
+        .await?
+        .into_iter()
+        .filter(|item| item.suggested_qty > 0)
+        .map(|item| PoLineDto { medication_id: item.medication_id, quantity: item.suggested_qty, unit_cost: None })
+        .collect();
+    if lines.is_empty() {
+        return Err(AppError::Conflict("Nothing is at or below its reorder point".to_string()));
+    }
+
+    let po_id = purchasing::create_order(&mut tx, supplier_id, Some("Generated from reorder list"), &lines, &user.username).await?;
+    let after = audit::snapshot(&mut *tx, Entity::PurchaseOrder, po_id).await?;
+    audit::record(&mut *tx, &user.username, Event::new("CREATE_PO",
+        format!("PO #{} for supplier ID {} from reorder list ({} lines)", po_id, supplier_id, lines.len()))
+        .on(Entity::PurchaseOrder, po_id)
+        .after(after)
+    ).await?;
+    tx.commit().await?;
+
+    Ok(po_id)
+}
+
+/// Moves a PO from `from` to `to`, or errors if it isn't currently in `from`.
+async fn change_po_status(pool: &SqlitePool, username: &str, purchase_order_id: i64, from: &[&str], to: &str, action: &str) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+
+    let before = audit::snapshot(&mut *tx, Entity::PurchaseOrder, purchase_order_id).await?;
+    let (status,): (String,) = sqlx::query_as("SELECT status FROM purchase_orders WHERE id = ?")
+        .bind(purchase_order_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::not_found("Purchase order"))?;
+    if !from.contains(&status.as_str()) {
+        return Err(AppError::Conflict(format!("Purchase order is {}", status.replace('_', " "))));
+    }
+
+    sqlx::query(
+        "UPDATE purchase_orders SET status = ?,
+            ordered_at = CASE WHEN ? = 'ordered' THEN CURRENT_TIMESTAMP ELSE ordered_at END,
+            ordered_by = CASE WHEN ? = 'ordered' THEN ? ELSE ordered_by END
+         WHERE id = ?"
+    )
+    .bind(to).bind(to).bind(to).bind(username).bind(purchase_order_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let after = audit::snapshot(&mut *tx, Entity::PurchaseOrder, purchase_order_id).await?;
+    audit::record(&mut *tx, username, Event::new(action, format!("PO #{}: {} -> {}", purchase_order_id, status, to))
+        .on(Entity::PurchaseOrder, purchase_order_id)
+        .before(before)
+        .after(after)
+    ).await?;
+    tx.commit().await?;
+    Ok(())
 }
 
 #[tauri::command]
-async fn get_patients(pool: State<'_, SqlitePool>, search: Option<String>) -> Result<Vec<Patient>, String> {
-    let query = match search {
-        Some(s) if !s.is_empty() => {
-            let pattern = format!("%{}%", s);
-            format!(
-                "SELECT DISTINCT p.* FROM patients p
-                 LEFT JOIN prescriptions rx ON p.id = rx.patient_id
-                 LEFT JOIN medications m ON rx.medication_id = m.id
-                 WHERE p.name LIKE '{}' OR m.name LIKE '{}'
-                 ORDER BY p.id DESC", 
-                 pattern, pattern
-            )
-        },
-        _ => "SELECT * FROM patients ORDER BY id DESC".to_string(),
-    };
+async fn submit_purchase_order(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, purchase_order_id: i64) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+    change_po_status(pool.inner(), &user.username, purchase_order_id, &["draft"], "ordered", "SUBMIT_PO").await?;
+    Ok("Purchase order marked as ordered.".to_string())
+}
 
-    let patients = sqlx::query_as::<_, Patient>(&query)
-        .fetch_all(pool.inner())
-        .await
-        .map_err(|e| e.to_string())?;
-    Ok(patients)
+/// Only orders with nothing received yet can be cancelled.
+#[tauri::command]
+async fn cancel_purchase_order(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, purchase_order_id: i64) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+    change_po_status(pool.inner(), &user.username, purchase_order_id, &["draft", "ordered"], "cancelled", "CANCEL_PO").await?;
+    Ok("Purchase order cancelled.".to_string())
 }
 
 #[tauri::command]
-async fn get_patient_history(pool: State<'_, SqlitePool>, patient_id: i64) -> Result<Vec<PatientHistoryItem>, String> {
-    let history = sqlx::query_as::<_, PatientHistoryItem>(
-        "SELECT p.id, m.name as drug_name, p.sig, p.quantity, p.date_filled, p.next_refill_date
-         FROM prescriptions p
-         JOIN medications m ON p.medication_id = m.id
-         WHERE p.patient_id = ? ORDER BY p.date_filled DESC"
-    )
-    .bind(patient_id)
-    .fetch_all(pool.inner())
-    .await
-    .map_err(|e| e.to_string())?;
-    Ok(history)
+async fn receive_purchase_order_line(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, data: ReceivePoLineDto) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InventoryAdjust).await?;
+    validation::validate_po_receipt(&data)?;
+
+    let mut tx = pool.begin().await?;
+    let receipt = purchasing::receive_line(&mut tx, &data, &user.username).await?;
+    audit::record(&mut *tx, &user.username, Event::new("RECEIVE_PO", format!(
+        "PO #{}: received {} {} lot {} (exp {}) at ${:.2} each; {} outstanding",
+        receipt.purchase_order_id, data.quantity, receipt.medication_name, data.lot_number.trim(), data.expiration, data.unit_cost, receipt.outstanding
+    ))
+        .on(Entity::PurchaseOrder, receipt.purchase_order_id)
+        .after(json!({
+            "medication": receipt.medication_name, "lot_number": data.lot_number.trim(), "expiration": data.expiration,
+            "quantity": data.quantity, "unit_cost": data.unit_cost, "outstanding": receipt.outstanding, "status": receipt.po_status,
+        }))
+    ).await?;
+    tx.commit().await?;
+
+    Ok(format!("Received {} {}; order is {}.", data.quantity, receipt.medication_name, receipt.po_status.replace('_', " ")))
 }
 
 // =====================================================
-// COMMANDS: INVENTORY
+// COMMANDS: PRESCRIPTIONS
 // =====================================================
 
 #[tauri::command]
-async fn add_medication(pool: State<'_, SqlitePool>, data: CreateMedicationDto) -> Result<String, String> {
-    let result = sqlx::query(
-        "INSERT INTO medications (name, din, ndc, description, stock, price, expiration) 
-         VALUES (?, ?, ?, ?, ?, ?, ?)"
+async fn create_prescription(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, data: CreatePrescriptionDto) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::RxFill).await?;
+    validation::validate_prescription(&data)?;
+
+    let mut tx = pool.begin().await?;
+
+    controlled::check_prescription(&mut tx, data.medication_id, data.refills).await?;
+
+    let fill_date = validation::parse_date(&data.date_filled).unwrap_or_else(validation::today);
+    let early_fill = dispense::check_too_soon(&mut tx, data.patient_id, data.medication_id, fill_date, data.early_fill_code.as_deref()).await?;
+
+    // Clinical screening: blocking alerts need an override reason, hard stops a pharmacist's
+    let alerts = clinical::screen(&mut tx, data.patient_id, data.medication_id).await?;
+    clinical::enforce(&alerts, data.override_reason.as_deref(), user.can(Capability::RxVerify))?;
+
+    // Insert Rx; next_refill_date is set by the original fill below
+    let status = if data.refills == 0 { RxStatus::Completed } else { RxStatus::Active };
+    let prescription_id = sqlx::query(
+        "INSERT INTO prescriptions (
+            patient_id, medication_id, prescriber, sig, quantity, refills, refills_remaining, days_supply,
+            date_filled, next_refill_date, expires_on, status
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, date(?, '+' || ? || ' days'), ?)"
     )
-    .bind(&data.name).bind(&data.din).bind(&data.ndc).bind(&data.description)
-    .bind(data.stock).bind(data.price).bind(&data.expiration)
-    .execute(pool.inner())
-    .await;
+    .bind(data.patient_id).bind(data.medication_id).bind(&data.prescriber).bind(&data.sig)
+    .bind(data.quantity).bind(data.refills).bind(data.refills).bind(data.days_supply)
+    .bind(&data.date_filled).bind(&data.date_filled).bind(&data.date_filled).bind(dispense::RX_VALID_DAYS)
+    .bind(status.as_str())
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
 
-    match result {
-        Ok(_) => {
-            let _ = sqlx::query("INSERT INTO audit_logs (username, action, details) VALUES (?, ?, ?)")
-                .bind(&data.logged_in_user)
-                .bind("ADD_INVENTORY")
-                .bind(format!("Added drug: {} (Stock: {})", data.name, data.stock))
-                .execute(pool.inner())
-                .await;
-            Ok("Medication added.".to_string())
-        },
-        Err(e) => Err(format!("Failed to save: {}", e))
+    dispense::record_fill(&mut tx, &dispense::NewFill {
+        prescription_id,
+        medication_id: data.medication_id,
+        fill_number: 0,
+        quantity: data.quantity,
+        days_supply: data.days_supply,
+        date_filled: &data.date_filled,
+        filled_by: &user.username,
+        early_fill_code: early_fill.as_ref().and(data.early_fill_code.as_deref()),
+    }).await?;
+
+    if let Some(details) = &early_fill {
+        audit::record(&mut *tx, &user.username, Event::new("EARLY_FILL_OVERRIDE", format!(
+            "Patient ID {} (Med ID {}): {}", data.patient_id, data.medication_id, details
+        )).on(Entity::Prescription, prescription_id)).await?;
     }
+    clinical::record_overrides(&mut tx, &user.username, data.patient_id, data.medication_id, &alerts, data.override_reason.as_deref()).await?;
+
+    let after = audit::snapshot(&mut *tx, Entity::Prescription, prescription_id).await?;
+    audit::record(&mut *tx, &user.username, Event::new("FILL_RX",
+        format!("Filled Rx ID {} for Patient ID: {} (Med ID: {}, Qty: {})", prescription_id, data.patient_id, data.medication_id, data.quantity))
+        .on(Entity::Prescription, prescription_id)
+        .after(after)
+    ).await?;
+
+    tx.commit().await?;
+
+    Ok("Filled & Updated.".to_string())
 }
 
+/// Dispenses the next refill of an existing prescription, consuming one of
+/// its remaining refills. Runs the same clinical screening as a new fill.
 #[tauri::command]
-async fn update_medication(pool: State<'_, SqlitePool>, data: UpdateMedicationDto) -> Result<String, String> {
-    let old_stock: (i32,) = sqlx::query_as("SELECT stock FROM medications WHERE id = ?")
-        .bind(data.id)
-        .fetch_one(pool.inner())
-        .await
-        .map_err(|_| "Drug not found".to_string())?;
+async fn refill_prescription(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, prescription_id: i64, override_reason: Option<String>, early_fill_code: Option<String>) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::RxFill).await?;
 
-    let result = sqlx::query(
-        "UPDATE medications SET stock = ?, price = ?, description = ? WHERE id = ?"
-    )
-    .bind(data.stock)
-    .bind(data.price)
-    .bind(&data.description)
-    .bind(data.id)
-    .execute(pool.inner())
-    .await;
+    let mut tx = pool.begin().await?;
 
-    match result {
-        Ok(_) => {
-            let _ = sqlx::query("INSERT INTO audit_logs (username, action, details) VALUES (?, ?, ?)")
-                .bind(&data.logged_in_user)
-                .bind("UPDATE_INVENTORY")
-                .bind(format!("Updated Med ID {}: Stock {} -> {}, Price ${}", data.id, old_stock.0, data.stock, data.price))
-                .execute(pool.inner())
-                .await;
-            Ok("Inventory updated successfully.".to_string())
-        },
-        Err(e) => Err(format!("Failed to update: {}", e))
+    let rx = dispense::refill_target(&mut tx, prescription_id).await?;
+
+    let before = audit::snapshot(&mut *tx, Entity::Prescription, prescription_id).await?;
+    let early_fill = dispense::check_too_soon(&mut tx, rx.patient_id, rx.medication_id, validation::today(), early_fill_code.as_deref()).await?;
+
+    let alerts = clinical::screen(&mut tx, rx.patient_id, rx.medication_id).await?;
+    clinical::enforce(&alerts, override_reason.as_deref(), user.can(Capability::RxVerify))?;
+
+    let remaining = dispense::record_refill(&mut tx, &rx, &user.username, early_fill.as_ref().and(early_fill_code.as_deref())).await?;
+
+    if let Some(details) = &early_fill {
+        audit::record(&mut *tx, &user.username, Event::new("EARLY_FILL_OVERRIDE", format!(
+            "Rx ID {} (Patient ID {}, Med ID {}): {}", prescription_id, rx.patient_id, rx.medication_id, details
+        )).on(Entity::Prescription, prescription_id)).await?;
     }
+    clinical::record_overrides(&mut tx, &user.username, rx.patient_id, rx.medication_id, &alerts, override_reason.as_deref()).await?;
+
+    let after = audit::snapshot(&mut *tx, Entity::Prescription, prescription_id).await?;
+    audit::record(&mut *tx, &user.username, Event::new("REFILL_RX", format!(
+        "Refill #{} of Rx ID {} (Qty: {}); {} refill(s) remaining", rx.next_fill_number, prescription_id, rx.quantity, remaining
+    ))
+        .on(Entity::Prescription, prescription_id)
+        .before(before)
+        .after(after)
+    ).await?;
+
+    tx.commit().await?;
+    Ok(format!("Refill processed. {} refill(s) remaining.", remaining))
 }
 
 #[tauri::command]
-async fn get_medications(pool: State<'_, SqlitePool>) -> Result<Vec<Medication>, String> {
-    let meds = sqlx::query_as::<_, Medication>("SELECT * FROM medications ORDER BY name ASC")
-        .fetch_all(pool.inner())
-        .await
-        .map_err(|e| e.to_string())?;
-    Ok(meds)
+async fn get_early_fill_reasons(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<EarlyFillReason>, AppError> {
+    sessions.authorize(pool.inner(), Capability::RxFill).await?;
+    Ok(dispense::EARLY_FILL_REASONS.iter().map(|&(code, label)| EarlyFillReason { code, label }).collect())
 }
 
-// =====================================================
-// COMMANDS: PRESCRIPTIONS
-// =====================================================
-
+/// Reverses a mistaken fill (wrong patient, wrong quantity): stock and the
+/// refill go back, and the fill stays on record as voided. Returning a
+/// scheduled drug to stock needs a witness.
 #[tauri::command]
-async fn create_prescription(pool: State<'_, SqlitePool>, data: CreatePrescriptionDto) -> Result<String, String> {
-    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+async fn void_fill(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, fill_id: i64, reason: String, witness: Option<WitnessDto>) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::RxVerify).await?;
+    if reason.trim().is_empty() {
+        return Err(AppError::validation("reason", "A reason is required to void a fill"));
+    }
+    let witness = match &witness {
+        Some(w) => Some(controlled::verify_witness(pool.inner(), w, &user).await?),
+        None => None,
+    };
 
-    // Check Stock
-    let med_stock: (i32,) = sqlx::query_as("SELECT stock FROM medications WHERE id = ?")
-        .bind(data.medication_id)
-        .fetch_one(&mut *tx)
-        .await
-        .map_err(|_| "Medication not found".to_string())?;
+    let mut tx = pool.begin().await?;
+
+    let fill = dispense::void_fill(&mut tx, fill_id, reason.trim(), &user.username, witness.as_deref()).await?;
+
+    audit::record(&mut *tx, &user.username, Event::new("VOID_FILL", format!(
+        "Voided fill #{} of Rx ID {} | Original: Patient ID {}, Med ID {}, Qty {}, filled {} by {} | Reason: {}{}",
+        fill.fill_number, fill.prescription_id, fill.patient_id, fill.medication_id, fill.quantity,
+        fill.date_filled, fill.filled_by.as_deref().unwrap_or("unknown"), reason.trim(),
+        witness.as_deref().map(|w| format!(" | Witness: {}", w)).unwrap_or_default()
+    ))
+        .on(Entity::Prescription, fill.prescription_id)
+        .before(json!({
+            "fill_id": fill_id, "fill_number": fill.fill_number, "quantity": fill.quantity,
+            "date_filled": fill.date_filled, "filled_by": fill.filled_by,
+        }))
+        .after(json!({ "fill_id": fill_id, "voided_by": user.username, "void_reason": reason.trim(), "witness": witness }))
+    ).await?;
+
+    tx.commit().await?;
+    Ok(format!("Fill voided. {} returned to stock.", fill.quantity))
+}
+
+/// Moves a prescription to `next` if the lifecycle allows it, recording who,
+/// when and why. The audit entry commits with the change.
+async fn change_rx_status(pool: &SqlitePool, username: &str, prescription_id: i64, next: RxStatus, reason: Option<&str>) -> Result<String, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let before = audit::snapshot(&mut *tx, Entity::Prescription, prescription_id).await?;
+    let current: (String,) = sqlx::query_as("SELECT status FROM prescriptions WHERE id = ?")
+        .bind(prescription_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::not_found("Prescription"))?;
+    let current = RxStatus::parse(&current.0).unwrap_or(RxStatus::Active);
 
-    if med_stock.0 < data.quantity {
-        return Err(format!("Insufficient stock! Current: {}, Requested: {}", med_stock.0, data.quantity));
+    if !current.can_become(next) {
+        return Err(AppError::Conflict(format!(
+            "A prescription that is {} cannot be marked {}", current.label(), next.label()
+        )));
     }
 
-    // Insert Rx
-    let insert_result = sqlx::query(
-        "INSERT INTO prescriptions (
-            patient_id, medication_id, prescriber, sig, quantity, refills, days_supply, date_filled, next_refill_date
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, date(?, '+' || ? || ' days'))"
+    let reason = reason.map(str::trim).filter(|r| !r.is_empty());
+    sqlx::query(
+        "UPDATE prescriptions SET status = ?, status_reason = ?, status_changed_at = CURRENT_TIMESTAMP, status_changed_by = ?
+         WHERE id = ?"
     )
-    .bind(data.patient_id).bind(data.medication_id).bind(&data.prescriber).bind(&data.sig)
-    .bind(data.quantity).bind(data.refills).bind(data.days_supply).bind(&data.date_filled)
-    .bind(&data.date_filled).bind(data.days_supply)
+    .bind(next.as_str()).bind(reason).bind(username).bind(prescription_id)
     .execute(&mut *tx)
-    .await;
+    .await?;
+
+    let after = audit::snapshot(&mut *tx, Entity::Prescription, prescription_id).await?;
+    audit::record(&mut *tx, username, Event::new(next.audit_action(), format!(
+        "Rx ID {}: {} -> {}{}",
+        prescription_id, current.as_str(), next.as_str(),
+        reason.map(|r| format!(" | Reason: {}", r)).unwrap_or_default()
+    ))
+        .on(Entity::Prescription, prescription_id)
+        .before(before)
+        .after(after)
+    ).await?;
+
+    tx.commit().await?;
+    Ok(format!("Prescription is now {}.", next.label()))
+}
+
+#[tauri::command]
+async fn hold_prescription(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, prescription_id: i64, reason: Option<String>) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::RxFill).await?;
+    change_rx_status(pool.inner(), &user.username, prescription_id, RxStatus::OnHold, reason.as_deref()).await
+}
+
+#[tauri::command]
+async fn resume_prescription(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, prescription_id: i64) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::RxFill).await?;
+    change_rx_status(pool.inner(), &user.username, prescription_id, RxStatus::Active, None).await
+}
+
+#[tauri::command]
+async fn cancel_prescription(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, prescription_id: i64, reason: String) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::RxVerify).await?;
+    if reason.trim().is_empty() {
+        return Err(AppError::validation("reason", "A reason is required to cancel a prescription"));
+    }
+    change_rx_status(pool.inner(), &user.username, prescription_id, RxStatus::Cancelled, Some(&reason)).await
+}
 
-    if let Err(e) = insert_result { return Err(format!("Rx Create Failed: {}", e)); }
+/// `pharmacy` is the receiving pharmacy, kept as the status reason.
+#[tauri::command]
+async fn transfer_prescription(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, prescription_id: i64, pharmacy: String) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::RxVerify).await?;
+    if pharmacy.trim().is_empty() {
+        return Err(AppError::validation("pharmacy", "Enter the pharmacy the prescription is transferred to"));
+    }
+    change_rx_status(pool.inner(), &user.username, prescription_id, RxStatus::TransferredOut, Some(&format!("Transferred to {}", pharmacy.trim()))).await
+}
+
+/// Runs the fill-time clinical checks without filling, so the form can show
+/// alerts as soon as a patient and drug are picked.
+#[tauri::command]
+async fn screen_prescription(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, patient_id: i64, medication_id: i64) -> Result<Vec<clinical::ClinicalAlert>, AppError> {
+    sessions.authorize(pool.inner(), Capability::RxRead).await?;
+
+    let mut conn = pool.acquire().await?;
+    let alerts = clinical::screen(&mut conn, patient_id, medication_id).await?;
+    Ok(alerts)
+}
+
+#[tauri::command]
+async fn get_interactions(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<InteractionRecord>, AppError> {
+    sessions.authorize(pool.inner(), Capability::RxRead).await?;
+
+    let records = sqlx::query_as::<_, InteractionRecord>(
+        "SELECT term_a, term_b, severity, monograph FROM drug_interactions ORDER BY term_a, term_b"
+    )
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(records)
+}
+
+/// Loads interaction pairs from an exported file. Existing pairs are updated
+/// in place; pairs not in the file are kept.
+#[tauri::command]
+async fn import_interactions(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, records: Vec<InteractionRecord>) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::InteractionsManage).await?;
+    validation::validate_interactions(&records)?;
 
-    // Deduct Stock
-    let update_stock = sqlx::query("UPDATE medications SET stock = stock - ? WHERE id = ?")
-        .bind(data.quantity).bind(data.medication_id)
+    let mut tx = pool.begin().await?;
+    for r in &records {
+        let a = r.term_a.trim().to_lowercase();
+        let b = r.term_b.trim().to_lowercase();
+        let (term_a, term_b) = if a <= b { (a, b) } else { (b, a) };
+        sqlx::query(
+            "INSERT INTO drug_interactions (term_a, term_b, severity, monograph) VALUES (?, ?, ?, ?)
+             ON CONFLICT(term_a, term_b) DO UPDATE SET severity = excluded.severity, monograph = excluded.monograph"
+        )
+        .bind(&term_a).bind(&term_b).bind(&r.severity).bind(r.monograph.trim())
         .execute(&mut *tx)
-        .await;
+        .await?;
+    }
+    audit::record(&mut *tx, &user.username, Event::new("IMPORT_INTERACTIONS", format!("Imported {} interaction(s)", records.len()))).await?;
+    tx.commit().await?;
 
-    if let Err(e) = update_stock { return Err(format!("Stock Update Failed: {}", e)); }
+    Ok(format!("Imported {} interaction(s).", records.len()))
+}
 
-    tx.commit().await.map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn get_pending_verifications(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<PendingVerificationItem>, AppError> {
+    sessions.authorize(pool.inner(), Capability::RxVerify).await?;
 
-    // LOG ACTION (Outside transaction, best effort)
-    let _ = sqlx::query("INSERT INTO audit_logs (username, action, details) VALUES (?, ?, ?)")
-        .bind(&data.logged_in_user)
-        .bind("FILL_RX")
-        .bind(format!("Filled Rx for Patient ID: {} (Med ID: {}, Qty: {})", data.patient_id, data.medication_id, data.quantity))
-        .execute(pool.inner())
-        .await;
+    let items = sqlx::query_as::<_, PendingVerificationItem>(
+        "SELECT f.id, f.prescription_id, f.fill_number, pat.name as patient_name, m.name as medication_name,
+            p.sig, f.quantity, f.date_filled, f.filled_by
+         FROM fills f
+         JOIN prescriptions p ON f.prescription_id = p.id
+         JOIN patients pat ON p.patient_id = pat.id
+         JOIN medications m ON p.medication_id = m.id
+         WHERE f.verified_at IS NULL AND f.voided_at IS NULL
+         ORDER BY f.id ASC"
+    )
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(items)
+}
 
-    Ok("Filled & Updated.".to_string())
+/// Pharmacist sign-off on a single fill (original or refill).
+#[tauri::command]
+async fn verify_fill(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, fill_id: i64) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::RxVerify).await?;
+
+    let mut tx = pool.begin().await?;
+
+    // Only an unverified, unvoided fill can be claimed, so two pharmacists
+    // verifying at once can't both succeed
+    let claimed: Option<(i64, i64)> = sqlx::query_as(
+        "UPDATE fills SET verified_by = ?, verified_at = CURRENT_TIMESTAMP
+         WHERE id = ? AND verified_by IS NULL AND voided_at IS NULL
+         RETURNING prescription_id, fill_number"
+    )
+    .bind(&user.username)
+    .bind(fill_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some((prescription_id, fill_number)) = claimed else {
+        let fill: (Option<String>, bool) = sqlx::query_as("SELECT verified_by, voided_at IS NOT NULL FROM fills WHERE id = ?")
+            .bind(fill_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(AppError::not_found("Fill"))?;
+        return Err(AppError::Conflict(match fill {
+            (_, true) => "Fill has been voided".to_string(),
+            (by, false) => format!("Fill already verified by {}", by.unwrap_or_default()),
+        }));
+    };
+
+    audit::record(&mut *tx, &user.username, Event::new("VERIFY_RX", format!("Verified fill #{} of Rx ID: {}", fill_number, prescription_id))
+        .on(Entity::Prescription, prescription_id)
+        .after(json!({ "fill_id": fill_id, "fill_number": fill_number, "verified_by": user.username }))
+    ).await?;
+
+    tx.commit().await?;
+    Ok("Fill verified.".to_string())
 }
 
 // =====================================================
 // COMMANDS: DASHBOARD
 // =====================================================
 
+/// Prescriptions that can still be refilled; only these show up as due.
+const REFILLABLE: &str = "p.status = 'active' AND p.refills_remaining > 0 AND (p.expires_on IS NULL OR p.expires_on >= date('now'))";
+
 #[tauri::command]
-async fn get_dashboard_stats(pool: State<'_, SqlitePool>) -> Result<DashboardStats, String> {
-    let due_today: (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM prescriptions p1
-         WHERE next_refill_date <= date('now')
-         AND NOT EXISTS (
-            SELECT 1 FROM prescriptions p2 
-            WHERE p2.patient_id = p1.patient_id 
-            AND p2.medication_id = p1.medication_id 
-            AND p2.id > p1.id
-         )"
-    )
-    .fetch_one(pool.inner()).await.unwrap_or((0,));
+async fn get_dashboard_stats(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<DashboardStats, AppError> {
+    sessions.authorize(pool.inner(), Capability::RxRead).await?;
 
-    let due_soon: (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM prescriptions p1
-         WHERE next_refill_date > date('now') 
-         AND next_refill_date <= date('now', '+7 days')
-         AND NOT EXISTS (
-            SELECT 1 FROM prescriptions p2 
-            WHERE p2.patient_id = p1.patient_id 
-            AND p2.medication_id = p1.medication_id 
-            AND p2.id > p1.id
-         )"
-    )
-    .fetch_one(pool.inner()).await.unwrap_or((0,));
+    let due_today: (i64,) = sqlx::query_as(&format!(
+        "SELECT COUNT(*) FROM prescriptions p
+         WHERE p.next_refill_date <= date('now') AND {}", REFILLABLE
+    ))
+    .fetch_one(pool.inner()).await?;
+
+    let due_soon: (i64,) = sqlx::query_as(&format!(
+        "SELECT COUNT(*) FROM prescriptions p
+         WHERE p.next_refill_date > date('now') 
+         AND p.next_refill_date <= date('now', '+7 days') AND {}", REFILLABLE
+    ))
+    .fetch_one(pool.inner()).await?;
+
+    let mut conn = pool.acquire().await?;
+    let low_stock = inventory::low_stock_items(&mut conn).await?.len() as i64;
 
-    let low_stock: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM medications WHERE stock < 100")
-        .fetch_one(pool.inner()).await.unwrap_or((0,));
+    let window = settings::get_i64(&mut conn, settings::EXPIRY_WARNING_DAYS).await?;
+    let expiring: (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM medication_lots WHERE quantity > 0 AND expiration <= date('now', '+' || ? || ' days')"
+    )
+    .bind(window)
+    .fetch_one(&mut *conn).await?;
 
-    Ok(DashboardStats { due_today: due_today.0, due_soon: due_soon.0, low_stock: low_stock.0 })
+    Ok(DashboardStats { due_today: due_today.0, due_soon: due_soon.0, low_stock, expiring: expiring.0 })
 }
 
 #[tauri::command]
-async fn get_due_prescriptions(pool: State<'_, SqlitePool>, filter: String) -> Result<Vec<DueRxItem>, String> {
+async fn get_due_prescriptions(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, filter: String) -> Result<Vec<DueRxItem>, AppError> {
+    sessions.authorize(pool.inner(), Capability::RxRead).await?;
+
     let date_query = if filter == "today" { "<= date('now')" } else { "> date('now') AND next_refill_date <= date('now', '+7 days')" };
     
     let sql = format!(
         "SELECT p.id, pat.name as patient_name, m.name as medication_name, p.next_refill_date, pat.phone,
-            p.patient_id, p.medication_id, p.quantity, p.sig, p.days_supply, p.refills, p.prescriber
+            p.patient_id, p.medication_id, p.quantity, p.sig, p.days_supply, p.refills, p.refills_remaining, p.expires_on, p.prescriber
          FROM prescriptions p
          JOIN patients pat ON p.patient_id = pat.id
          JOIN medications m ON p.medication_id = m.id
-         WHERE p.next_refill_date {}
-         AND NOT EXISTS (
-            SELECT 1 FROM prescriptions p2 
-            WHERE p2.patient_id = p.patient_id 
-            AND p2.medication_id = p.medication_id 
-            AND p2.id > p.id
-         )
-         ORDER BY p.next_refill_date ASC", date_query
+         WHERE p.next_refill_date {} AND {}
+         ORDER BY p.next_refill_date ASC", date_query, REFILLABLE
     );
 
     let items = sqlx::query_as::<_, DueRxItem>(&sql)
         .fetch_all(pool.inner())
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
     Ok(items)
 }
 
 // --- NEW COMMAND: GET UPCOMING REFILLS ---
 #[tauri::command]
-async fn get_upcoming_refills(pool: State<'_, SqlitePool>) -> Result<Vec<DueRxItem>, String> {
-    let sql = "
+async fn get_upcoming_refills(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<DueRxItem>, AppError> {
+    sessions.authorize(pool.inner(), Capability::RxRead).await?;
+
+    let sql = format!("
         SELECT p.id, pat.name as patient_name, m.name as medication_name, p.next_refill_date, pat.phone,
-            p.patient_id, p.medication_id, p.quantity, p.sig, p.days_supply, p.refills, p.prescriber
+            p.patient_id, p.medication_id, p.quantity, p.sig, p.days_supply, p.refills, p.refills_remaining, p.expires_on, p.prescriber
          FROM prescriptions p
          JOIN patients pat ON p.patient_id = pat.id
          JOIN medications m ON p.medication_id = m.id
-         WHERE 
-         NOT EXISTS (
-            SELECT 1 FROM prescriptions p2 
-            WHERE p2.patient_id = p.patient_id 
-            AND p2.medication_id = p.medication_id 
-            AND p2.id > p.id
-         )
+         WHERE {}
          ORDER BY p.next_refill_date ASC
          LIMIT 4
-    ";
-    let items = sqlx::query_as::<_, DueRxItem>(sql)
+    ", REFILLABLE);
+    let items = sqlx::query_as::<_, DueRxItem>(&sql)
         .fetch_all(pool.inner())
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
     Ok(items)
 }
 
@@ -300,47 +1548,388 @@ async fn get_upcoming_refills(pool: State<'_, SqlitePool>) -> Result<Vec<DueRxIt
 // =====================================================
 
 #[tauri::command]
-async fn login_user(pool: State<'_, SqlitePool>, creds: LoginDto) -> Result<AuthResponse, String> {
-    let user = sqlx::query!(
-        "SELECT role FROM users WHERE username = ? AND password = ?",
-        creds.username, creds.password
-    )
-    .fetch_optional(pool.inner())
-    .await
-    .map_err(|e| e.to_string())?;
+async fn login_user(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, creds: LoginDto) -> Result<AuthResponse, AppError> {
+    let user = auth::authenticate(pool.inner(), &creds.username, &creds.password).await?;
+    sessions.start(user.id, &creds.username, &user.role, user.must_change_password);
 
-    match user {
-        Some(u) => {
-            let _ = sqlx::query("INSERT INTO audit_logs (username, action, details) VALUES (?, ?, ?)")
-                .bind(&creds.username)
-                .bind("LOGIN")
-                .bind("User logged in successfully")
-                .execute(pool.inner())
-                .await;
+    Ok(AuthResponse {
+        success: true,
+        role: user.role,
+        username: creds.username,
+        must_change_password: user.must_change_password,
+    })
+}
 
-            Ok(AuthResponse { success: true, role: u.role, username: creds.username })
-        },
-        None => Err("Invalid credentials".to_string()),
+#[tauri::command]
+async fn change_password(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, data: ChangePasswordDto) -> Result<String, AppError> {
+    // A pending forced change is exactly what this command resolves, so only touch() here
+    let session = sessions.touch()?;
+
+    let user = sqlx::query_as::<_, UserCredentials>(auth::USER_CREDENTIALS_SQL)
+        .bind(&session.username)
+        .fetch_one(pool.inner())
+        .await?;
+
+    if !auth::verify_password(Some(&user.password_hash), &data.current_password).await {
+        audit::record(pool.inner(), &session.username, Event::new("PASSWORD_CHANGE_FAILED", "Current password rejected").on(Entity::User, session.user_id)).await?;
+        return Err(AppError::validation("current_password", "Current password is incorrect"));
+    }
+
+    auth::check_password_policy(&session.username, &data.new_password)
+        .map_err(|m| AppError::validation("new_password", m))?;
+    if data.new_password == data.current_password {
+        return Err(AppError::validation("new_password", "New password must be different from the current one"));
     }
+
+    let hash = auth::hash_password(&data.new_password).await?;
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE users SET password_hash = ?, must_change_password = 0, failed_attempts = 0 WHERE id = ?")
+        .bind(&hash).bind(session.user_id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(&mut *tx, &session.username, Event::new("PASSWORD_CHANGED", "User changed their password").on(Entity::User, session.user_id)).await?;
+    tx.commit().await?;
+
+    sessions.clear_password_change();
+    Ok("Password updated.".to_string())
 }
 
 #[tauri::command]
-async fn log_action(pool: State<'_, SqlitePool>, username: String, action: String, details: String) -> Result<(), String> {
-    sqlx::query("INSERT INTO audit_logs (username, action, details) VALUES (?, ?, ?)")
-        .bind(username).bind(action).bind(details)
-        .execute(pool.inner())
-        .await
-        .map_err(|e| e.to_string())?;
+async fn logout(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<(), AppError> {
+    if let Some(session) = sessions.end() {
+        audit::record(pool.inner(), &session.username, Event::new("LOGOUT", "User logged out").on(Entity::User, session.user_id)).await?;
+    }
     Ok(())
 }
 
+/// Lets the UI poll for idle expiry without the poll itself counting as activity.
+#[tauri::command]
+async fn get_session(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Option<AuthResponse>, AppError> {
+    match sessions.peek() {
+        Ok(s) => Ok(Some(AuthResponse {
+            success: true,
+            role: s.role,
+            username: s.username,
+            must_change_password: s.must_change_password,
+        })),
+        Err(AuthError::Expired { username }) => {
+            audit::record(pool.inner(), &username, Event::new("SESSION_EXPIRED", "Logged out after inactivity")).await?;
+            Ok(None)
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// One page of the audit log, newest first. Pass the previous page's
+/// `next_cursor` as `before_id` to continue.
+#[tauri::command]
+async fn get_audit_logs(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, filter: Option<AuditLogFilter>, before_id: Option<i64>, limit: Option<i64>) -> Result<AuditLogPage, AppError> {
+    sessions.authorize(pool.inner(), Capability::AuditRead).await?;
+    let filter = filter.unwrap_or_default();
+    validation::validate_audit_filter(&filter)?;
+
+    Ok(search::search_audit_logs(pool.inner(), &filter, before_id, limit).await?)
+}
+
+/// Action codes present in the log, for the viewer's filter.
+#[tauri::command]
+async fn get_audit_actions(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<String>, AppError> {
+    sessions.authorize(pool.inner(), Capability::AuditRead).await?;
+
+    let rows: Vec<(String,)> = sqlx::query_as("SELECT DISTINCT action FROM audit_logs ORDER BY action")
+        .fetch_all(pool.inner())
+        .await?;
+    Ok(rows.into_iter().map(|(a,)| a).collect())
+}
+
+/// Writes every entry matching the filter to a CSV or JSON file in the
+/// exports folder and returns the file's path.
+#[tauri::command]
+async fn export_audit_logs(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, filter: Option<AuditLogFilter>, format: String) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::AuditRead).await?;
+    let filter = filter.unwrap_or_default();
+    validation::validate_audit_filter(&filter)?;
+
+    let rows = search::audit_log_query(&filter, None)
+        .build_query_as::<AuditLogItem>()
+        .fetch_all(pool.inner())
+        .await?;
+
+    let contents = match format.as_str() {
+        "csv" => audit::to_csv(&rows),
+        "json" => serde_json::to_string_pretty(&rows).map_err(|_| AppError::Internal)?,
+        _ => return Err(AppError::validation("format", "Format must be csv or json")),
+    };
+
+    tokio::fs::create_dir_all(audit::EXPORT_DIR).await?;
+    let file_name = format!("audit-log-{}.{}", chrono::Local::now().format("%Y%m%d-%H%M%S"), format);
+    let path = std::path::Path::new(audit::EXPORT_DIR).join(file_name);
+    tokio::fs::write(&path, contents).await?;
+    let path = tokio::fs::canonicalize(&path).await?.display().to_string();
+
+    // An export that can't be audited isn't kept
+    let logged = audit::record(pool.inner(), &user.username, Event::new("EXPORT_AUDIT_LOG", format!(
+        "{} entries to {} | Filter: {}", rows.len(), path, serde_json::to_string(&filter).unwrap_or_default()
+    ))).await;
+    if let Err(e) = logged {
+        let _ = tokio::fs::remove_file(&path).await;
+        return Err(e.into());
+    }
+    Ok(path)
+}
+
+/// Recomputes the audit hash chain and reports the first broken entry, if any.
 #[tauri::command]
-async fn get_audit_logs(pool: State<'_, SqlitePool>) -> Result<Vec<AuditLogItem>, String> {
-    let logs = sqlx::query_as::<_, AuditLogItem>("SELECT * FROM audit_logs ORDER BY timestamp DESC")
+async fn verify_audit_chain(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<AuditChainReport, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::AuditRead).await?;
+
+    let mut tx = pool.begin().await?;
+    let report = audit::verify_chain(&mut tx).await?;
+
+    let outcome = match &report.problem {
+        Some(problem) => format!("BROKEN: {}", problem),
+        None => format!("Intact ({} entries)", report.rows_checked),
+    };
+    audit::record(&mut *tx, &user.username, Event::new("VERIFY_AUDIT_CHAIN", outcome.as_str())).await?;
+    tx.commit().await?;
+    Ok(report)
+}
+
+/// Everything the audit log holds about one record, newest first. `entity_type`
+/// is one of patient, medication, prescription, supplier, purchase_order,
+/// count_session or user.
+#[tauri::command]
+async fn get_entity_history(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, entity_type: String, entity_id: i64) -> Result<Vec<AuditLogItem>, AppError> {
+    sessions.authorize(pool.inner(), Capability::AuditRead).await?;
+    let entity = Entity::parse(&entity_type)
+        .ok_or(AppError::validation("entity_type", format!("Unknown record type: {}", entity_type)))?;
+
+    let mut conn = pool.acquire().await?;
+    Ok(audit::history(&mut conn, entity, entity_id).await?)
+}
+
+// =====================================================
+// COMMANDS: SETTINGS
+// =====================================================
+
+#[tauri::command]
+async fn get_settings(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<Setting>, AppError> {
+    sessions.authorize(pool.inner(), Capability::SettingsManage).await?;
+
+    let rows = sqlx::query_as::<_, Setting>("SELECT key, value, updated_at, updated_by FROM settings ORDER BY key")
         .fetch_all(pool.inner())
-        .await
-        .map_err(|e| e.to_string())?;
-    Ok(logs)
+        .await?;
+    Ok(rows)
+}
+
+#[tauri::command]
+async fn update_setting(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, key: String, value: String) -> Result<String, AppError> {
+    let user = sessions.authorize(pool.inner(), Capability::SettingsManage).await?;
+    settings::validate(&key, &value)?;
+
+    let mut tx = pool.begin().await?;
+
+    let old: Option<(String,)> = sqlx::query_as("SELECT value FROM settings WHERE key = ?")
+        .bind(&key)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    sqlx::query(
+        "INSERT INTO settings (key, value, updated_at, updated_by) VALUES (?, ?, CURRENT_TIMESTAMP, ?)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at, updated_by = excluded.updated_by"
+    )
+    .bind(&key).bind(value.trim()).bind(&user.username)
+    .execute(&mut *tx)
+    .await?;
+
+    let old = old.map(|(v,)| v);
+    audit::record(&mut *tx, &user.username, Event::new("UPDATE_SETTING", format!(
+        "{}: '{}' -> '{}'", key, old.as_deref().unwrap_or_default(), value.trim()
+    ))
+        .before(old.map(|v| json!({ "key": key, "value": v })))
+        .after(json!({ "key": key, "value": value.trim() }))
+    ).await?;
+
+    tx.commit().await?;
+    Ok("Setting saved.".to_string())
+}
+
+// =====================================================
+// COMMANDS: USER MANAGEMENT
+// =====================================================
+
+/// Call before disabling `user_id` or moving it to another role (`removing`
+/// true). Errors if it is the last active admin, since that would leave the
+/// store with nobody able to manage users.
+async fn ensure_admin_remains(tx: &mut sqlx::SqliteConnection, user_id: i64, removing: bool) -> Result<(), AppError> {
+    if !removing {
+        return Ok(());
+    }
+    let (last_admin,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (SELECT 1 FROM users WHERE id = ? AND role = 'admin' AND active = 1)
+            AND NOT EXISTS (SELECT 1 FROM users WHERE id != ? AND role = 'admin' AND active = 1)"
+    )
+    .bind(user_id).bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if last_admin {
+        return Err(AppError::Conflict("Cannot remove the last active admin".to_string()));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn list_users(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>) -> Result<Vec<UserSummary>, AppError> {
+    sessions.authorize(pool.inner(), Capability::UsersManage).await?;
+
+    let users = sqlx::query_as::<_, UserSummary>(
+        "SELECT id, username, role, active, must_change_password,
+                COALESCE(locked_until > datetime('now'), 0) AS is_locked
+         FROM users ORDER BY username ASC"
+    )
+    .fetch_all(pool.inner())
+    .await?;
+    Ok(users)
+}
+
+#[tauri::command]
+async fn create_user(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, data: CreateUserDto) -> Result<String, AppError> {
+    let admin = sessions.authorize(pool.inner(), Capability::UsersManage).await?;
+
+    let username = data.username.trim();
+    if username.is_empty() {
+        return Err(AppError::validation("username", "Username is required"));
+    }
+    let role = Role::parse(&data.role).ok_or(AppError::validation("role", format!("Unknown role: {}", data.role)))?;
+    auth::check_password_policy(username, &data.password)
+        .map_err(|m| AppError::validation("password", m))?;
+
+    // Admin-chosen passwords are temporary; the new user sets their own on first login
+    let hash = auth::hash_password(&data.password).await?;
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query("INSERT INTO users (username, password_hash, role, must_change_password) VALUES (?, ?, ?, 1)")
+        .bind(username).bind(&hash).bind(role.as_str())
+        .execute(&mut *tx)
+        .await;
+
+    let id = match result {
+        Ok(r) => r.last_insert_rowid(),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Err(AppError::Conflict(format!("Username '{}' is already taken", username)));
+        },
+        Err(e) => return Err(e.into()),
+    };
+
+    let after = audit::snapshot(&mut *tx, Entity::User, id).await?;
+    audit::record(&mut *tx, &admin.username, Event::new("CREATE_USER", format!("Created user '{}' with role {}", username, role.as_str()))
+        .on(Entity::User, id)
+        .after(after)
+    ).await?;
+
+    tx.commit().await?;
+    Ok("User created.".to_string())
+}
+
+#[tauri::command]
+async fn set_user_active(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, user_id: i64, active: bool) -> Result<String, AppError> {
+    let admin = sessions.authorize(pool.inner(), Capability::UsersManage).await?;
+    let mut tx = pool.begin().await?;
+
+    let before = audit::snapshot(&mut *tx, Entity::User, user_id).await?;
+    let target: (String,) = sqlx::query_as("SELECT username FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::not_found("User"))?;
+
+    ensure_admin_remains(&mut tx, user_id, !active).await?;
+
+    // Re-enabling also clears any lockout left over from before
+    sqlx::query("UPDATE users SET active = ?, failed_attempts = 0, locked_until = NULL WHERE id = ?")
+        .bind(active).bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let (action, verb) = if active { ("ENABLE_USER", "Enabled") } else { ("DISABLE_USER", "Disabled") };
+    let after = audit::snapshot(&mut *tx, Entity::User, user_id).await?;
+    audit::record(&mut *tx, &admin.username, Event::new(action, format!("{} user '{}'", verb, target.0))
+        .on(Entity::User, user_id)
+        .before(before)
+        .after(after)
+    ).await?;
+
+    tx.commit().await?;
+
+    if !active && user_id == admin.user_id {
+        sessions.end();
+    }
+    Ok(format!("User {}.", verb.to_lowercase()))
+}
+
+#[tauri::command]
+async fn set_user_role(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, user_id: i64, role: String) -> Result<String, AppError> {
+    let admin = sessions.authorize(pool.inner(), Capability::UsersManage).await?;
+    let new_role = Role::parse(&role).ok_or(AppError::validation("role", format!("Unknown role: {}", role)))?;
+    let mut tx = pool.begin().await?;
+
+    let before = audit::snapshot(&mut *tx, Entity::User, user_id).await?;
+    let target: (String, String) = sqlx::query_as("SELECT username, role FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::not_found("User"))?;
+
+    ensure_admin_remains(&mut tx, user_id, new_role != Role::Admin).await?;
+
+    sqlx::query("UPDATE users SET role = ? WHERE id = ?")
+        .bind(new_role.as_str()).bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let after = audit::snapshot(&mut *tx, Entity::User, user_id).await?;
+    audit::record(&mut *tx, &admin.username, Event::new("CHANGE_ROLE", format!("User '{}': {} -> {}", target.0, target.1, new_role.as_str()))
+        .on(Entity::User, user_id)
+        .before(before)
+        .after(after)
+    ).await?;
+
+    tx.commit().await?;
+    Ok("Role updated.".to_string())
+}
+
+#[tauri::command]
+async fn reset_user_password(pool: State<'_, SqlitePool>, sessions: State<'_, SessionStore>, user_id: i64, new_password: String) -> Result<String, AppError> {
+    let admin = sessions.authorize(pool.inner(), Capability::UsersManage).await?;
+
+    let target: (String,) = sqlx::query_as("SELECT username FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool.inner())
+        .await?
+        .ok_or(AppError::not_found("User"))?;
+
+    auth::check_password_policy(&target.0, &new_password)
+        .map_err(|m| AppError::validation("new_password", m))?;
+    let hash = auth::hash_password(&new_password).await?;
+
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut *tx, Entity::User, user_id).await?;
+    sqlx::query(
+        "UPDATE users SET password_hash = ?, must_change_password = 1, failed_attempts = 0, locked_until = NULL WHERE id = ?"
+    )
+    .bind(&hash).bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let after = audit::snapshot(&mut *tx, Entity::User, user_id).await?;
+    audit::record(&mut *tx, &admin.username, Event::new("RESET_PASSWORD", format!("Reset password for '{}'", target.0))
+        .on(Entity::User, user_id)
+        .before(before)
+        .after(after)
+    ).await?;
+
+    tx.commit().await?;
+    Ok("Password reset. The user must choose a new one at next login.".to_string())
 }
 
 // =====================================================
@@ -354,26 +1943,75 @@ async fn main() {
         .connect_with(SqliteConnectOptions::new().filename("pharmacy.db").create_if_missing(true))
         .await.expect("DB Connection Failed");
 
-    // Migrations
-    sqlx::query("CREATE TABLE IF NOT EXISTS patients (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, birth_date TEXT NOT NULL, phone TEXT NOT NULL, email TEXT, address TEXT NOT NULL, city TEXT NOT NULL, state TEXT NOT NULL, postal_code TEXT NOT NULL, health_card_num TEXT NOT NULL, allergies TEXT, insurance_provider TEXT, insurance_id TEXT)").execute(&pool).await.unwrap();
-    sqlx::query("CREATE TABLE IF NOT EXISTS medications (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, din TEXT UNIQUE NOT NULL, ndc TEXT, description TEXT, stock INTEGER DEFAULT 0, price REAL DEFAULT 0.0, expiration TEXT NOT NULL)").execute(&pool).await.unwrap();
-    sqlx::query("CREATE TABLE IF NOT EXISTS prescriptions (id INTEGER PRIMARY KEY AUTOINCREMENT, patient_id INTEGER NOT NULL, medication_id INTEGER NOT NULL, prescriber TEXT NOT NULL, sig TEXT NOT NULL, quantity INTEGER NOT NULL, refills INTEGER NOT NULL, days_supply INTEGER NOT NULL, date_filled TEXT NOT NULL, next_refill_date TEXT NOT NULL, FOREIGN KEY(patient_id) REFERENCES patients(id), FOREIGN KEY(medication_id) REFERENCES medications(id))").execute(&pool).await.unwrap();
-    
-    // New Migrations for Auth
-    sqlx::query("CREATE TABLE IF NOT EXISTS users (id INTEGER PRIMARY KEY AUTOINCREMENT, username TEXT UNIQUE NOT NULL, password TEXT NOT NULL, role TEXT NOT NULL)").execute(&pool).await.unwrap();
-    sqlx::query("CREATE TABLE IF NOT EXISTS audit_logs (id INTEGER PRIMARY KEY AUTOINCREMENT, username TEXT NOT NULL, action TEXT NOT NULL, details TEXT, timestamp DATETIME DEFAULT CURRENT_TIMESTAMP)").execute(&pool).await.unwrap();
+    if let Err(e) = migrations::run(&pool).await {
+        eprintln!("❌ {}", e);
+        std::process::exit(1);
+    }
+
+    match audit::seal_unchained(&pool).await {
+        Ok(0) => {},
+        Ok(n) => println!("🔒 Sealed {} existing audit entries into the hash chain", n),
+        Err(e) => {
+            eprintln!("❌ Could not seal the audit trail: {}", e);
+            std::process::exit(1);
+        }
+    }
 
     seed::init_db(&pool).await;
 
     tauri::Builder::default()
         .manage(pool)
+        .manage(SessionStore::default())
         .invoke_handler(tauri::generate_handler![
-            add_patient, get_patients, get_patient_history,
+            add_patient, update_patient, get_patients, get_patient_history,
+            find_duplicate_patients, merge_patients,
+            get_patient_allergies, add_patient_allergy, remove_patient_allergy,
             add_medication, get_medications, update_medication,
-            create_prescription,
+            get_medication_ingredients, set_medication_ingredients,
+            get_medication_lots, receive_lot, get_adjustment_reasons, adjust_lot, get_stock_movements, reconcile_stock, get_lot_fills,
+            get_count_locations, start_count_session, get_count_sessions, get_count_lines, record_count,
+            post_count_session, cancel_count_session,
+            get_schedules, get_controlled_register, get_controlled_reconciliation,
+            get_suppliers, add_supplier, get_purchase_orders, get_purchase_order_lines, create_purchase_order,
+            create_reorder_purchase_order, submit_purchase_order, cancel_purchase_order, receive_purchase_order_line,
+            get_low_stock_items, get_expiring_stock, quarantine_expired_stock, get_quarantined_stock, resolve_quarantine,
+            create_prescription, refill_prescription, screen_prescription, get_pending_verifications, verify_fill,
+            get_early_fill_reasons, void_fill, hold_prescription, resume_prescription, cancel_prescription, transfer_prescription,
+            get_interactions, import_interactions,
             get_dashboard_stats, get_due_prescriptions, get_upcoming_refills,
-            login_user, log_action, get_audit_logs
+            login_user, change_password, logout, get_session, get_audit_logs, get_audit_actions, export_audit_logs, verify_audit_chain, get_entity_history,
+            get_settings, update_setting,
+            list_users, create_user, set_user_active, set_user_role, reset_user_password
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::test_pool;
+
+    async fn removing_admin(pool: &SqlitePool, user_id: i64, removing: bool) -> Result<(), AppError> {
+        ensure_admin_remains(&mut pool.acquire().await.unwrap(), user_id, removing).await
+    }
+
+    #[tokio::test]
+    async fn last_active_admin_is_kept() {
+        let pool = test_pool().await;
+        sqlx::raw_sql(
+            "INSERT INTO users (username, password_hash, role) VALUES ('boss', 'x', 'admin'), ('pharm', 'x', 'pharmacist');
+             INSERT INTO users (username, password_hash, role, active) VALUES ('old_boss', 'x', 'admin', 0);"
+        )
+        .execute(&pool).await.unwrap();
+
+        assert!(matches!(removing_admin(&pool, 1, true).await, Err(AppError::Conflict(_))));
+        assert!(removing_admin(&pool, 1, false).await.is_ok());
+        // Neither a non-admin nor an already disabled admin holds the store's access
+        assert!(removing_admin(&pool, 2, true).await.is_ok());
+        assert!(removing_admin(&pool, 3, true).await.is_ok());
+
+        sqlx::query("UPDATE users SET active = 1 WHERE id = 3").execute(&pool).await.unwrap();
+        assert!(removing_admin(&pool, 1, true).await.is_ok());
+    }
+}
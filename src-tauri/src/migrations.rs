@@ -0,0 +1,716 @@
+use sqlx::SqlitePool;
+
+// =====================================================
+// SCHEMA MIGRATIONS
+// =====================================================
+// Every schema change gets a new entry at the END of `MIGRATIONS` with the
+// next version number. Never edit a migration that has already shipped:
+// stores in the field have recorded it in `schema_version` and won't re-run it.
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    // Baseline: the tables main() used to create inline. `IF NOT EXISTS` lets
+    // databases created before versioning adopt this step without changes.
+    Migration {
+        version: 1,
+        name: "baseline",
+        sql: "
+            CREATE TABLE IF NOT EXISTS patients (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, birth_date TEXT NOT NULL, phone TEXT NOT NULL, email TEXT, address TEXT NOT NULL, city TEXT NOT NULL, state TEXT NOT NULL, postal_code TEXT NOT NULL, health_card_num TEXT NOT NULL, allergies TEXT, insurance_provider TEXT, insurance_id TEXT);
+            CREATE TABLE IF NOT EXISTS medications (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, din TEXT UNIQUE NOT NULL, ndc TEXT, description TEXT, stock INTEGER DEFAULT 0, price REAL DEFAULT 0.0, expiration TEXT NOT NULL);
+            CREATE TABLE IF NOT EXISTS prescriptions (id INTEGER PRIMARY KEY AUTOINCREMENT, patient_id INTEGER NOT NULL, medication_id INTEGER NOT NULL, prescriber TEXT NOT NULL, sig TEXT NOT NULL, quantity INTEGER NOT NULL, refills INTEGER NOT NULL, days_supply INTEGER NOT NULL, date_filled TEXT NOT NULL, next_refill_date TEXT NOT NULL, FOREIGN KEY(patient_id) REFERENCES patients(id), FOREIGN KEY(medication_id) REFERENCES medications(id));
+            CREATE TABLE IF NOT EXISTS users (id INTEGER PRIMARY KEY AUTOINCREMENT, username TEXT UNIQUE NOT NULL, password TEXT NOT NULL, role TEXT NOT NULL);
+            CREATE TABLE IF NOT EXISTS audit_logs (id INTEGER PRIMARY KEY AUTOINCREMENT, username TEXT NOT NULL, action TEXT NOT NULL, details TEXT, timestamp DATETIME DEFAULT CURRENT_TIMESTAMP);
+        ",
+    },
+    // Passwords become Argon2 hashes (legacy plaintext rows are upgraded on next
+    // login), plus lockout counters and a forced-rotation flag. Accounts whose
+    // password still equals the username are the seeded defaults.
+    Migration {
+        version: 2,
+        name: "user_credentials",
+        sql: "
+            ALTER TABLE users RENAME COLUMN password TO password_hash;
+            ALTER TABLE users ADD COLUMN failed_attempts INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE users ADD COLUMN locked_until DATETIME;
+            ALTER TABLE users ADD COLUMN must_change_password INTEGER NOT NULL DEFAULT 0;
+            UPDATE users SET must_change_password = 1 WHERE password_hash = username;
+        ",
+    },
+    // Who filled each Rx and which pharmacist verified it.
+    Migration {
+        version: 3,
+        name: "prescription_verification",
+        sql: "
+            ALTER TABLE prescriptions ADD COLUMN filled_by TEXT;
+            ALTER TABLE prescriptions ADD COLUMN verified_by TEXT;
+            ALTER TABLE prescriptions ADD COLUMN verified_at DATETIME;
+        ",
+    },
+    // Staff who leave are disabled rather than deleted so their audit history stays attributable.
+    Migration {
+        version: 4,
+        name: "user_active_flag",
+        sql: "
+            ALTER TABLE users ADD COLUMN active INTEGER NOT NULL DEFAULT 1;
+        ",
+    },
+    // Structured allergy records and ingredient/class tags on medications.
+    // Existing free-text allergies are split on commas; "None"-style entries are dropped.
+    // `patients.allergies` stays as a display summary of the structured rows.
+    Migration {
+        version: 5,
+        name: "structured_allergies",
+        sql: "
+            CREATE TABLE patient_allergies (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                patient_id INTEGER NOT NULL,
+                allergen TEXT NOT NULL,
+                reaction TEXT,
+                severity TEXT NOT NULL DEFAULT 'unknown' CHECK (severity IN ('mild', 'moderate', 'severe', 'unknown')),
+                recorded_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY(patient_id) REFERENCES patients(id)
+            );
+            CREATE INDEX idx_patient_allergies_patient ON patient_allergies(patient_id);
+
+            CREATE TABLE medication_ingredients (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                medication_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                kind TEXT NOT NULL CHECK (kind IN ('ingredient', 'class')),
+                UNIQUE(medication_id, name),
+                FOREIGN KEY(medication_id) REFERENCES medications(id)
+            );
+
+            WITH RECURSIVE split(patient_id, item, rest) AS (
+                SELECT id, '', REPLACE(allergies, ';', ',') || ',' FROM patients
+                WHERE allergies IS NOT NULL AND TRIM(allergies) != ''
+                UNION ALL
+                SELECT patient_id, TRIM(SUBSTR(rest, 1, INSTR(rest, ',') - 1)), SUBSTR(rest, INSTR(rest, ',') + 1)
+                FROM split WHERE rest != ''
+            )
+            INSERT INTO patient_allergies (patient_id, allergen)
+            SELECT patient_id, item FROM split
+            WHERE item != '' AND LOWER(item) NOT IN ('none', 'nka', 'nkda', 'no known allergies');
+
+            UPDATE patients SET allergies = (
+                SELECT GROUP_CONCAT(allergen, ', ') FROM patient_allergies WHERE patient_id = patients.id
+            );
+        ",
+    },
+    // Interaction pairs between ingredient/class names (as used in medication_ingredients).
+    // Pairs are stored with term_a <= term_b so each pair exists once.
+    Migration {
+        version: 6,
+        name: "drug_interactions",
+        sql: "
+            CREATE TABLE drug_interactions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                term_a TEXT NOT NULL,
+                term_b TEXT NOT NULL,
+                severity TEXT NOT NULL CHECK (severity IN ('contraindicated', 'major', 'moderate', 'minor')),
+                monograph TEXT NOT NULL,
+                UNIQUE(term_a, term_b),
+                CHECK (term_a <= term_b)
+            );
+        ",
+    },
+    // Splits prescriptions (the order) from fills (each dispense against it).
+    // Before this, a refill was a brand-new prescriptions row copying patient,
+    // drug, prescriber and sig with one fewer refill. Taken in date order, a row
+    // continues the chain before it only when its refill count is exactly one
+    // lower; anything else (a renewal) starts a new prescription. Each chain
+    // collapses into its first row; every row in the chain becomes a fill, and
+    // the newest row's refill count is what remains. `prescriptions.next_refill_date` is
+    // kept as the due date after the latest fill. Verification moves to fills.
+    Migration {
+        version: 7,
+        name: "prescription_fills",
+        sql: "
+            ALTER TABLE prescriptions ADD COLUMN refills_remaining INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE prescriptions ADD COLUMN expires_on TEXT;
+
+            CREATE TABLE fills (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                prescription_id INTEGER NOT NULL,
+                fill_number INTEGER NOT NULL,
+                quantity INTEGER NOT NULL,
+                days_supply INTEGER NOT NULL,
+                date_filled TEXT NOT NULL,
+                next_refill_date TEXT NOT NULL,
+                filled_by TEXT,
+                verified_by TEXT,
+                verified_at DATETIME,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE(prescription_id, fill_number),
+                FOREIGN KEY(prescription_id) REFERENCES prescriptions(id)
+            );
+
+            CREATE TEMP TABLE rx_chain AS
+            WITH ordered AS (
+                SELECT id, patient_id, medication_id, prescriber, sig, date_filled,
+                    CASE WHEN refills = LAG(refills) OVER same_order - 1 THEN 0 ELSE 1 END AS starts_chain
+                FROM prescriptions
+                WINDOW same_order AS (PARTITION BY patient_id, medication_id, prescriber, sig ORDER BY date_filled, id)
+            ), numbered AS (
+                SELECT *, SUM(starts_chain) OVER (PARTITION BY patient_id, medication_id, prescriber, sig ORDER BY date_filled, id) AS chain_no
+                FROM ordered
+            )
+            SELECT id,
+                FIRST_VALUE(id) OVER chain AS root_id,
+                ROW_NUMBER() OVER chain - 1 AS fill_number
+            FROM numbered
+            WINDOW chain AS (PARTITION BY patient_id, medication_id, prescriber, sig, chain_no ORDER BY date_filled, id);
+
+            INSERT INTO fills (prescription_id, fill_number, quantity, days_supply, date_filled, next_refill_date, filled_by, verified_by, verified_at)
+            SELECT c.root_id, c.fill_number, p.quantity, p.days_supply, p.date_filled, p.next_refill_date, p.filled_by, p.verified_by, p.verified_at
+            FROM prescriptions p JOIN rx_chain c ON c.id = p.id;
+
+            UPDATE prescriptions SET
+                refills_remaining = MAX(0, (
+                    SELECT p2.refills FROM rx_chain c JOIN prescriptions p2 ON p2.id = c.id
+                    WHERE c.root_id = prescriptions.id ORDER BY c.fill_number DESC LIMIT 1
+                )),
+                next_refill_date = (
+                    SELECT f.next_refill_date FROM fills f
+                    WHERE f.prescription_id = prescriptions.id ORDER BY f.fill_number DESC LIMIT 1
+                ),
+                expires_on = date(date_filled, '+365 days')
+            WHERE id IN (SELECT root_id FROM rx_chain);
+
+            DELETE FROM prescriptions WHERE id NOT IN (SELECT root_id FROM rx_chain);
+            DROP TABLE rx_chain;
+
+            ALTER TABLE prescriptions DROP COLUMN filled_by;
+            ALTER TABLE prescriptions DROP COLUMN verified_by;
+            ALTER TABLE prescriptions DROP COLUMN verified_at;
+        ",
+    },
+    // Prescription lifecycle. Anything with no refills left is already completed.
+    Migration {
+        version: 8,
+        name: "prescription_status",
+        sql: "
+            ALTER TABLE prescriptions ADD COLUMN status TEXT NOT NULL DEFAULT 'active'
+                CHECK (status IN ('active', 'on_hold', 'cancelled', 'transferred_out', 'completed'));
+            ALTER TABLE prescriptions ADD COLUMN status_reason TEXT;
+            ALTER TABLE prescriptions ADD COLUMN status_changed_at DATETIME;
+            ALTER TABLE prescriptions ADD COLUMN status_changed_by TEXT;
+
+            UPDATE prescriptions SET status = 'completed' WHERE refills_remaining <= 0;
+        ",
+    },
+    // Voided fills stay in the table for the record; stock and refills are given back.
+    Migration {
+        version: 9,
+        name: "fill_voids",
+        sql: "
+            ALTER TABLE fills ADD COLUMN voided_at DATETIME;
+            ALTER TABLE fills ADD COLUMN voided_by TEXT;
+            ALTER TABLE fills ADD COLUMN void_reason TEXT;
+        ",
+    },
+    // Too-soon refill policy: thresholds live in a settings table, controlled
+    // substances are flagged, and fills remember an early-fill reason code.
+    Migration {
+        version: 10,
+        name: "too_soon_checks",
+        sql: "
+            CREATE TABLE settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_by TEXT
+            );
+            INSERT INTO settings (key, value) VALUES ('too_soon_pct', '80'), ('controlled_too_soon_pct', '90');
+
+            ALTER TABLE medications ADD COLUMN controlled INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE fills ADD COLUMN early_fill_code TEXT;
+        ",
+    },
+    // Stock is held per lot (number, expiry, quantity) and fills record the lots
+    // they drew from. Existing stock becomes one LEGACY lot per drug.
+    Migration {
+        version: 11,
+        name: "medication_lots",
+        sql: "
+            CREATE TABLE medication_lots (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                medication_id INTEGER NOT NULL REFERENCES medications(id) ON DELETE CASCADE,
+                lot_number TEXT NOT NULL,
+                expiration TEXT NOT NULL,
+                quantity INTEGER NOT NULL CHECK (quantity >= 0),
+                received_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (medication_id, lot_number)
+            );
+            CREATE INDEX idx_lots_medication ON medication_lots(medication_id, expiration);
+
+            -- Which lots each fill was dispensed from, for recalls and voids
+            CREATE TABLE fill_lots (
+                fill_id INTEGER NOT NULL REFERENCES fills(id) ON DELETE CASCADE,
+                lot_id INTEGER NOT NULL REFERENCES medication_lots(id),
+                quantity INTEGER NOT NULL CHECK (quantity > 0),
+                PRIMARY KEY (fill_id, lot_id)
+            );
+            CREATE INDEX idx_fill_lots_lot ON fill_lots(lot_id);
+
+            -- Existing stock becomes one lot per drug with the old single expiry
+            INSERT INTO medication_lots (medication_id, lot_number, expiration, quantity)
+            SELECT id, 'LEGACY', expiration, stock FROM medications WHERE stock > 0;
+
+            -- stock and expiration on medications are now derived from the lots:
+            -- total on hand and the earliest expiry still on the shelf
+            CREATE TRIGGER lots_after_insert AFTER INSERT ON medication_lots BEGIN
+                UPDATE medications SET
+                    stock = (SELECT COALESCE(SUM(quantity), 0) FROM medication_lots WHERE medication_id = NEW.medication_id),
+                    expiration = COALESCE((SELECT MIN(expiration) FROM medication_lots WHERE medication_id = NEW.medication_id AND quantity > 0), expiration)
+                WHERE id = NEW.medication_id;
+            END;
+            CREATE TRIGGER lots_after_update AFTER UPDATE ON medication_lots BEGIN
+                UPDATE medications SET
+                    stock = (SELECT COALESCE(SUM(quantity), 0) FROM medication_lots WHERE medication_id = NEW.medication_id),
+                    expiration = COALESCE((SELECT MIN(expiration) FROM medication_lots WHERE medication_id = NEW.medication_id AND quantity > 0), expiration)
+                WHERE id = NEW.medication_id;
+            END;
+            CREATE TRIGGER lots_after_delete AFTER DELETE ON medication_lots BEGIN
+                UPDATE medications SET
+                    stock = (SELECT COALESCE(SUM(quantity), 0) FROM medication_lots WHERE medication_id = OLD.medication_id),
+                    expiration = COALESCE((SELECT MIN(expiration) FROM medication_lots WHERE medication_id = OLD.medication_id AND quantity > 0), expiration)
+                WHERE id = OLD.medication_id;
+            END;
+        ",
+    },
+    // Expiry warning window setting, and a quarantine bucket for stock taken
+    // off the shelf pending return to supplier or destruction.
+    Migration {
+        version: 12,
+        name: "expiry_quarantine",
+        sql: "
+            INSERT INTO settings (key, value) VALUES ('expiry_warning_days', '90');
+
+            -- Stock pulled off the shelf (expired, recalled) awaiting return or destruction
+            CREATE TABLE quarantined_stock (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                lot_id INTEGER NOT NULL REFERENCES medication_lots(id),
+                quantity INTEGER NOT NULL CHECK (quantity > 0),
+                reason TEXT NOT NULL,
+                quarantined_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                quarantined_by TEXT NOT NULL,
+                disposition TEXT NOT NULL DEFAULT 'pending' CHECK (disposition IN ('pending', 'returned', 'destroyed')),
+                resolved_at DATETIME,
+                resolved_by TEXT
+            );
+            CREATE INDEX idx_quarantine_pending ON quarantined_stock(disposition);
+        ",
+    },
+    // Per-drug reorder point and quantity. Existing drugs keep the old
+    // store-wide threshold of 100 until someone sets their own.
+    Migration {
+        version: 13,
+        name: "reorder_points",
+        sql: "
+            ALTER TABLE medications ADD COLUMN reorder_point INTEGER NOT NULL DEFAULT 100;
+            ALTER TABLE medications ADD COLUMN reorder_qty INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE medications ADD COLUMN auto_reorder INTEGER NOT NULL DEFAULT 0;
+
+            INSERT INTO settings (key, value) VALUES
+                ('velocity_window_days', '90'), ('reorder_lead_days', '7'), ('reorder_cover_days', '30');
+        ",
+    },
+    // Suppliers and purchase orders. Each receipt against a PO line records the
+    // lot it went into, so stock increases can be traced back to an order.
+    Migration {
+        version: 14,
+        name: "purchase_orders",
+        sql: "
+            CREATE TABLE suppliers (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                contact TEXT,
+                phone TEXT,
+                email TEXT,
+                active INTEGER NOT NULL DEFAULT 1
+            );
+
+            CREATE TABLE purchase_orders (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                supplier_id INTEGER NOT NULL REFERENCES suppliers(id),
+                status TEXT NOT NULL DEFAULT 'draft'
+                    CHECK (status IN ('draft', 'ordered', 'partially_received', 'received', 'cancelled')),
+                notes TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                created_by TEXT NOT NULL,
+                ordered_at DATETIME,
+                ordered_by TEXT
+            );
+
+            CREATE TABLE purchase_order_lines (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                purchase_order_id INTEGER NOT NULL REFERENCES purchase_orders(id) ON DELETE CASCADE,
+                medication_id INTEGER NOT NULL REFERENCES medications(id),
+                quantity_ordered INTEGER NOT NULL CHECK (quantity_ordered > 0),
+                quantity_received INTEGER NOT NULL DEFAULT 0,
+                unit_cost REAL,
+                UNIQUE (purchase_order_id, medication_id)
+            );
+
+            CREATE TABLE po_receipts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                line_id INTEGER NOT NULL REFERENCES purchase_order_lines(id),
+                lot_id INTEGER NOT NULL REFERENCES medication_lots(id),
+                quantity INTEGER NOT NULL CHECK (quantity > 0),
+                unit_cost REAL NOT NULL,
+                received_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                received_by TEXT NOT NULL
+            );
+            CREATE INDEX idx_po_receipts_line ON po_receipts(line_id);
+        ",
+    },
+    // Append-only stock ledger. Lot quantities are now only ever changed by
+    // inserting a movement; current lot balances are carried in as opening rows
+    // before the trigger that applies movements exists.
+    Migration {
+        version: 15,
+        name: "stock_movements",
+        sql: "
+            CREATE TABLE stock_movements (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                lot_id INTEGER NOT NULL REFERENCES medication_lots(id),
+                kind TEXT NOT NULL CHECK (kind IN ('fill', 'receive', 'adjust', 'return', 'waste', 'transfer')),
+                quantity INTEGER NOT NULL CHECK (quantity != 0),
+                reason_code TEXT NOT NULL,
+                reference_id INTEGER,
+                username TEXT NOT NULL,
+                note TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE INDEX idx_movements_lot ON stock_movements(lot_id);
+
+            INSERT INTO stock_movements (lot_id, kind, quantity, reason_code, username)
+            SELECT id, 'adjust', quantity, 'opening_balance', 'system' FROM medication_lots WHERE quantity > 0;
+
+            CREATE TRIGGER movements_apply AFTER INSERT ON stock_movements BEGIN
+                UPDATE medication_lots SET quantity = quantity + NEW.quantity WHERE id = NEW.lot_id;
+            END;
+            CREATE TRIGGER movements_no_update BEFORE UPDATE ON stock_movements BEGIN
+                SELECT RAISE(ABORT, 'stock_movements is append-only');
+            END;
+            CREATE TRIGGER movements_no_delete BEFORE DELETE ON stock_movements BEGIN
+                SELECT RAISE(ABORT, 'stock_movements is append-only');
+            END;
+        ",
+    },
+    // Physical count sessions. Each line snapshots a lot's quantity when the
+    // count starts; variances are posted to the ledger when a pharmacist approves.
+    Migration {
+        version: 16,
+        name: "count_sessions",
+        sql: "
+            CREATE TABLE count_sessions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                location TEXT,
+                status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'posted', 'cancelled')),
+                started_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                started_by TEXT NOT NULL,
+                posted_at DATETIME,
+                approved_by TEXT
+            );
+
+            CREATE TABLE count_lines (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id INTEGER NOT NULL REFERENCES count_sessions(id) ON DELETE CASCADE,
+                lot_id INTEGER NOT NULL REFERENCES medication_lots(id),
+                expected INTEGER NOT NULL,
+                counted INTEGER CHECK (counted >= 0),
+                counted_by TEXT,
+                counted_at DATETIME,
+                UNIQUE (session_id, lot_id)
+            );
+        ",
+    },
+    // Controlled substance schedules and the perpetual register. Every ledger
+    // movement of a scheduled drug is copied into the register by trigger with
+    // the drug's running balance, so no stock path can skip it. Movements and
+    // destruction of quarantined stock can carry a witness.
+    Migration {
+        version: 17,
+        name: "controlled_register",
+        sql: "
+            ALTER TABLE medications ADD COLUMN schedule TEXT CHECK (schedule IN ('narcotic', 'controlled', 'targeted'));
+            ALTER TABLE stock_movements ADD COLUMN witness TEXT;
+            ALTER TABLE quarantined_stock ADD COLUMN witness TEXT;
+
+            CREATE TABLE controlled_register (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                medication_id INTEGER NOT NULL REFERENCES medications(id),
+                movement_id INTEGER UNIQUE REFERENCES stock_movements(id),
+                kind TEXT NOT NULL,
+                quantity INTEGER NOT NULL,
+                balance INTEGER NOT NULL,
+                lot_number TEXT,
+                reason_code TEXT NOT NULL,
+                reference_id INTEGER,
+                recorded_by TEXT NOT NULL,
+                witness TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE INDEX idx_register_medication ON controlled_register(medication_id, id);
+
+            CREATE TRIGGER register_movement AFTER INSERT ON stock_movements
+            WHEN (SELECT m.schedule FROM medication_lots l JOIN medications m ON m.id = l.medication_id WHERE l.id = NEW.lot_id) IS NOT NULL
+            BEGIN
+                INSERT INTO controlled_register (medication_id, movement_id, kind, quantity, balance, lot_number, reason_code, reference_id, recorded_by, witness)
+                SELECT l.medication_id, NEW.id, NEW.kind, NEW.quantity,
+                    COALESCE((SELECT balance FROM controlled_register WHERE medication_id = l.medication_id ORDER BY id DESC LIMIT 1), 0) + NEW.quantity,
+                    l.lot_number, NEW.reason_code, NEW.reference_id, NEW.username, NEW.witness
+                FROM medication_lots l WHERE l.id = NEW.lot_id;
+            END;
+            CREATE TRIGGER register_no_update BEFORE UPDATE ON controlled_register BEGIN
+                SELECT RAISE(ABORT, 'controlled_register is append-only');
+            END;
+            CREATE TRIGGER register_no_delete BEFORE DELETE ON controlled_register BEGIN
+                SELECT RAISE(ABORT, 'controlled_register is append-only');
+            END;
+        ",
+    },
+    // Hash chain over the audit trail. Rows are hashed by the application on
+    // insert (existing rows are sealed once at startup); after that a row can
+    // never be changed, and no row can be deleted.
+    Migration {
+        version: 18,
+        name: "audit_chain",
+        sql: "
+            ALTER TABLE audit_logs ADD COLUMN prev_hash TEXT;
+            ALTER TABLE audit_logs ADD COLUMN hash TEXT;
+
+            CREATE TRIGGER audit_logs_seal_only BEFORE UPDATE ON audit_logs
+            WHEN OLD.hash IS NOT NULL OR NEW.id IS NOT OLD.id OR NEW.username IS NOT OLD.username
+                OR NEW.action IS NOT OLD.action OR NEW.details IS NOT OLD.details OR NEW.timestamp IS NOT OLD.timestamp
+            BEGIN
+                SELECT RAISE(ABORT, 'audit_logs entries cannot be changed');
+            END;
+            CREATE TRIGGER audit_logs_no_delete BEFORE DELETE ON audit_logs BEGIN
+                SELECT RAISE(ABORT, 'audit_logs is append-only');
+            END;
+        ",
+    },
+    // Structured audit events: the record an entry concerns, and JSON
+    // snapshots of it before and after the change. The seal trigger is
+    // recreated so the new columns are frozen too.
+    Migration {
+        version: 19,
+        name: "audit_entities",
+        sql: "
+            ALTER TABLE audit_logs ADD COLUMN entity_type TEXT;
+            ALTER TABLE audit_logs ADD COLUMN entity_id INTEGER;
+            ALTER TABLE audit_logs ADD COLUMN before_json TEXT;
+            ALTER TABLE audit_logs ADD COLUMN after_json TEXT;
+            CREATE INDEX idx_audit_logs_entity ON audit_logs(entity_type, entity_id);
+
+            DROP TRIGGER audit_logs_seal_only;
+            CREATE TRIGGER audit_logs_seal_only BEFORE UPDATE ON audit_logs
+            WHEN OLD.hash IS NOT NULL OR NEW.id IS NOT OLD.id OR NEW.username IS NOT OLD.username
+                OR NEW.action IS NOT OLD.action OR NEW.details IS NOT OLD.details OR NEW.timestamp IS NOT OLD.timestamp
+                OR NEW.entity_type IS NOT OLD.entity_type OR NEW.entity_id IS NOT OLD.entity_id
+                OR NEW.before_json IS NOT OLD.before_json OR NEW.after_json IS NOT OLD.after_json
+            BEGIN
+                SELECT RAISE(ABORT, 'audit_logs entries cannot be changed');
+            END;
+        ",
+    },
+];
+
+/// Highest schema version this build knows how to produce.
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Schema version recorded in the database (0 for a fresh or pre-versioning file).
+pub async fn current_version(pool: &SqlitePool) -> Result<i64, String> {
+    let row: (Option<i64>,) = sqlx::query_as("SELECT MAX(version) FROM schema_version")
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(row.0.unwrap_or(0))
+}
+
+/// Brings the database up to `latest_version()`.
+/// Each step runs in its own transaction together with its `schema_version` row,
+/// so a failing migration leaves the database at the previous version.
+/// Refuses to touch a database written by a newer build.
+pub async fn run(pool: &SqlitePool) -> Result<i64, String> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )"
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let current = current_version(pool).await?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(format!(
+            "Database schema is version {} but this build only supports up to version {}. Please update the application.",
+            current, latest
+        ));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+        sqlx::raw_sql(migration.sql)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Migration {} ({}) failed: {}", migration.version, migration.name, e))?;
+
+        sqlx::query("INSERT INTO schema_version (version, name) VALUES (?, ?)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        tx.commit().await.map_err(|e| e.to_string())?;
+        println!("🛠️  Applied migration {} ({})", migration.version, migration.name);
+    }
+
+    Ok(latest)
+}
+
+// A single connection keeps every query on the same in-memory database.
+#[cfg(test)]
+async fn memory_pool() -> SqlitePool {
+    sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap()
+}
+
+/// An in-memory database at the latest schema, for tests in any module.
+#[cfg(test)]
+pub(crate) async fn test_pool() -> SqlitePool {
+    let pool = memory_pool().await;
+    run(&pool).await.unwrap();
+    pool
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_sequential() {
+        for (i, m) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(m.version, i as i64 + 1, "migration '{}' is out of order", m.name);
+        }
+    }
+
+    #[tokio::test]
+    async fn fresh_database_reaches_latest() {
+        let pool = memory_pool().await;
+        assert_eq!(run(&pool).await.unwrap(), latest_version());
+        assert_eq!(current_version(&pool).await.unwrap(), latest_version());
+    }
+
+    #[tokio::test]
+    async fn rerun_is_a_no_op() {
+        let pool = test_pool().await;
+        run(&pool).await.unwrap();
+
+        let rows: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM schema_version")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(rows.0, MIGRATIONS.len() as i64);
+    }
+
+    #[tokio::test]
+    async fn migrates_pre_versioning_database_forward() {
+        let pool = memory_pool().await;
+
+        // Layout written by the old inline CREATE TABLE calls in main(), with live data.
+        sqlx::raw_sql(
+            "CREATE TABLE patients (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, birth_date TEXT NOT NULL, phone TEXT NOT NULL, email TEXT, address TEXT NOT NULL, city TEXT NOT NULL, state TEXT NOT NULL, postal_code TEXT NOT NULL, health_card_num TEXT NOT NULL, allergies TEXT, insurance_provider TEXT, insurance_id TEXT);
+             CREATE TABLE medications (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, din TEXT UNIQUE NOT NULL, ndc TEXT, description TEXT, stock INTEGER DEFAULT 0, price REAL DEFAULT 0.0, expiration TEXT NOT NULL);
+             CREATE TABLE prescriptions (id INTEGER PRIMARY KEY AUTOINCREMENT, patient_id INTEGER NOT NULL, medication_id INTEGER NOT NULL, prescriber TEXT NOT NULL, sig TEXT NOT NULL, quantity INTEGER NOT NULL, refills INTEGER NOT NULL, days_supply INTEGER NOT NULL, date_filled TEXT NOT NULL, next_refill_date TEXT NOT NULL, FOREIGN KEY(patient_id) REFERENCES patients(id), FOREIGN KEY(medication_id) REFERENCES medications(id));
+             CREATE TABLE users (id INTEGER PRIMARY KEY AUTOINCREMENT, username TEXT UNIQUE NOT NULL, password TEXT NOT NULL, role TEXT NOT NULL);
+             CREATE TABLE audit_logs (id INTEGER PRIMARY KEY AUTOINCREMENT, username TEXT NOT NULL, action TEXT NOT NULL, details TEXT, timestamp DATETIME DEFAULT CURRENT_TIMESTAMP);
+             INSERT INTO patients (name, birth_date, phone, address, city, state, postal_code, health_card_num) VALUES ('John Smith', '1985-04-12', '416-555-0199', '123 Maple Dr', 'Toronto', 'ON', 'M5V 2T6', '123-456-789-AB');
+             INSERT INTO medications (name, din, stock, price, expiration) VALUES ('Amoxicillin 500mg', '02238888', 500, 12.99, '2025-12-31');
+             INSERT INTO prescriptions (patient_id, medication_id, prescriber, sig, quantity, refills, days_supply, date_filled, next_refill_date) VALUES (1, 1, 'Dr. Hibbert', 'Take 1 capsule TID', 30, 0, 10, '2023-11-01', '2023-11-11');
+             INSERT INTO users (username, password, role) VALUES ('admin', 'admin', 'admin');"
+        )
+        .execute(&pool).await.unwrap();
+
+        assert_eq!(run(&pool).await.unwrap(), latest_version());
+
+        let patients: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM patients").fetch_one(&pool).await.unwrap();
+        let rxs: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM prescriptions").fetch_one(&pool).await.unwrap();
+        let users: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users").fetch_one(&pool).await.unwrap();
+        assert_eq!((patients.0, rxs.0, users.0), (1, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn collapses_legacy_refill_chains_into_fills() {
+        let pool = memory_pool().await;
+
+        // Stop just before the fills migration
+        sqlx::raw_sql("CREATE TABLE schema_version (version INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at DATETIME)")
+            .execute(&pool).await.unwrap();
+        for m in MIGRATIONS.iter().filter(|m| m.version < 7) {
+            sqlx::raw_sql(m.sql).execute(&pool).await.unwrap();
+            sqlx::query("INSERT INTO schema_version (version, name) VALUES (?, ?)")
+                .bind(m.version).bind(m.name)
+                .execute(&pool).await.unwrap();
+        }
+
+        // Original with 2 refills, then a refill the old dashboard wrote as a new row
+        // with 1 refill left, an unrelated single prescription, and a renewal of
+        // the first written later with the same sig.
+        sqlx::raw_sql(
+            "INSERT INTO patients (name, birth_date, phone, address, city, state, postal_code, health_card_num) VALUES ('John Smith', '1985-04-12', '416-555-0199', '123 Maple Dr', 'Toronto', 'ON', 'M5V 2T6', '123-456-789-AB');
+             INSERT INTO medications (name, din, stock, price, expiration) VALUES ('Atorvastatin 20mg', '02245555', 200, 45.50, '2026-06-15');
+             INSERT INTO prescriptions (patient_id, medication_id, prescriber, sig, quantity, refills, days_supply, date_filled, next_refill_date, filled_by, verified_by)
+                VALUES (1, 1, 'Dr. Nick', 'Take 1 tablet daily', 30, 2, 30, '2023-10-01', '2023-10-31', 'tech', 'pharm'),
+                       (1, 1, 'Dr. Nick', 'Take 1 tablet daily', 30, 1, 30, '2023-10-31', '2023-11-30', 'tech', NULL),
+                       (1, 1, 'Dr. House', 'Take 2 tablets daily', 60, 0, 30, '2023-11-05', '2023-12-05', 'tech', NULL),
+                       (1, 1, 'Dr. Nick', 'Take 1 tablet daily', 30, 5, 30, '2024-01-15', '2024-02-14', 'tech', NULL);"
+        )
+        .execute(&pool).await.unwrap();
+
+        run(&pool).await.unwrap();
+
+        let rxs: Vec<(i64, i64, i64, String, String)> = sqlx::query_as(
+            "SELECT id, refills, refills_remaining, next_refill_date, expires_on FROM prescriptions ORDER BY id"
+        ).fetch_all(&pool).await.unwrap();
+        assert_eq!(rxs, vec![
+            (1, 2, 1, "2023-11-30".to_string(), "2024-09-30".to_string()),
+            (3, 0, 0, "2023-12-05".to_string(), "2024-11-04".to_string()),
+            (4, 5, 5, "2024-02-14".to_string(), "2025-01-14".to_string()),
+        ]);
+
+        let fills: Vec<(i64, i64, Option<String>)> = sqlx::query_as(
+            "SELECT prescription_id, fill_number, verified_by FROM fills ORDER BY prescription_id, fill_number"
+        ).fetch_all(&pool).await.unwrap();
+        assert_eq!(fills, vec![(1, 0, Some("pharm".to_string())), (1, 1, None), (3, 0, None), (4, 0, None)]);
+    }
+
+    #[tokio::test]
+    async fn refuses_newer_database() {
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO schema_version (version, name) VALUES (?, 'from the future')")
+            .bind(latest_version() + 1)
+            .execute(&pool).await.unwrap();
+
+        let err = run(&pool).await.unwrap_err();
+        assert!(err.contains("only supports"), "unexpected error: {}", err);
+    }
+}
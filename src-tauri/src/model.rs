@@ -4,7 +4,6 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CreatePatientDto {
-    pub logged_in_user: String,
     pub name: String,
     pub birth_date: String,
     pub phone: String,
@@ -14,6 +13,7 @@ pub struct CreatePatientDto {
     pub state: String,
     pub postal_code: String,
     pub health_card_num: String,
+    /// Free text on the intake form; split into structured allergy records on create.
     pub allergies: Option<String>,
     pub insurance_provider: Option<String>,
     pub insurance_id: Option<String>,
@@ -36,21 +36,78 @@ pub struct Patient {
     pub insurance_id: Option<String>,
 }
 
+/// Full replacement of a profile's editable fields. `allergies` is ignored here;
+/// allergy records are edited through their own commands.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdatePatientDto {
+    pub id: i64,
+    #[serde(flatten)]
+    pub fields: CreatePatientDto,
+}
+
+/// A pair of profiles that look like the same person. `reason` is
+/// "health_card_num" or "name_and_birth_date".
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct DuplicatePatientPair {
+    pub patient_id: i64,
+    pub patient_name: String,
+    pub duplicate_id: i64,
+    pub duplicate_name: String,
+    pub reason: String,
+}
+
+/// Every field is optional; supplied fields are combined with AND.
+/// `text` is the free-form search box (matches patient name or any drug they were dispensed).
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct PatientSearchFilter {
+    pub text: Option<String>,
+    pub name: Option<String>,
+    pub birth_date: Option<String>,
+    pub phone: Option<String>,
+    pub health_card_num: Option<String>,
+    pub drug: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PatientAllergy {
+    pub id: i64,
+    pub patient_id: i64,
+    pub allergen: String,
+    pub reaction: Option<String>,
+    pub severity: String,
+    pub recorded_at: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateAllergyDto {
+    pub patient_id: i64,
+    pub allergen: String,
+    pub reaction: Option<String>,
+    pub severity: String,
+}
+
+/// One row per fill; `id` is the fill.
 #[derive(Debug, Serialize, sqlx::FromRow)]
 pub struct PatientHistoryItem {
     pub id: i64,
+    pub prescription_id: i64,
+    pub fill_number: i64,
+    /// Current status of the prescription the fill belongs to.
+    pub status: String,
     pub drug_name: String,
+    /// Set for scheduled drugs; voiding their fills needs a witness.
+    pub schedule: Option<String>,
     pub sig: String,
     pub quantity: i32,
     pub date_filled: String,
     pub next_refill_date: String,
+    pub voided_at: Option<String>,
 }
 
 // --- MEDICATION MODELS ---
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CreateMedicationDto {
-    pub logged_in_user: String,
     pub name: String,
     pub din: String,
     pub ndc: Option<String>,
@@ -58,16 +115,40 @@ pub struct CreateMedicationDto {
     pub stock: i32,
     pub price: f64,
     pub expiration: String,
+    #[serde(default)]
+    pub controlled: bool,
+    /// Lot number for the opening stock; a placeholder is used when blank.
+    #[serde(default)]
+    pub lot_number: Option<String>,
+    #[serde(default)]
+    pub reorder_point: Option<i32>,
+    #[serde(default)]
+    pub reorder_qty: Option<i32>,
+    #[serde(default)]
+    pub auto_reorder: bool,
+    /// narcotic, controlled or targeted; scheduled drugs are always flagged controlled.
+    #[serde(default)]
+    pub schedule: Option<String>,
 }
 
-// NEW: For editing stock/price
+// For editing price/description. Stock only changes through lots.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct UpdateMedicationDto {
-    pub logged_in_user: String,
     pub id: i64,
-    pub stock: i32,
     pub price: f64,
     pub description: Option<String>,
+    /// Left unchanged when omitted (as are the reorder fields).
+    #[serde(default)]
+    pub controlled: Option<bool>,
+    #[serde(default)]
+    pub reorder_point: Option<i32>,
+    #[serde(default)]
+    pub reorder_qty: Option<i32>,
+    #[serde(default)]
+    pub auto_reorder: Option<bool>,
+    /// An empty string clears the schedule.
+    #[serde(default)]
+    pub schedule: Option<String>,
 }
 
 #[derive(Debug, Serialize, sqlx::FromRow)]
@@ -80,13 +161,303 @@ pub struct Medication {
     pub stock: i32,
     pub price: f64,
     pub expiration: String,
+    pub controlled: bool,
+    /// Reorder once stock falls to this level.
+    pub reorder_point: i32,
+    pub reorder_qty: i32,
+    /// Work both out from recent dispensing instead of using the stored values.
+    pub auto_reorder: bool,
+    pub schedule: Option<String>,
+}
+
+/// A drug at or below its reorder point.
+#[derive(Debug, Serialize)]
+pub struct LowStockItem {
+    pub medication_id: i64,
+    pub name: String,
+    pub din: String,
+    pub stock: i32,
+    /// Effective values: computed from velocity when auto reorder is on and there is history.
+    pub reorder_point: i32,
+    pub reorder_qty: i32,
+    pub auto_reorder: bool,
+    /// Average units dispensed per day over the velocity window.
+    pub daily_usage: f64,
+    /// None when nothing has been dispensed in the window.
+    pub days_of_cover: Option<f64>,
+    pub suggested_qty: i32,
+}
+
+/// An active ingredient ("amoxicillin") or drug class ("penicillin") a medication
+/// belongs to. Allergy checks match allergens against these names.
+#[derive(Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct IngredientTag {
+    pub name: String,
+    pub kind: String,
+}
+
+/// One row of the interaction table, also the import format.
+/// `term_a`/`term_b` are ingredient or class names.
+#[derive(Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct InteractionRecord {
+    pub term_a: String,
+    pub term_b: String,
+    pub severity: String,
+    pub monograph: String,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct MedicationLot {
+    pub id: i64,
+    pub medication_id: i64,
+    pub lot_number: String,
+    pub expiration: String,
+    pub quantity: i32,
+    pub received_at: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReceiveLotDto {
+    pub medication_id: i64,
+    pub lot_number: String,
+    pub expiration: String,
+    pub quantity: i32,
+}
+
+/// A fill that drew from a given lot, for recall lookups.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct LotFillItem {
+    pub fill_id: i64,
+    pub prescription_id: i64,
+    pub patient_id: i64,
+    pub patient_name: String,
+    pub phone: String,
+    pub quantity: i32,
+    pub date_filled: String,
+    pub voided_at: Option<String>,
+}
+
+/// One row of the stock ledger. `quantity` is signed; `balance` is the drug's
+/// total on hand after this movement.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StockMovement {
+    pub id: i64,
+    pub lot_id: i64,
+    pub lot_number: String,
+    pub kind: String,
+    pub quantity: i32,
+    pub reason_code: String,
+    pub reference_id: Option<i64>,
+    pub username: String,
+    pub note: Option<String>,
+    pub witness: Option<String>,
+    pub created_at: String,
+    pub balance: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StockDiscrepancy {
+    pub medication_id: i64,
+    pub medication_name: String,
+    pub lot_id: i64,
+    pub lot_number: String,
+    pub on_hand: i64,
+    pub ledger: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdjustmentReason {
+    pub code: &'static str,
+    pub label: &'static str,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct CountSession {
+    pub id: i64,
+    /// Shelf location counted; None for a full count.
+    pub location: Option<String>,
+    pub status: String,
+    pub started_at: String,
+    pub started_by: String,
+    pub posted_at: Option<String>,
+    pub approved_by: Option<String>,
+    pub line_count: i64,
+    pub counted_lines: i64,
+}
+
+/// One row of a count's variance report. `variance` and `variance_value`
+/// (at the drug's price) are None until the line is counted.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct CountLineItem {
+    pub id: i64,
+    pub lot_id: i64,
+    pub medication_id: i64,
+    pub medication_name: String,
+    pub din: String,
+    pub schedule: Option<String>,
+    pub location: Option<String>,
+    pub lot_number: String,
+    pub expiration: String,
+    pub expected: i32,
+    pub counted: Option<i32>,
+    pub variance: Option<i32>,
+    pub variance_value: Option<f64>,
+    pub counted_by: Option<String>,
+}
+
+/// A lot with stock left that is expired or inside the expiry warning window.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExpiringLotItem {
+    pub lot_id: i64,
+    pub medication_id: i64,
+    pub medication_name: String,
+    pub din: String,
+    pub lot_number: String,
+    pub expiration: String,
+    pub quantity: i32,
+    pub expired: bool,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct QuarantineItem {
+    pub id: i64,
+    pub lot_id: i64,
+    pub medication_name: String,
+    pub lot_number: String,
+    pub expiration: String,
+    pub quantity: i32,
+    pub reason: String,
+    pub quarantined_at: String,
+    pub quarantined_by: String,
+    pub disposition: String,
+    pub resolved_at: Option<String>,
+    pub resolved_by: Option<String>,
+    pub witness: Option<String>,
+}
+
+// --- CONTROLLED SUBSTANCE MODELS ---
+
+/// A second staff member signing off on a controlled substance movement.
+#[derive(Debug, Deserialize)]
+pub struct WitnessDto {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduleOption {
+    pub code: &'static str,
+    pub label: &'static str,
+}
+
+/// One line of a drug's perpetual register. `movement_id` is empty for
+/// register openings.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct RegisterEntry {
+    pub id: i64,
+    pub movement_id: Option<i64>,
+    pub kind: String,
+    pub quantity: i32,
+    pub balance: i64,
+    pub lot_number: Option<String>,
+    pub reason_code: String,
+    pub reference_id: Option<i64>,
+    pub recorded_by: String,
+    pub witness: Option<String>,
+    pub created_at: String,
+}
+
+/// Register balance against stock on hand for a scheduled drug.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct RegisterReconciliation {
+    pub medication_id: i64,
+    pub name: String,
+    pub din: String,
+    pub schedule: String,
+    pub stock: i64,
+    /// None when the register has never been opened.
+    pub register_balance: Option<i64>,
+    pub last_entry_at: Option<String>,
+    pub discrepancy: bool,
+}
+
+// --- PURCHASING MODELS ---
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Supplier {
+    pub id: i64,
+    pub name: String,
+    pub contact: Option<String>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    pub active: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateSupplierDto {
+    pub name: String,
+    pub contact: Option<String>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PoLineDto {
+    pub medication_id: i64,
+    pub quantity: i32,
+    /// Expected cost; the actual cost is recorded on receipt.
+    #[serde(default)]
+    pub unit_cost: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreatePurchaseOrderDto {
+    pub supplier_id: i64,
+    pub notes: Option<String>,
+    pub lines: Vec<PoLineDto>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PurchaseOrderSummary {
+    pub id: i64,
+    pub supplier_id: i64,
+    pub supplier_name: String,
+    pub status: String,
+    pub notes: Option<String>,
+    pub created_at: String,
+    pub created_by: String,
+    pub ordered_at: Option<String>,
+    pub line_count: i64,
+    pub units_ordered: i64,
+    pub units_received: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PurchaseOrderLine {
+    pub id: i64,
+    pub purchase_order_id: i64,
+    pub medication_id: i64,
+    pub medication_name: String,
+    pub din: String,
+    pub quantity_ordered: i32,
+    pub quantity_received: i32,
+    pub unit_cost: Option<f64>,
+}
+
+/// One delivery against a PO line; may be less than what is outstanding.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReceivePoLineDto {
+    pub line_id: i64,
+    pub quantity: i32,
+    pub lot_number: String,
+    pub expiration: String,
+    pub unit_cost: f64,
 }
 
 // --- PRESCRIPTION MODELS ---
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CreatePrescriptionDto {
-    pub logged_in_user: String,
     pub patient_id: i64,
     pub medication_id: i64,
     pub prescriber: String,
@@ -95,6 +466,12 @@ pub struct CreatePrescriptionDto {
     pub refills: i32,
     pub days_supply: i32,
     pub date_filled: String,
+    /// Required when clinical screening raises a blocking alert.
+    #[serde(default)]
+    pub override_reason: Option<String>,
+    /// Required when filling before the too-soon date.
+    #[serde(default)]
+    pub early_fill_code: Option<String>,
 }
 
 #[derive(Debug, Serialize, sqlx::FromRow)]
@@ -108,9 +485,27 @@ pub struct Prescription {
     pub sig: String,
     pub quantity: i32,
     pub refills: i32,
+    pub refills_remaining: i32,
     pub days_supply: i32,
     pub date_filled: String,
     pub next_refill_date: String,
+    pub expires_on: Option<String>,
+    pub status: String,
+    pub status_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PendingVerificationItem {
+    /// The fill awaiting sign-off.
+    pub id: i64,
+    pub prescription_id: i64,
+    pub fill_number: i64,
+    pub patient_name: String,
+    pub medication_name: String,
+    pub sig: String,
+    pub quantity: i32,
+    pub date_filled: String,
+    pub filled_by: Option<String>,
 }
 
 // --- DASHBOARD MODELS ---
@@ -120,6 +515,8 @@ pub struct DashboardStats {
     pub due_today: i64,
     pub due_soon: i64,
     pub low_stock: i64,
+    /// Lots expired or expiring within the warning window.
+    pub expiring: i64,
 }
 
 #[derive(Debug, Serialize, sqlx::FromRow)]
@@ -135,6 +532,8 @@ pub struct DueRxItem {
     pub sig: String,
     pub days_supply: i32,
     pub refills: i32,
+    pub refills_remaining: i32,
+    pub expires_on: Option<String>,
     pub prescriber: String,
 }
 
@@ -151,6 +550,43 @@ pub struct AuthResponse {
     pub success: bool,
     pub role: String, 
     pub username: String,
+    pub must_change_password: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChangePasswordDto {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+// Internal: credential columns read during login (never sent to the UI)
+#[derive(Debug, sqlx::FromRow)]
+pub struct UserCredentials {
+    pub id: i64,
+    pub password_hash: String,
+    pub role: String,
+    pub must_change_password: bool,
+    pub is_locked: bool,
+    pub active: bool,
+}
+
+// --- USER MANAGEMENT MODELS ---
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateUserDto {
+    pub username: String,
+    pub password: String,
+    pub role: String,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct UserSummary {
+    pub id: i64,
+    pub username: String,
+    pub role: String,
+    pub active: bool,
+    pub must_change_password: bool,
+    pub is_locked: bool,
 }
 
 #[derive(Debug, Serialize, sqlx::FromRow)]
@@ -160,4 +596,57 @@ pub struct AuditLogItem {
     pub action: String,
     pub details: Option<String>,
     pub timestamp: String,
-}
\ No newline at end of file
+    pub entity_type: Option<String>,
+    pub entity_id: Option<i64>,
+    pub before_json: Option<String>,
+    pub after_json: Option<String>,
+    pub hash: Option<String>,
+}
+
+/// Audit log viewer and export filters. Every field is optional; supplied
+/// fields are combined with AND. Dates are YYYY-MM-DD and inclusive.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct AuditLogFilter {
+    pub username: Option<String>,
+    /// Exact action code, e.g. FILL_RX.
+    pub action: Option<String>,
+    pub from_date: Option<String>,
+    pub to_date: Option<String>,
+    /// Free text matched against the details.
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogPage {
+    pub items: Vec<AuditLogItem>,
+    /// Pass back as `before_id` for the next (older) page; empty on the last page.
+    pub next_cursor: Option<i64>,
+}
+
+/// Result of walking the audit hash chain.
+#[derive(Debug, Serialize)]
+pub struct AuditChainReport {
+    pub rows_checked: i64,
+    pub intact: bool,
+    pub first_broken_id: Option<i64>,
+    pub problem: Option<String>,
+    /// Hash of the newest row when the chain is intact. Noting it down lets a
+    /// later check detect rows removed from the end.
+    pub latest_hash: Option<String>,
+}
+
+// --- SETTINGS MODELS ---
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Setting {
+    pub key: String,
+    pub value: String,
+    pub updated_at: Option<String>,
+    pub updated_by: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EarlyFillReason {
+    pub code: &'static str,
+    pub label: &'static str,
+}
@@ -0,0 +1,181 @@
+use sqlx::SqliteConnection;
+
+use crate::clinical;
+use crate::error::AppError;
+use crate::model::{DuplicatePatientPair, Patient};
+
+// =====================================================
+// PATIENT PROFILES
+// =====================================================
+// The same person entered twice shows up as two profiles with split
+// histories. Pairs are found by health card number or by name and birth date;
+// a merge folds one profile into the other.
+
+/// Profiles that look like the same person. Health card numbers are compared
+/// without separators; names ignore case and padding.
+pub async fn find_duplicates(conn: &mut SqliteConnection) -> Result<Vec<DuplicatePatientPair>, sqlx::Error> {
+    sqlx::query_as::<_, DuplicatePatientPair>(
+        "SELECT a.id AS patient_id, a.name AS patient_name, b.id AS duplicate_id, b.name AS duplicate_name,
+            CASE WHEN REPLACE(REPLACE(UPPER(a.health_card_num), '-', ''), ' ', '') = REPLACE(REPLACE(UPPER(b.health_card_num), '-', ''), ' ', '')
+                 THEN 'health_card_num' ELSE 'name_and_birth_date' END AS reason
+         FROM patients a
+         JOIN patients b ON a.id < b.id
+         WHERE (LOWER(TRIM(a.name)) = LOWER(TRIM(b.name)) AND a.birth_date = b.birth_date)
+            OR (TRIM(a.health_card_num) != ''
+                AND REPLACE(REPLACE(UPPER(a.health_card_num), '-', ''), ' ', '') = REPLACE(REPLACE(UPPER(b.health_card_num), '-', ''), ' ', ''))
+         ORDER BY a.id, b.id"
+    )
+    .fetch_all(&mut *conn)
+    .await
+}
+
+/// Both profiles as they were before a merge, for the audit trail.
+pub struct Merged {
+    pub survivor: Patient,
+    pub duplicate: Patient,
+    pub prescriptions_moved: u64,
+}
+
+/// Folds `duplicate_id` into `survivor_id`: prescriptions are re-pointed, blank
+/// contact/insurance fields are filled from the duplicate, allergy records are
+/// moved across, and the duplicate profile is removed. Must run inside the
+/// caller's transaction.
+pub async fn merge(conn: &mut SqliteConnection, survivor_id: i64, duplicate_id: i64) -> Result<Merged, AppError> {
+    if survivor_id == duplicate_id {
+        return Err(AppError::validation("duplicate_id", "Cannot merge a patient into itself"));
+    }
+
+    let survivor = sqlx::query_as::<_, Patient>("SELECT * FROM patients WHERE id = ?")
+        .bind(survivor_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AppError::not_found("Surviving patient"))?;
+    let duplicate = sqlx::query_as::<_, Patient>("SELECT * FROM patients WHERE id = ?")
+        .bind(duplicate_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AppError::not_found("Duplicate patient"))?;
+
+    let prescriptions_moved = sqlx::query("UPDATE prescriptions SET patient_id = ? WHERE patient_id = ?")
+        .bind(survivor_id).bind(duplicate_id)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+
+    // Never lose an allergy recorded on either profile; skip ones the survivor already has
+    sqlx::query(
+        "UPDATE patient_allergies SET patient_id = ?
+         WHERE patient_id = ? AND LOWER(TRIM(allergen)) NOT IN (
+            SELECT LOWER(TRIM(allergen)) FROM patient_allergies WHERE patient_id = ?
+         )"
+    )
+    .bind(survivor_id).bind(duplicate_id).bind(survivor_id)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("DELETE FROM patient_allergies WHERE patient_id = ?")
+        .bind(duplicate_id)
+        .execute(&mut *conn)
+        .await?;
+    clinical::refresh_allergy_summary(conn, survivor_id).await?;
+
+    // The form saves an untouched optional field as an empty string
+    sqlx::query(
+        "UPDATE patients SET email = COALESCE(NULLIF(TRIM(email), ''), ?),
+            insurance_provider = COALESCE(NULLIF(TRIM(insurance_provider), ''), ?),
+            insurance_id = COALESCE(NULLIF(TRIM(insurance_id), ''), ?)
+         WHERE id = ?"
+    )
+    .bind(&duplicate.email)
+    .bind(&duplicate.insurance_provider).bind(&duplicate.insurance_id)
+    .bind(survivor_id)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("DELETE FROM patients WHERE id = ?")
+        .bind(duplicate_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(Merged { survivor, duplicate, prescriptions_moved })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::test_pool;
+    use sqlx::SqlitePool;
+
+    async fn patients_pool() -> SqlitePool {
+        let pool = test_pool().await;
+        let patients = [
+            ("John Smith", "1985-04-12", "123-456-789-AB", Some("john@example.com"), Some(""), None),
+            ("  JOHN SMITH ", "1985-04-12", "X", None, None, None),
+            ("Jon Smith", "1990-01-01", "123 456 789 ab", Some("jon@example.com"), Some("Sun Life"), Some("SL-1")),
+            ("Jane Roe", "1970-01-01", "", None, None, None),
+            ("Jim Poe", "1971-01-01", " ", None, None, None),
+        ];
+        for (name, birth_date, card, email, provider, insurance_id) in patients {
+            sqlx::query(
+                "INSERT INTO patients (name, birth_date, phone, email, address, city, state, postal_code, health_card_num, insurance_provider, insurance_id)
+                 VALUES (?, ?, '416-555-0199', ?, '1 Main St', 'Toronto', 'ON', 'M5V 2T6', ?, ?, ?)"
+            )
+            .bind(name).bind(birth_date).bind(email).bind(card).bind(provider).bind(insurance_id)
+            .execute(&pool).await.unwrap();
+        }
+        sqlx::raw_sql(
+            "INSERT INTO medications (name, din, stock, price, expiration) VALUES ('Amoxicillin 500mg', '02238888', 0, 1.0, '2099-12-31');
+             INSERT INTO prescriptions (patient_id, medication_id, prescriber, sig, quantity, refills, days_supply, date_filled, next_refill_date)
+                VALUES (1, 1, 'Dr. Nick', 'Take 1 capsule TID', 30, 0, 10, '2024-01-01', '2024-01-11'),
+                       (3, 1, 'Dr. Nick', 'Take 1 capsule TID', 30, 0, 10, '2024-02-01', '2024-02-11'),
+                       (3, 1, 'Dr. Nick', 'Take 1 capsule TID', 30, 0, 10, '2024-03-01', '2024-03-11');
+             INSERT INTO patient_allergies (patient_id, allergen) VALUES (1, 'Penicillin'), (3, 'penicillin '), (3, 'Latex');"
+        )
+        .execute(&pool).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn duplicates_by_card_or_name_and_birth_date() {
+        let pool = patients_pool().await;
+        let pairs = find_duplicates(&mut pool.acquire().await.unwrap()).await.unwrap();
+        let pairs: Vec<(i64, i64, &str)> = pairs.iter().map(|p| (p.patient_id, p.duplicate_id, p.reason.as_str())).collect();
+
+        // Blank cards never pair up
+        assert_eq!(pairs, [(1, 2, "name_and_birth_date"), (1, 3, "health_card_num")]);
+    }
+
+    #[tokio::test]
+    async fn merge_folds_the_duplicate_into_the_survivor() {
+        let pool = patients_pool().await;
+        let merged = merge(&mut pool.acquire().await.unwrap(), 1, 3).await.unwrap();
+        assert_eq!((merged.survivor.name.as_str(), merged.duplicate.name.as_str(), merged.prescriptions_moved), ("John Smith", "Jon Smith", 2));
+
+        let (owners,): (String,) = sqlx::query_as("SELECT GROUP_CONCAT(patient_id) FROM prescriptions").fetch_one(&pool).await.unwrap();
+        assert_eq!(owners, "1,1,1");
+
+        let allergies: Vec<(String,)> = sqlx::query_as("SELECT allergen FROM patient_allergies ORDER BY id").fetch_all(&pool).await.unwrap();
+        assert_eq!(allergies, vec![("Penicillin".to_string(),), ("Latex".to_string(),)]);
+
+        // Survivor's own values win; blank and missing ones come from the duplicate
+        let survivor: (Option<String>, Option<String>, Option<String>, Option<String>) = sqlx::query_as(
+            "SELECT email, insurance_provider, insurance_id, allergies FROM patients WHERE id = 1"
+        )
+        .fetch_one(&pool).await.unwrap();
+        assert_eq!(survivor, (
+            Some("john@example.com".to_string()), Some("Sun Life".to_string()), Some("SL-1".to_string()), Some("Penicillin, Latex".to_string()),
+        ));
+
+        let (left,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM patients WHERE id = 3").fetch_one(&pool).await.unwrap();
+        assert_eq!(left, 0);
+    }
+
+    #[tokio::test]
+    async fn merge_needs_two_existing_profiles() {
+        let pool = patients_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        assert!(matches!(merge(&mut conn, 1, 1).await, Err(AppError::Validation(_))));
+        assert!(matches!(merge(&mut conn, 1, 99).await, Err(AppError::NotFound(_))));
+        assert!(matches!(merge(&mut conn, 99, 1).await, Err(AppError::NotFound(_))));
+    }
+}
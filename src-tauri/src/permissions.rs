@@ -0,0 +1,123 @@
+// =====================================================
+// ROLES & CAPABILITIES
+// =====================================================
+// Commands ask for a capability, never for a role, so adding a role or moving
+// a capability between roles is a change to this file only.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    PatientsRead,
+    PatientsWrite,
+    PatientsMerge,
+    InventoryRead,
+    InventoryAdjust,
+    /// Sign off stock postings such as count variances.
+    InventoryApprove,
+    RxRead,
+    RxFill,
+    RxVerify,
+    InteractionsManage,
+    SettingsManage,
+    AuditRead,
+    UsersManage,
+}
+
+impl Capability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Capability::PatientsRead => "patients.read",
+            Capability::PatientsWrite => "patients.write",
+            Capability::PatientsMerge => "patients.merge",
+            Capability::InventoryRead => "inventory.read",
+            Capability::InventoryAdjust => "inventory.adjust",
+            Capability::InventoryApprove => "inventory.approve",
+            Capability::RxRead => "rx.read",
+            Capability::RxFill => "rx.fill",
+            Capability::RxVerify => "rx.verify",
+            Capability::InteractionsManage => "interactions.manage",
+            Capability::SettingsManage => "settings.manage",
+            Capability::AuditRead => "audit.read",
+            Capability::UsersManage => "users.manage",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Admin,
+    Pharmacist,
+    Tech,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Admin, Role::Pharmacist, Role::Tech];
+
+    pub fn parse(s: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|r| r.as_str() == s)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Pharmacist => "pharmacist",
+            Role::Tech => "tech",
+        }
+    }
+
+    pub fn capabilities(&self) -> &'static [Capability] {
+        use Capability::*;
+        match self {
+            // Admins run the store but are not licensed to sign off on fills
+            Role::Admin => &[PatientsRead, PatientsWrite, PatientsMerge, InventoryRead, InventoryAdjust, RxRead, RxFill, SettingsManage, AuditRead, UsersManage],
+            Role::Pharmacist => &[PatientsRead, PatientsWrite, PatientsMerge, InventoryRead, InventoryAdjust, InventoryApprove, RxRead, RxFill, RxVerify, InteractionsManage, SettingsManage],
+            Role::Tech => &[PatientsRead, PatientsWrite, InventoryRead, InventoryAdjust, RxRead, RxFill],
+        }
+    }
+
+    pub fn can(&self, capability: Capability) -> bool {
+        self.capabilities().contains(&capability)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SessionStore;
+    use Capability::*;
+
+    const EVERY: [Capability; 13] = [
+        PatientsRead, PatientsWrite, PatientsMerge, InventoryRead, InventoryAdjust, InventoryApprove,
+        RxRead, RxFill, RxVerify, InteractionsManage, SettingsManage, AuditRead, UsersManage,
+    ];
+
+    fn holders(capability: Capability) -> Vec<&'static str> {
+        Role::ALL.into_iter().filter(|r| r.can(capability)).map(|r| r.as_str()).collect()
+    }
+
+    #[test]
+    fn sign_offs_are_pharmacist_only() {
+        assert_eq!(holders(RxVerify), ["pharmacist"]);
+        assert_eq!(holders(InventoryApprove), ["pharmacist"]);
+        assert_eq!(holders(InteractionsManage), ["pharmacist"]);
+    }
+
+    #[test]
+    fn role_matrix() {
+        assert_eq!(holders(UsersManage), ["admin"]);
+        assert_eq!(holders(AuditRead), ["admin"]);
+        assert_eq!(holders(PatientsMerge), ["admin", "pharmacist"]);
+        assert_eq!(holders(SettingsManage), ["admin", "pharmacist"]);
+        for everyone in [PatientsRead, PatientsWrite, InventoryRead, InventoryAdjust, RxRead, RxFill] {
+            assert_eq!(holders(everyone), ["admin", "pharmacist", "tech"], "{}", everyone.as_str());
+        }
+    }
+
+    #[test]
+    fn unknown_roles_get_nothing() {
+        assert_eq!(Role::parse("owner"), None);
+        assert_eq!(Role::parse("Pharmacist"), None);
+
+        let session = SessionStore::default().start(1, "owner", "owner", false);
+        assert!(EVERY.iter().all(|&c| !session.can(c)));
+    }
+}
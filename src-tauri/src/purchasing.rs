@@ -0,0 +1,181 @@
+use sqlx::SqliteConnection;
+
+use crate::error::AppError;
+use crate::inventory::{self, Movement};
+use crate::model::{PoLineDto, ReceivePoLineDto};
+
+// =====================================================
+// PURCHASING
+// =====================================================
+// draft -> ordered -> partially_received -> received. A draft or ordered PO
+// with nothing received can be cancelled. Stock only goes up on receipt.
+
+/// Statuses a PO can be received against.
+pub const RECEIVABLE: [&str; 2] = ["ordered", "partially_received"];
+
+/// Creates a draft PO. Must run inside the caller's transaction.
+pub async fn create_order(
+    conn: &mut SqliteConnection,
+    supplier_id: i64,
+    notes: Option<&str>,
+    lines: &[PoLineDto],
+    created_by: &str,
+) -> Result<i64, AppError> {
+    let supplier: (bool,) = sqlx::query_as("SELECT active FROM suppliers WHERE id = ?")
+        .bind(supplier_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AppError::not_found("Supplier"))?;
+    if !supplier.0 {
+        return Err(AppError::Conflict("That supplier is no longer active".to_string()));
+    }
+
+    let po_id = sqlx::query("INSERT INTO purchase_orders (supplier_id, notes, created_by) VALUES (?, ?, ?)")
+        .bind(supplier_id).bind(notes).bind(created_by)
+        .execute(&mut *conn)
+        .await?
+        .last_insert_rowid();
+
+    for line in lines {
+        sqlx::query(
+            "INSERT INTO purchase_order_lines (purchase_order_id, medication_id, quantity_ordered, unit_cost) VALUES (?, ?, ?, ?)"
+        )
+        .bind(po_id).bind(line.medication_id).bind(line.quantity).bind(line.unit_cost)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(po_id)
+}
+
+#[derive(sqlx::FromRow)]
+struct LineToReceive {
+    purchase_order_id: i64,
+    medication_id: i64,
+    medication_name: String,
+    quantity_ordered: i32,
+    quantity_received: i32,
+    status: String,
+}
+
+/// What a receipt did, for the audit trail.
+pub struct Receipt {
+    pub purchase_order_id: i64,
+    pub medication_name: String,
+    pub outstanding: i32,
+    pub po_status: &'static str,
+}
+
+/// Books a delivery against a PO line: the stock goes into the given lot, the
+/// line's received count and cost are updated and the PO moves to partially
+/// received or received. Must run inside the caller's transaction.
+pub async fn receive_line(conn: &mut SqliteConnection, dto: &ReceivePoLineDto, received_by: &str) -> Result<Receipt, AppError> {
+    let line = sqlx::query_as::<_, LineToReceive>(
+        "SELECT l.purchase_order_id, l.medication_id, m.name AS medication_name, l.quantity_ordered, l.quantity_received, po.status
+         FROM purchase_order_lines l
+         JOIN purchase_orders po ON po.id = l.purchase_order_id
+         JOIN medications m ON m.id = l.medication_id
+         WHERE l.id = ?"
+    )
+    .bind(dto.line_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(AppError::not_found("Order line"))?;
+
+    if !RECEIVABLE.contains(&line.status.as_str()) {
+        return Err(AppError::Conflict(format!("Cannot receive against a {} purchase order", line.status.replace('_', " "))));
+    }
+    let outstanding = line.quantity_ordered - line.quantity_received;
+    if dto.quantity > outstanding {
+        return Err(AppError::validation("quantity", format!("Only {} still outstanding on this line", outstanding)));
+    }
+
+    let lot_number = dto.lot_number.trim();
+    let movement = Movement { kind: "receive", reason_code: "purchase_order", reference_id: Some(line.purchase_order_id), username: received_by, note: None, witness: None };
+    let lot_id = inventory::receive_into_lot(conn, line.medication_id, lot_number, &dto.expiration, dto.quantity, &movement).await?;
+
+    sqlx::query("INSERT INTO po_receipts (line_id, lot_id, quantity, unit_cost, received_by) VALUES (?, ?, ?, ?, ?)")
+        .bind(dto.line_id).bind(lot_id).bind(dto.quantity).bind(dto.unit_cost).bind(received_by)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("UPDATE purchase_order_lines SET quantity_received = quantity_received + ?, unit_cost = ? WHERE id = ?")
+        .bind(dto.quantity).bind(dto.unit_cost).bind(dto.line_id)
+        .execute(&mut *conn)
+        .await?;
+
+    let (open_lines,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM purchase_order_lines WHERE purchase_order_id = ? AND quantity_received < quantity_ordered"
+    )
+    .bind(line.purchase_order_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    let po_status = if open_lines == 0 { "received" } else { "partially_received" };
+
+    sqlx::query("UPDATE purchase_orders SET status = ? WHERE id = ?")
+        .bind(po_status).bind(line.purchase_order_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(Receipt {
+        purchase_order_id: line.purchase_order_id,
+        medication_name: line.medication_name,
+        outstanding: outstanding - dto.quantity,
+        po_status,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::test_pool;
+
+    async fn receive(conn: &mut SqliteConnection, line_id: i64, lot_number: &str, quantity: i32) -> Result<Receipt, AppError> {
+        let dto = ReceivePoLineDto { line_id, quantity, lot_number: lot_number.to_string(), expiration: "2099-01-01".to_string(), unit_cost: 0.25 };
+        receive_line(conn, &dto, "pharm").await
+    }
+
+    async fn po_status(conn: &mut SqliteConnection) -> String {
+        let (status,): (String,) = sqlx::query_as("SELECT status FROM purchase_orders WHERE id = 1").fetch_one(&mut *conn).await.unwrap();
+        status
+    }
+
+    #[tokio::test]
+    async fn receipts_close_out_the_order_line_by_line() {
+        let pool = test_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        sqlx::raw_sql(
+            "INSERT INTO suppliers (name) VALUES ('McKesson');
+             INSERT INTO medications (name, din, stock, price, expiration)
+                VALUES ('Amoxicillin 500mg', '02238888', 0, 0.5, '2099-12-31'), ('Ibuprofen 200mg', '02242345', 0, 0.1, '2099-12-31');"
+        )
+        .execute(&mut *conn).await.unwrap();
+        let lines = [
+            PoLineDto { medication_id: 1, quantity: 100, unit_cost: Some(0.2) },
+            PoLineDto { medication_id: 2, quantity: 50, unit_cost: None },
+        ];
+        create_order(&mut conn, 1, None, &lines, "pharm").await.unwrap();
+
+        assert!(matches!(receive(&mut conn, 1, "A", 10).await, Err(AppError::Conflict(_))), "draft orders can't be received");
+        sqlx::query("UPDATE purchase_orders SET status = 'ordered' WHERE id = 1").execute(&mut *conn).await.unwrap();
+
+        let receipt = receive(&mut conn, 1, " A ", 60).await.unwrap();
+        assert_eq!((receipt.outstanding, receipt.po_status), (40, "partially_received"));
+        assert_eq!(po_status(&mut conn).await, "partially_received");
+
+        assert!(matches!(receive(&mut conn, 1, "B", 41).await, Err(AppError::Validation(_))), "over-receipt must be refused");
+
+        let receipt = receive(&mut conn, 1, "B", 40).await.unwrap();
+        assert_eq!((receipt.outstanding, receipt.po_status), (0, "partially_received"));
+        let receipt = receive(&mut conn, 2, "C", 50).await.unwrap();
+        assert_eq!((receipt.outstanding, receipt.po_status), (0, "received"));
+        assert_eq!(po_status(&mut conn).await, "received");
+
+        let stock: Vec<(String, i32)> = sqlx::query_as("SELECT lot_number, quantity FROM medication_lots ORDER BY id").fetch_all(&mut *conn).await.unwrap();
+        assert_eq!(stock, [("A".to_string(), 60), ("B".to_string(), 40), ("C".to_string(), 50)]);
+        let received: Vec<(i32, f64)> = sqlx::query_as("SELECT quantity_received, unit_cost FROM purchase_order_lines ORDER BY id").fetch_all(&mut *conn).await.unwrap();
+        assert_eq!(received, [(100, 0.25), (50, 0.25)]);
+
+        assert!(matches!(receive(&mut conn, 2, "C", 1).await, Err(AppError::Conflict(_))));
+    }
+}
@@ -0,0 +1,105 @@
+// =====================================================
+// PRESCRIPTION LIFECYCLE
+// =====================================================
+// Only active prescriptions can be filled or show up as due. Cancelled,
+// transferred-out and completed are final.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RxStatus {
+    Active,
+    OnHold,
+    Cancelled,
+    TransferredOut,
+    /// No refills left to dispense.
+    Completed,
+}
+
+impl RxStatus {
+    pub const ALL: [RxStatus; 5] = [RxStatus::Active, RxStatus::OnHold, RxStatus::Cancelled, RxStatus::TransferredOut, RxStatus::Completed];
+
+    pub fn parse(s: &str) -> Option<RxStatus> {
+        RxStatus::ALL.into_iter().find(|r| r.as_str() == s)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RxStatus::Active => "active",
+            RxStatus::OnHold => "on_hold",
+            RxStatus::Cancelled => "cancelled",
+            RxStatus::TransferredOut => "transferred_out",
+            RxStatus::Completed => "completed",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RxStatus::Active => "active",
+            RxStatus::OnHold => "on hold",
+            RxStatus::Cancelled => "cancelled",
+            RxStatus::TransferredOut => "transferred out",
+            RxStatus::Completed => "completed",
+        }
+    }
+
+    /// Transitions a user can make by hand. `Completed` is only ever set by
+    /// dispensing the last refill.
+    pub fn can_become(&self, next: RxStatus) -> bool {
+        use RxStatus::*;
+        matches!(
+            (self, next),
+            (Active, OnHold) | (OnHold, Active)
+                | (Active | OnHold, Cancelled)
+                | (Active | OnHold, TransferredOut)
+        )
+    }
+
+    /// Audit action for moving into this status.
+    pub fn audit_action(&self) -> &'static str {
+        match self {
+            RxStatus::Active => "RESUME_RX",
+            RxStatus::OnHold => "HOLD_RX",
+            RxStatus::Cancelled => "CANCEL_RX",
+            RxStatus::TransferredOut => "TRANSFER_RX",
+            RxStatus::Completed => "COMPLETE_RX",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use RxStatus::*;
+
+    #[test]
+    fn transitions() {
+        // Rows are the current status, columns the next, both in `ALL` order:
+        // active, on hold, cancelled, transferred out, completed.
+        let allowed = [
+            [false, true, true, true, false],
+            [true, false, true, true, false],
+            [false, false, false, false, false],
+            [false, false, false, false, false],
+            [false, false, false, false, false],
+        ];
+        for (from, row) in RxStatus::ALL.into_iter().zip(allowed) {
+            for (to, expected) in RxStatus::ALL.into_iter().zip(row) {
+                assert_eq!(from.can_become(to), expected, "{} -> {}", from.as_str(), to.as_str());
+            }
+        }
+    }
+
+    #[test]
+    fn final_statuses_are_terminal() {
+        for status in [Completed, Cancelled, TransferredOut] {
+            assert!(RxStatus::ALL.iter().all(|&next| !status.can_become(next)), "{} is not terminal", status.as_str());
+        }
+    }
+
+    #[test]
+    fn parses_its_own_names() {
+        for status in RxStatus::ALL {
+            assert_eq!(RxStatus::parse(status.as_str()), Some(status));
+        }
+        assert_eq!(RxStatus::parse("Active"), None);
+    }
+}
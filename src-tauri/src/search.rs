@@ -0,0 +1,297 @@
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+
+use crate::model::{AuditLogFilter, AuditLogItem, AuditLogPage, Patient, PatientSearchFilter};
+
+// =====================================================
+// PATIENT SEARCH
+// =====================================================
+// All user input goes through push_bind(); nothing typed at the counter is
+// ever spliced into the SQL text.
+
+/// Escapes LIKE metacharacters so "100%" or "_" match literally, then wraps
+/// the term for a contains-match. Pair with `ESCAPE '\'`.
+fn contains_pattern(term: &str) -> String {
+    let mut out = String::with_capacity(term.len() + 2);
+    out.push('%');
+    for c in term.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('%');
+    out
+}
+
+/// Phone numbers and health card numbers are typed with all sorts of
+/// separators; compare on the characters that matter.
+fn strip_separators(term: &str) -> String {
+    term.chars().filter(|c| !matches!(c, '-' | ' ' | '(' | ')' | '.')).collect()
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+const DRUG_MATCH: &str =
+    "EXISTS (SELECT 1 FROM prescriptions rx JOIN medications m ON rx.medication_id = m.id
+             WHERE rx.patient_id = p.id AND m.name LIKE ";
+
+pub fn patient_search_query(filter: &PatientSearchFilter) -> QueryBuilder<'static, Sqlite> {
+    let mut qb = QueryBuilder::new("SELECT p.* FROM patients p WHERE 1 = 1");
+
+    if let Some(text) = non_empty(&filter.text) {
+        let pattern = contains_pattern(text);
+        qb.push(" AND (p.name LIKE ").push_bind(pattern.clone()).push(" ESCAPE '\\' OR ")
+            .push(DRUG_MATCH).push_bind(pattern).push(" ESCAPE '\\'))");
+    }
+    if let Some(name) = non_empty(&filter.name) {
+        qb.push(" AND p.name LIKE ").push_bind(contains_pattern(name)).push(" ESCAPE '\\'");
+    }
+    if let Some(birth_date) = non_empty(&filter.birth_date) {
+        qb.push(" AND p.birth_date = ").push_bind(birth_date.to_string());
+    }
+    if let Some(phone) = non_empty(&filter.phone) {
+        qb.push(" AND REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(p.phone, '-', ''), ' ', ''), '(', ''), ')', ''), '.', '') LIKE ")
+            .push_bind(contains_pattern(&strip_separators(phone))).push(" ESCAPE '\\'");
+    }
+    if let Some(card) = non_empty(&filter.health_card_num) {
+        qb.push(" AND REPLACE(REPLACE(p.health_card_num, '-', ''), ' ', '') LIKE ")
+            .push_bind(contains_pattern(&strip_separators(card))).push(" ESCAPE '\\'");
+    }
+    if let Some(drug) = non_empty(&filter.drug) {
+        qb.push(" AND ").push(DRUG_MATCH).push_bind(contains_pattern(drug)).push(" ESCAPE '\\')");
+    }
+
+    qb.push(" ORDER BY p.id DESC");
+    qb
+}
+
+pub async fn search_patients(pool: &SqlitePool, filter: &PatientSearchFilter) -> Result<Vec<Patient>, sqlx::Error> {
+    patient_search_query(filter)
+        .build_query_as::<Patient>()
+        .fetch_all(pool)
+        .await
+}
+
+// =====================================================
+// AUDIT LOG SEARCH
+// =====================================================
+
+pub const AUDIT_PAGE_SIZE: i64 = 100;
+const AUDIT_PAGE_MAX: i64 = 500;
+
+/// Newest first. `before_id` continues from the last row of the previous page;
+/// ids only ever grow, so pages don't shift as new entries are written.
+pub fn audit_log_query(filter: &AuditLogFilter, before_id: Option<i64>) -> QueryBuilder<'static, Sqlite> {
+    let mut qb = QueryBuilder::new(
+        "SELECT id, username, action, details, timestamp, entity_type, entity_id, before_json, after_json, hash
+         FROM audit_logs WHERE 1 = 1"
+    );
+
+    if let Some(username) = non_empty(&filter.username) {
+        qb.push(" AND username = ").push_bind(username.to_string()).push(" COLLATE NOCASE");
+    }
+    if let Some(action) = non_empty(&filter.action) {
+        qb.push(" AND action = ").push_bind(action.to_uppercase());
+    }
+    if let Some(from) = non_empty(&filter.from_date) {
+        qb.push(" AND timestamp >= ").push_bind(from.to_string());
+    }
+    if let Some(to) = non_empty(&filter.to_date) {
+        qb.push(" AND timestamp < date(").push_bind(to.to_string()).push(", '+1 day')");
+    }
+    if let Some(text) = non_empty(&filter.text) {
+        qb.push(" AND details LIKE ").push_bind(contains_pattern(text)).push(" ESCAPE '\\'");
+    }
+    if let Some(before_id) = before_id {
+        qb.push(" AND id < ").push_bind(before_id);
+    }
+
+    qb.push(" ORDER BY id DESC");
+    qb
+}
+
+pub async fn search_audit_logs(pool: &SqlitePool, filter: &AuditLogFilter, before_id: Option<i64>, limit: Option<i64>) -> Result<AuditLogPage, sqlx::Error> {
+    let limit = limit.unwrap_or(AUDIT_PAGE_SIZE).clamp(1, AUDIT_PAGE_MAX);
+
+    // One extra row tells us whether there is another page
+    let mut qb = audit_log_query(filter, before_id);
+    qb.push(" LIMIT ").push_bind(limit + 1);
+    let mut items = qb.build_query_as::<AuditLogItem>().fetch_all(pool).await?;
+
+    let next_cursor = if items.len() as i64 > limit {
+        items.truncate(limit as usize);
+        items.last().map(|i| i.id)
+    } else {
+        None
+    };
+    Ok(AuditLogPage { items, next_cursor })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{self, Event};
+    use crate::migrations::test_pool;
+
+    async fn seeded_pool() -> SqlitePool {
+        let pool = test_pool().await;
+
+        let patients = [
+            ("Miles O'Brien", "1970-03-14", "(416) 555-0101", "111-222-333-OB"),
+            ("Zoë Ångström", "1988-07-02", "604.555.0102", "444-555-666-ZA"),
+            ("Percy 100% Smith", "1990-01-01", "212-555-0103", "777-888-999-PS"),
+            ("José Núñez", "1965-12-25", "905 555 0104", "121-212-121-JN"),
+        ];
+        for p in patients {
+            sqlx::query(
+                "INSERT INTO patients (name, birth_date, phone, address, city, state, postal_code, health_card_num)
+                 VALUES (?, ?, ?, '1 Main St', 'Toronto', 'ON', 'M5V 2T6', ?)"
+            )
+            .bind(p.0).bind(p.1).bind(p.2).bind(p.3)
+            .execute(&pool).await.unwrap();
+        }
+
+        sqlx::query("INSERT INTO medications (name, din, stock, price, expiration) VALUES ('Amoxicillin 500mg', '02238888', 100, 1.0, '2030-01-01')")
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO prescriptions (patient_id, medication_id, prescriber, sig, quantity, refills, days_supply, date_filled, next_refill_date)
+             VALUES (2, 1, 'Dr. Who', 'Take 1 daily', 30, 0, 30, '2024-01-01', '2024-01-31')"
+        )
+        .execute(&pool).await.unwrap();
+        pool
+    }
+
+    fn text(s: &str) -> PatientSearchFilter {
+        PatientSearchFilter { text: Some(s.to_string()), ..Default::default() }
+    }
+
+    async fn names(pool: &SqlitePool, filter: PatientSearchFilter) -> Vec<String> {
+        search_patients(pool, &filter).await.unwrap().into_iter().map(|p| p.name).collect()
+    }
+
+    #[tokio::test]
+    async fn apostrophes_are_searchable() {
+        let pool = seeded_pool().await;
+        assert_eq!(names(&pool, text("O'Brien")).await, vec!["Miles O'Brien"]);
+    }
+
+    #[tokio::test]
+    async fn injection_attempt_is_treated_as_text() {
+        let pool = seeded_pool().await;
+        assert!(names(&pool, text("' OR 1=1 --")).await.is_empty());
+        assert!(names(&pool, text("'; DROP TABLE patients; --")).await.is_empty());
+
+        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM patients").fetch_one(&pool).await.unwrap();
+        assert_eq!(count.0, 4);
+    }
+
+    #[tokio::test]
+    async fn wildcards_match_literally() {
+        let pool = seeded_pool().await;
+        assert_eq!(names(&pool, text("%")).await, vec!["Percy 100% Smith"]);
+        assert!(names(&pool, text("_")).await.is_empty());
+        assert!(names(&pool, text("O_Brien")).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unicode_names_match() {
+        let pool = seeded_pool().await;
+        assert_eq!(names(&pool, text("Ångström")).await, vec!["Zoë Ångström"]);
+        assert_eq!(names(&pool, text("Núñez")).await, vec!["José Núñez"]);
+    }
+
+    #[tokio::test]
+    async fn free_text_matches_drug_names() {
+        let pool = seeded_pool().await;
+        assert_eq!(names(&pool, text("amoxi")).await, vec!["Zoë Ångström"]);
+    }
+
+    #[tokio::test]
+    async fn typed_fields_combine_with_and() {
+        let pool = seeded_pool().await;
+
+        let by_phone = PatientSearchFilter { phone: Some("416-555-0101".into()), ..Default::default() };
+        assert_eq!(names(&pool, by_phone).await, vec!["Miles O'Brien"]);
+
+        let by_card = PatientSearchFilter { health_card_num: Some("444555666".into()), ..Default::default() };
+        assert_eq!(names(&pool, by_card).await, vec!["Zoë Ångström"]);
+
+        let dob_and_drug = PatientSearchFilter {
+            birth_date: Some("1988-07-02".into()),
+            drug: Some("Amoxicillin".into()),
+            ..Default::default()
+        };
+        assert_eq!(names(&pool, dob_and_drug).await, vec!["Zoë Ångström"]);
+
+        let mismatch = PatientSearchFilter {
+            name: Some("Miles".into()),
+            drug: Some("Amoxicillin".into()),
+            ..Default::default()
+        };
+        assert!(names(&pool, mismatch).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn empty_filter_returns_everyone_newest_first() {
+        let pool = seeded_pool().await;
+        let all = search_patients(&pool, &PatientSearchFilter::default()).await.unwrap();
+        assert_eq!(all.iter().map(|p| p.id).collect::<Vec<_>>(), vec![4, 3, 2, 1]);
+    }
+
+    async fn audited_pool() -> SqlitePool {
+        let pool = seeded_pool().await;
+        let entries = [
+            ("admin", "LOGIN", "User logged in"),
+            ("tech", "ADD_PATIENT", "Added Miles O'Brien"),
+            ("tech", "FILL_RX", "Filled 100% of Rx ID 1"),
+            ("pharm", "FILL_RX", "Filled Rx ID 2"),
+            ("tech", "FILL_RX", "Filled Rx ID 3"),
+        ];
+        for (username, action, details) in entries {
+            audit::record(&pool, username, Event::new(action, details)).await.unwrap();
+        }
+        pool
+    }
+
+    async fn audit_ids(pool: &SqlitePool, filter: AuditLogFilter) -> Vec<i64> {
+        search_audit_logs(pool, &filter, None, None).await.unwrap().items.into_iter().map(|i| i.id).collect()
+    }
+
+    #[tokio::test]
+    async fn audit_filters_combine_with_and() {
+        let pool = audited_pool().await;
+
+        let tech_fills = AuditLogFilter { username: Some("TECH".into()), action: Some("fill_rx".into()), ..Default::default() };
+        assert_eq!(audit_ids(&pool, tech_fills).await, vec![5, 3]);
+
+        let percent = AuditLogFilter { text: Some("100%".into()), ..Default::default() };
+        assert_eq!(audit_ids(&pool, percent).await, vec![3]);
+
+        let future = AuditLogFilter { from_date: Some("2999-01-01".into()), ..Default::default() };
+        assert!(audit_ids(&pool, future).await.is_empty());
+
+        let through_today = AuditLogFilter { to_date: Some(crate::validation::today().to_string()), ..Default::default() };
+        assert_eq!(audit_ids(&pool, through_today).await.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn audit_cursor_pages_without_overlap() {
+        let pool = audited_pool().await;
+        let filter = AuditLogFilter::default();
+
+        let first = search_audit_logs(&pool, &filter, None, Some(2)).await.unwrap();
+        assert_eq!(first.items.iter().map(|i| i.id).collect::<Vec<_>>(), vec![5, 4]);
+
+        // A new entry must not push anything from page one onto page two
+        audit::record(&pool, "admin", Event::new("LOGOUT", "User logged out")).await.unwrap();
+
+        let second = search_audit_logs(&pool, &filter, first.next_cursor, Some(2)).await.unwrap();
+        assert_eq!(second.items.iter().map(|i| i.id).collect::<Vec<_>>(), vec![3, 2]);
+
+        let last = search_audit_logs(&pool, &filter, second.next_cursor, Some(2)).await.unwrap();
+        assert_eq!(last.items.iter().map(|i| i.id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(last.next_cursor, None);
+    }
+}
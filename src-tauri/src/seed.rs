@@ -1,5 +1,8 @@
 use sqlx::SqlitePool;
 
+use crate::auth;
+use crate::clinical;
+
 pub async fn init_db(pool: &SqlitePool) {
     // =========================================================
     // 1. SEED PATIENTS
@@ -20,14 +23,17 @@ pub async fn init_db(pool: &SqlitePool) {
             ("Geralt Rivera", "1955-11-30", "212-555-1980", "geralt@news.com", "55 West St", "New York", "NY", "10001", "555-999-000-XX", "Sulfa Drugs", "Aetna", "AE-334455"),
         ];
 
+        let mut conn = pool.acquire().await.unwrap();
         for p in patients {
-            sqlx::query(
+            let patient_id = sqlx::query(
                 "INSERT INTO patients (name, birth_date, phone, email, address, city, state, postal_code, health_card_num, allergies, insurance_provider, insurance_id) 
                  VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
             )
             .bind(p.0).bind(p.1).bind(p.2).bind(p.3).bind(p.4).bind(p.5)
             .bind(p.6).bind(p.7).bind(p.8).bind(p.9).bind(p.10).bind(p.11)
-            .execute(pool).await.unwrap();
+            .execute(&mut *conn).await.unwrap()
+            .last_insert_rowid();
+            clinical::record_allergies_from_text(&mut conn, patient_id, p.9).await.unwrap();
         }
     }
 
@@ -42,20 +48,40 @@ pub async fn init_db(pool: &SqlitePool) {
     if med_count.0 == 0 {
         println!("💊 Seeding Database with Inventory...");
 
+        // (name, din, ndc, description, stock, price, expiration, lot, schedule)
         let meds = vec![
-            ("Amoxicillin 500mg", "02238888", "00000-111-22", "Shelf A1", 500, 12.99, "2025-12-31"),
-            ("Atorvastatin 20mg", "02245555", "55555-333-44", "Shelf B3", 200, 45.50, "2026-06-15"),
-            ("Metformin 500mg", "02111222", "12345-678-90", "Shelf A2", 1000, 8.25, "2024-11-30"),
-            ("Lisinopril 10mg", "02333444", "98765-432-10", "Shelf C1", 30, 15.00, "2023-10-01"),
-            ("Escitalopram 10mg", "02444555", "11223-344-55", "Shelf B2", 150, 22.75, "2025-05-20"),
+            ("Amoxicillin 500mg", "02238888", "00000-111-22", "Shelf A1", 500, 12.99, "2025-12-31", "AX4471", None),
+            ("Atorvastatin 20mg", "02245555", "55555-333-44", "Shelf B3", 200, 45.50, "2026-06-15", "AT2290", None),
+            ("Metformin 500mg", "02111222", "12345-678-90", "Shelf A2", 1000, 8.25, "2024-11-30", "MF0815", None),
+            ("Lisinopril 10mg", "02333444", "98765-432-10", "Shelf C1", 30, 15.00, "2023-10-01", "LS3306", None),
+            ("Escitalopram 10mg", "02444555", "11223-344-55", "Shelf B2", 150, 22.75, "2025-05-20", "ES1172", None),
+            ("Hydromorphone 2mg", "00885428", "00409-128-01", "Narcotic Safe", 100, 0.42, "2027-03-31", "HM2051", Some("narcotic")),
         ];
 
         for m in meds {
+            // Stock comes from the opening ledger row below via the triggers,
+            // which also open a scheduled drug's register
+            sqlx::query(
+                "INSERT INTO medications (name, din, ndc, description, stock, price, expiration, controlled, schedule) 
+                 VALUES (?, ?, ?, ?, 0, ?, ?, ?, ?)"
+            )
+            .bind(m.0).bind(m.1).bind(m.2).bind(m.3).bind(m.5).bind(m.6).bind(m.8.is_some()).bind(m.8)
+            .execute(pool).await.unwrap();
+
+            sqlx::query(
+                "INSERT INTO medication_lots (medication_id, lot_number, expiration, quantity)
+                 SELECT id, ?, ?, 0 FROM medications WHERE din = ?"
+            )
+            .bind(m.7).bind(m.6).bind(m.1)
+            .execute(pool).await.unwrap();
+
             sqlx::query(
-                "INSERT INTO medications (name, din, ndc, description, stock, price, expiration) 
-                 VALUES (?, ?, ?, ?, ?, ?, ?)"
+                "INSERT INTO stock_movements (lot_id, kind, quantity, reason_code, username)
+                 SELECT l.id, 'receive', ?, 'opening_stock', 'system'
+                 FROM medication_lots l JOIN medications m ON m.id = l.medication_id
+                 WHERE m.din = ? AND l.lot_number = ?"
             )
-            .bind(m.0).bind(m.1).bind(m.2).bind(m.3).bind(m.4).bind(m.5).bind(m.6)
+            .bind(m.4).bind(m.1).bind(m.7)
             .execute(pool).await.unwrap();
         }
     }
@@ -87,8 +113,8 @@ pub async fn init_db(pool: &SqlitePool) {
         for rx in rxs {
             sqlx::query(
                 "INSERT INTO prescriptions (
-                    patient_id, medication_id, prescriber, sig, quantity, refills, days_supply, date_filled, next_refill_date
-                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, date(?, '+' || ? || ' days'))"
+                    patient_id, medication_id, prescriber, sig, quantity, refills, refills_remaining, days_supply, date_filled, next_refill_date, expires_on
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, date(?, '+' || ? || ' days'), date(?, '+365 days'))"
             )
             .bind(rx.0) // Patient ID
             .bind(rx.1) // Med ID
@@ -96,26 +122,101 @@ pub async fn init_db(pool: &SqlitePool) {
             .bind(rx.3) // Sig
             .bind(rx.4) // Qty
             .bind(rx.5) // Refills
+            .bind(rx.5) // Refills remaining
             .bind(rx.6) // Days Supply
             .bind(rx.7) // Date Filled (used for field)
             .bind(rx.7) // Date Filled (used for calc)
             .bind(rx.6) // Days Supply (used for calc)
+            .bind(rx.7) // Date Filled (expiry)
             .execute(pool).await.unwrap();
         }
+
+        // Each seeded prescription has only its original fill
+        sqlx::query(
+            "INSERT INTO fills (prescription_id, fill_number, quantity, days_supply, date_filled, next_refill_date)
+             SELECT id, 0, quantity, days_supply, date_filled, next_refill_date FROM prescriptions"
+        )
+        .execute(pool).await.unwrap();
+        sqlx::query("UPDATE prescriptions SET status = 'completed' WHERE refills_remaining = 0")
+            .execute(pool).await.unwrap();
     }
     // 4. SEED USERS
+    // Default accounts ship with well-known passwords, so they must be changed on first login.
     let user_count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users")
         .fetch_one(pool).await.unwrap_or((0,));
 
     if user_count.0 == 0 {
         println!("🔐 Seeding Users...");
-        sqlx::query("INSERT INTO users (username, password, role) VALUES (?, ?, ?)")
-            .bind("admin").bind("admin").bind("admin")
-            .execute(pool).await.unwrap();
-            
-        sqlx::query("INSERT INTO users (username, password, role) VALUES (?, ?, ?)")
-            .bind("tech").bind("tech").bind("tech")
+        for (username, role) in [("admin", "admin"), ("pharm", "pharmacist"), ("tech", "tech")] {
+            sqlx::query("INSERT INTO users (username, password_hash, role, must_change_password) VALUES (?, ?, ?, 1)")
+                .bind(username)
+                .bind(auth::hash_password(username).await.unwrap())
+                .bind(role)
+                .execute(pool).await.unwrap();
+        }
+    }
+
+    // 5. SEED INGREDIENT TAGS
+    // Lets the allergy check catch e.g. a penicillin allergy against Amoxicillin.
+    let tag_count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM medication_ingredients")
+        .fetch_one(pool).await.unwrap_or((0,));
+
+    if tag_count.0 == 0 {
+        println!("🧪 Seeding Ingredient Tags...");
+        let tags = vec![
+            ("02238888", "amoxicillin", "ingredient"), ("02238888", "penicillin", "class"), ("02238888", "beta-lactam", "class"),
+            ("02245555", "atorvastatin", "ingredient"), ("02245555", "statin", "class"),
+            ("02111222", "metformin", "ingredient"), ("02111222", "biguanide", "class"),
+            ("02333444", "lisinopril", "ingredient"), ("02333444", "ace inhibitor", "class"),
+            ("02444555", "escitalopram", "ingredient"), ("02444555", "ssri", "class"),
+        ];
+        for (din, name, kind) in tags {
+            sqlx::query(
+                "INSERT INTO medication_ingredients (medication_id, name, kind)
+                 SELECT id, ?, ? FROM medications WHERE din = ?"
+            )
+            .bind(name).bind(kind).bind(din)
             .execute(pool).await.unwrap();
+        }
     }
-}
 
+    // 6. SEED INTERACTIONS
+    // A starter set; pharmacies import their full table from a file.
+    let interaction_count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM drug_interactions")
+        .fetch_one(pool).await.unwrap_or((0,));
+
+    if interaction_count.0 == 0 {
+        println!("⚠️  Seeding Drug Interactions...");
+        // (term_a, term_b, severity, monograph) with term_a <= term_b
+        let interactions = vec![
+            ("maoi", "ssri", "contraindicated", "Risk of serotonin syndrome. Allow 14 days between stopping an MAOI and starting an SSRI."),
+            ("nsaid", "ssri", "moderate", "Increased risk of GI bleeding. Consider gastroprotection."),
+            ("ace inhibitor", "potassium-sparing diuretic", "major", "Risk of hyperkalemia. Monitor serum potassium."),
+            ("atorvastatin", "clarithromycin", "major", "Clarithromycin raises atorvastatin levels; risk of myopathy and rhabdomyolysis."),
+            ("iodinated contrast", "metformin", "major", "Risk of lactic acidosis. Hold metformin around contrast studies."),
+        ];
+        for (term_a, term_b, severity, monograph) in interactions {
+            sqlx::query("INSERT INTO drug_interactions (term_a, term_b, severity, monograph) VALUES (?, ?, ?, ?)")
+                .bind(term_a).bind(term_b).bind(severity).bind(monograph)
+                .execute(pool).await.unwrap();
+        }
+    }
+
+    // 7. SEED SUPPLIERS
+    // Example wholesalers so purchase orders can be raised out of the box.
+    let supplier_count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM suppliers")
+        .fetch_one(pool).await.unwrap_or((0,));
+
+    if supplier_count.0 == 0 {
+        println!("🚚 Seeding Suppliers...");
+        let suppliers = vec![
+            ("McKesson Canada", "Order Desk", "1-800-555-0101", "orders@example-mckesson.ca"),
+            ("Kohl & Frisch", "Customer Service", "1-800-555-0199", "service@example-kohlfrisch.ca"),
+        ];
+        for (name, contact, phone, email) in suppliers {
+            sqlx::query("INSERT INTO suppliers (name, contact, phone, email) VALUES (?, ?, ?, ?)")
+                .bind(name).bind(contact).bind(phone).bind(email)
+                .execute(pool).await.unwrap();
+        }
+    }
+}
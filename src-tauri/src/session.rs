@@ -0,0 +1,212 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use sqlx::SqlitePool;
+
+use crate::audit::{self, Entity, Event};
+use crate::error::AppError;
+use crate::permissions::{Capability, Role};
+
+// =====================================================
+// SESSIONS
+// =====================================================
+// The desktop app has a single webview, so there is at most one signed-in user.
+// Commands never trust a username coming from the frontend; they ask the
+// store who is logged in.
+
+/// Inactivity after which the session is dropped and the user must log in again.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user_id: i64,
+    pub username: String,
+    pub role: String,
+    pub must_change_password: bool,
+    last_seen: Instant,
+}
+
+impl Session {
+    /// Unknown role strings get no capabilities.
+    pub fn can(&self, capability: Capability) -> bool {
+        Role::parse(&self.role).is_some_and(|r| r.can(capability))
+    }
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    NotLoggedIn,
+    Expired { username: String },
+    PasswordChangeRequired,
+    Forbidden { capability: Capability },
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::NotLoggedIn => write!(f, "Not logged in"),
+            AuthError::Expired { .. } => write!(f, "Session expired due to inactivity. Please log in again."),
+            AuthError::PasswordChangeRequired => write!(f, "You must change your password before continuing"),
+            AuthError::Forbidden { capability } => write!(f, "Forbidden: your role does not have the '{}' permission", capability.as_str()),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SessionStore {
+    current: Mutex<Option<Session>>,
+}
+
+impl SessionStore {
+    /// Replaces any existing session with a fresh one for this user.
+    pub fn start(&self, user_id: i64, username: &str, role: &str, must_change_password: bool) -> Session {
+        let session = Session {
+            user_id,
+            username: username.to_string(),
+            role: role.to_string(),
+            must_change_password,
+            last_seen: Instant::now(),
+        };
+        *self.current.lock().unwrap() = Some(session.clone());
+        session
+    }
+
+    pub fn end(&self) -> Option<Session> {
+        self.current.lock().unwrap().take()
+    }
+
+    pub fn clear_password_change(&self) {
+        if let Some(s) = self.current.lock().unwrap().as_mut() {
+            s.must_change_password = false;
+        }
+    }
+
+    /// Current session without counting as activity (used by UI polling).
+    pub fn peek(&self) -> Result<Session, AuthError> {
+        self.check(false)
+    }
+
+    /// Current session, refreshing its idle timer. Still allowed while a
+    /// password change is pending so `change_password` / `logout` can run.
+    pub fn touch(&self) -> Result<Session, AuthError> {
+        self.check(true)
+    }
+
+    fn check(&self, refresh: bool) -> Result<Session, AuthError> {
+        let mut guard = self.current.lock().unwrap();
+        let session = guard.as_mut().ok_or(AuthError::NotLoggedIn)?;
+
+        if session.last_seen.elapsed() > IDLE_TIMEOUT {
+            let username = session.username.clone();
+            *guard = None;
+            return Err(AuthError::Expired { username });
+        }
+        if refresh {
+            session.last_seen = Instant::now();
+        }
+        Ok(session.clone())
+    }
+
+    /// Resolves the acting user for a command. Idle expiry is recorded in the
+    /// audit trail as an automatic logout.
+    pub async fn require(&self, pool: &SqlitePool) -> Result<Session, AppError> {
+        let session = match self.touch() {
+            Ok(s) => s,
+            Err(AuthError::Expired { username }) => {
+                audit::record(pool, &username, Event::new("SESSION_EXPIRED", "Logged out after inactivity")).await?;
+                return Err(AuthError::Expired { username }.into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if session.must_change_password {
+            return Err(AuthError::PasswordChangeRequired.into());
+        }
+        Ok(session)
+    }
+
+    /// `require()` plus a role check. Denials are audited.
+    pub async fn authorize(&self, pool: &SqlitePool, capability: Capability) -> Result<Session, AppError> {
+        let session = self.require(pool).await?;
+        if !session.can(capability) {
+            let event = Event::new("ACCESS_DENIED", format!("Role '{}' lacks '{}'", session.role, capability.as_str()));
+            audit::record(pool, &session.username, event.on(Entity::User, session.user_id)).await?;
+            return Err(AuthError::Forbidden { capability }.into());
+        }
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::test_pool;
+
+    fn idle_for(store: &SessionStore, idle: Duration) {
+        store.current.lock().unwrap().as_mut().unwrap().last_seen = Instant::now() - idle;
+    }
+
+    fn idle_time(store: &SessionStore) -> Duration {
+        store.current.lock().unwrap().as_ref().unwrap().last_seen.elapsed()
+    }
+
+    #[test]
+    fn nobody_logged_in() {
+        let store = SessionStore::default();
+        assert!(matches!(store.peek(), Err(AuthError::NotLoggedIn)));
+        assert!(matches!(store.touch(), Err(AuthError::NotLoggedIn)));
+    }
+
+    #[test]
+    fn idle_session_expires() {
+        let store = SessionStore::default();
+        store.start(1, "tech", "tech", false);
+        idle_for(&store, IDLE_TIMEOUT + Duration::from_secs(1));
+
+        match store.touch() {
+            Err(AuthError::Expired { username }) => assert_eq!(username, "tech"),
+            other => panic!("expected expiry, got {:?}", other),
+        }
+        assert!(matches!(store.peek(), Err(AuthError::NotLoggedIn)));
+    }
+
+    #[test]
+    fn peek_does_not_count_as_activity() {
+        let store = SessionStore::default();
+        store.start(1, "tech", "tech", false);
+        idle_for(&store, IDLE_TIMEOUT - Duration::from_secs(60));
+
+        assert_eq!(store.peek().unwrap().username, "tech");
+        assert!(idle_time(&store) >= IDLE_TIMEOUT - Duration::from_secs(60));
+
+        store.touch().unwrap();
+        assert!(idle_time(&store) < Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn pending_password_change_blocks_commands() {
+        let pool = test_pool().await;
+        let store = SessionStore::default();
+        store.start(1, "tech", "tech", true);
+
+        let err = store.require(&pool).await.unwrap_err();
+        assert_eq!(err.message(), AuthError::PasswordChangeRequired.to_string());
+        // change_password itself only touches the session
+        assert!(store.touch().is_ok());
+
+        store.clear_password_change();
+        assert_eq!(store.require(&pool).await.unwrap().username, "tech");
+    }
+
+    #[tokio::test]
+    async fn expiry_is_audited_as_a_logout() {
+        let pool = test_pool().await;
+        let store = SessionStore::default();
+        store.start(1, "tech", "tech", false);
+        idle_for(&store, IDLE_TIMEOUT + Duration::from_secs(1));
+
+        assert!(matches!(store.require(&pool).await, Err(AppError::Unauthorized(_))));
+        let entry: (String, String) = sqlx::query_as("SELECT username, action FROM audit_logs").fetch_one(&pool).await.unwrap();
+        assert_eq!(entry, ("tech".to_string(), "SESSION_EXPIRED".to_string()));
+    }
+}
@@ -0,0 +1,61 @@
+use sqlx::SqliteConnection;
+
+use crate::error::AppError;
+
+// =====================================================
+// STORE SETTINGS
+// =====================================================
+// Pharmacy-wide policy values kept in the `settings` table so they can be
+// tuned without a new build. Every key has a built-in default in case the
+// row is missing.
+
+/// Percent of the last fill's days supply that must have elapsed before a refill.
+pub const TOO_SOON_PCT: &str = "too_soon_pct";
+/// Same, for controlled substances (normally stricter).
+pub const CONTROLLED_TOO_SOON_PCT: &str = "controlled_too_soon_pct";
+/// Lots expiring within this many days are flagged on the dashboard.
+pub const EXPIRY_WARNING_DAYS: &str = "expiry_warning_days";
+/// Days of fill history used to work out how fast a drug moves.
+pub const VELOCITY_WINDOW_DAYS: &str = "velocity_window_days";
+/// Days between ordering and receiving stock; auto reorder points cover this.
+pub const REORDER_LEAD_DAYS: &str = "reorder_lead_days";
+/// Days of use an auto-computed reorder quantity should buy.
+pub const REORDER_COVER_DAYS: &str = "reorder_cover_days";
+
+/// (key, default, allowed range, description)
+pub const KNOWN: [(&str, i64, (i64, i64), &str); 6] = [
+    (TOO_SOON_PCT, 80, (1, 100), "Percent of days supply used before a refill is allowed"),
+    (CONTROLLED_TOO_SOON_PCT, 90, (1, 100), "Percent of days supply used before a controlled-substance refill is allowed"),
+    (EXPIRY_WARNING_DAYS, 90, (1, 730), "Days ahead to flag stock that is about to expire"),
+    (VELOCITY_WINDOW_DAYS, 90, (7, 365), "Days of dispensing history used for auto reorder points"),
+    (REORDER_LEAD_DAYS, 7, (1, 90), "Supplier lead time in days covered by auto reorder points"),
+    (REORDER_COVER_DAYS, 30, (1, 180), "Days of use an auto reorder quantity should cover"),
+];
+
+fn default_for(key: &str) -> Option<i64> {
+    KNOWN.iter().find(|(k, ..)| *k == key).map(|(_, d, ..)| *d)
+}
+
+fn range_for(key: &str) -> Option<(i64, i64)> {
+    KNOWN.iter().find(|(k, ..)| *k == key).map(|(_, _, r, _)| *r)
+}
+
+pub async fn get_i64(conn: &mut SqliteConnection, key: &str) -> Result<i64, sqlx::Error> {
+    let row: Option<(String,)> = sqlx::query_as("SELECT value FROM settings WHERE key = ?")
+        .bind(key)
+        .fetch_optional(&mut *conn)
+        .await?;
+    let default = default_for(key).unwrap_or_default();
+    Ok(row.and_then(|(v,)| v.parse().ok()).unwrap_or(default))
+}
+
+/// Rejects unknown keys and out-of-range values before anything is stored.
+pub fn validate(key: &str, value: &str) -> Result<(), AppError> {
+    let Some((min, max)) = range_for(key) else {
+        return Err(AppError::validation("key", format!("Unknown setting '{}'", key)));
+    };
+    match value.trim().parse::<i64>() {
+        Ok(n) if (min..=max).contains(&n) => Ok(()),
+        _ => Err(AppError::validation("value", format!("Must be a whole number from {} to {}", min, max))),
+    }
+}
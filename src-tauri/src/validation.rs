@@ -0,0 +1,480 @@
+use chrono::{Local, NaiveDate};
+
+use crate::clinical::{ALLERGY_SEVERITIES, INTERACTION_SEVERITIES};
+use crate::controlled;
+use crate::error::{AppError, FieldError};
+use crate::model::{CreateAllergyDto, CreateMedicationDto, CreatePatientDto, CreatePrescriptionDto, IngredientTag, InteractionRecord, ReceiveLotDto, UpdateMedicationDto,
+    CreateSupplierDto, CreatePurchaseOrderDto, ReceivePoLineDto, AuditLogFilter};
+
+// =====================================================
+// INPUT VALIDATION
+// =====================================================
+// Each validate_* checks every field of a DTO and reports all problems in a
+// single AppError::Validation, so the form can highlight them at once.
+// Runs before any database access.
+
+#[derive(Default)]
+pub struct FieldErrors(Vec<FieldError>);
+
+impl FieldErrors {
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.0.push(FieldError { field: field.to_string(), message: message.into() });
+    }
+
+    pub fn require(&mut self, field: &str, value: &str, label: &str) {
+        if value.trim().is_empty() {
+            self.add(field, format!("{} is required", label));
+        }
+    }
+
+    pub fn finish(self) -> Result<(), AppError> {
+        if self.0.is_empty() { Ok(()) } else { Err(AppError::Validation(self.0)) }
+    }
+}
+
+// --- FORMAT HELPERS ---
+
+/// Strict `YYYY-MM-DD` (what `<input type="date">` sends and what SQLite date() expects).
+pub fn parse_date(value: &str) -> Option<NaiveDate> {
+    if value.len() != 10 {
+        return None;
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+}
+
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+const PROVINCES: [&str; 13] = ["AB", "BC", "MB", "NB", "NL", "NS", "NT", "NU", "ON", "PE", "QC", "SK", "YT"];
+
+const STATES: [&str; 51] = [
+    "AL", "AK", "AZ", "AR", "CA", "CO", "CT", "DE", "DC", "FL", "GA", "HI", "ID", "IL", "IN", "IA", "KS",
+    "KY", "LA", "ME", "MD", "MA", "MI", "MN", "MS", "MO", "MT", "NE", "NV", "NH", "NJ", "NM", "NY", "NC",
+    "ND", "OH", "OK", "OR", "PA", "RI", "SC", "SD", "TN", "TX", "UT", "VT", "VA", "WA", "WV", "WI", "WY",
+];
+
+/// First letter of a Canadian postal code for each province (Canada Post FSA regions).
+fn postal_prefixes(province: &str) -> &'static str {
+    match province {
+        "NL" => "A", "NS" => "B", "PE" => "C", "NB" => "E", "QC" => "GHJ",
+        "ON" => "KLMNP", "MB" => "R", "SK" => "S", "AB" => "T", "BC" => "V",
+        "NT" | "NU" => "X", "YT" => "Y",
+        _ => "",
+    }
+}
+
+/// `A1A 1A1` (space optional) with a first letter that belongs to the province.
+fn is_canadian_postal_code(code: &str, province: &str) -> bool {
+    let compact: Vec<char> = code.chars().filter(|c| *c != ' ').map(|c| c.to_ascii_uppercase()).collect();
+    compact.len() == 6
+        && compact.iter().enumerate().all(|(i, c)| if i % 2 == 0 { c.is_ascii_alphabetic() } else { c.is_ascii_digit() })
+        && postal_prefixes(province).contains(compact[0])
+}
+
+/// `12345` or ZIP+4 `12345-6789`.
+fn is_zip_code(code: &str) -> bool {
+    let (base, plus4) = match code.split_once('-') {
+        Some((b, p)) => (b, Some(p)),
+        None => (code, None),
+    };
+    base.len() == 5 && base.chars().all(|c| c.is_ascii_digit())
+        && plus4.is_none_or(|p| p.len() == 4 && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// North American number: 10 digits, or 11 with a leading country code 1.
+/// Common separators are allowed.
+fn is_phone(value: &str) -> bool {
+    if !value.chars().all(|c| c.is_ascii_digit() || " -().+".contains(c)) {
+        return false;
+    }
+    let digits: String = value.chars().filter(|c| c.is_ascii_digit()).collect();
+    digits.len() == 10 || (digits.len() == 11 && digits.starts_with('1'))
+}
+
+fn is_email(value: &str) -> bool {
+    match value.split_once('@') {
+        Some((local, domain)) => !local.is_empty() && !value.contains(' ')
+            && domain.split('.').count() >= 2 && domain.split('.').all(|part| !part.is_empty()),
+        None => false,
+    }
+}
+
+/// Health Canada Drug Identification Number: exactly 8 digits.
+pub fn is_din(value: &str) -> bool {
+    value.len() == 8 && value.chars().all(|c| c.is_ascii_digit())
+}
+
+/// FDA National Drug Code in one of the 10-digit hyphenated layouts
+/// (4-4-2, 5-3-2, 5-4-1) or the 11-digit billing form (5-4-2 / plain digits).
+fn is_ndc(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    if !parts.iter().all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit())) {
+        return false;
+    }
+    let lens: Vec<usize> = parts.iter().map(|p| p.len()).collect();
+    matches!(lens.as_slice(), [4, 4, 2] | [5, 3, 2] | [5, 4, 1] | [5, 4, 2] | [10] | [11])
+}
+
+// --- DTO VALIDATORS ---
+
+pub fn validate_patient(dto: &CreatePatientDto) -> Result<(), AppError> {
+    let mut errors = FieldErrors::default();
+
+    errors.require("name", &dto.name, "Name");
+    errors.require("address", &dto.address, "Street address");
+    errors.require("city", &dto.city, "City");
+    errors.require("health_card_num", &dto.health_card_num, "Health card number");
+
+    match parse_date(&dto.birth_date) {
+        None => errors.add("birth_date", "Date of birth must be a valid date (YYYY-MM-DD)"),
+        Some(d) if d > today() => errors.add("birth_date", "Date of birth cannot be in the future"),
+        Some(d) if d < NaiveDate::from_ymd_opt(1900, 1, 1).unwrap() => errors.add("birth_date", "Date of birth is too far in the past"),
+        Some(_) => {}
+    }
+
+    if !is_phone(&dto.phone) {
+        errors.add("phone", "Phone must be a 10-digit number, e.g. 416-555-0199");
+    }
+
+    if let Some(email) = dto.email.as_deref().filter(|e| !e.trim().is_empty()) {
+        if !is_email(email.trim()) {
+            errors.add("email", "Email address is not valid");
+        }
+    }
+
+    let region = dto.state.trim().to_ascii_uppercase();
+    let postal = dto.postal_code.trim();
+    if PROVINCES.contains(&region.as_str()) {
+        if !is_canadian_postal_code(postal, &region) {
+            errors.add("postal_code", format!("Postal code is not valid for {} (expected A1A 1A1)", region));
+        }
+    } else if STATES.contains(&region.as_str()) {
+        if !is_zip_code(postal) {
+            errors.add("postal_code", "ZIP code must be 12345 or 12345-6789");
+        }
+    } else {
+        errors.add("state", "Province/State must be a two-letter Canadian province or US state code");
+    }
+
+    errors.finish()
+}
+
+pub fn validate_allergy(dto: &CreateAllergyDto) -> Result<(), AppError> {
+    let mut errors = FieldErrors::default();
+
+    errors.require("allergen", &dto.allergen, "Allergen");
+    if !ALLERGY_SEVERITIES.contains(&dto.severity.as_str()) {
+        errors.add("severity", format!("Severity must be one of: {}", ALLERGY_SEVERITIES.join(", ")));
+    }
+
+    errors.finish()
+}
+
+pub fn validate_new_medication(dto: &CreateMedicationDto) -> Result<(), AppError> {
+    let mut errors = FieldErrors::default();
+
+    errors.require("name", &dto.name, "Drug name");
+    if !is_din(dto.din.trim()) {
+        errors.add("din", "DIN must be exactly 8 digits");
+    }
+    if let Some(ndc) = dto.ndc.as_deref().filter(|n| !n.trim().is_empty()) {
+        if !is_ndc(ndc.trim()) {
+            errors.add("ndc", "NDC must be 4-4-2, 5-3-2 or 5-4-1 digits (e.g. 12345-678-90)");
+        }
+    }
+    if dto.stock < 0 {
+        errors.add("stock", "Stock cannot be negative");
+    }
+    if !dto.price.is_finite() || dto.price < 0.0 {
+        errors.add("price", "Price must be zero or more");
+    }
+    if parse_date(&dto.expiration).is_none() {
+        errors.add("expiration", "Expiration must be a valid date (YYYY-MM-DD)");
+    }
+    check_reorder(&mut errors, dto.reorder_point, dto.reorder_qty);
+    check_schedule(&mut errors, dto.schedule.as_deref());
+
+    errors.finish()
+}
+
+pub fn validate_medication_update(dto: &UpdateMedicationDto) -> Result<(), AppError> {
+    let mut errors = FieldErrors::default();
+
+    if !dto.price.is_finite() || dto.price < 0.0 {
+        errors.add("price", "Price must be zero or more");
+    }
+    check_reorder(&mut errors, dto.reorder_point, dto.reorder_qty);
+    check_schedule(&mut errors, dto.schedule.as_deref());
+
+    errors.finish()
+}
+
+fn check_schedule(errors: &mut FieldErrors, schedule: Option<&str>) {
+    if let Some(code) = schedule.map(str::trim).filter(|s| !s.is_empty()) {
+        if !controlled::is_schedule(code) {
+            errors.add("schedule", format!("Unknown schedule '{}'", code));
+        }
+    }
+}
+
+fn check_reorder(errors: &mut FieldErrors, reorder_point: Option<i32>, reorder_qty: Option<i32>) {
+    if reorder_point.is_some_and(|p| p < 0) {
+        errors.add("reorder_point", "Reorder point cannot be negative");
+    }
+    if reorder_qty.is_some_and(|q| q < 0) {
+        errors.add("reorder_qty", "Reorder quantity cannot be negative");
+    }
+}
+
+pub fn validate_lot(dto: &ReceiveLotDto) -> Result<(), AppError> {
+    let mut errors = FieldErrors::default();
+
+    errors.require("lot_number", &dto.lot_number, "Lot number");
+    if parse_date(&dto.expiration).is_none() {
+        errors.add("expiration", "Expiration must be a valid date (YYYY-MM-DD)");
+    }
+    if dto.quantity <= 0 {
+        errors.add("quantity", "Quantity received must be more than zero");
+    }
+
+    errors.finish()
+}
+
+pub fn validate_supplier(dto: &CreateSupplierDto) -> Result<(), AppError> {
+    let mut errors = FieldErrors::default();
+
+    errors.require("name", &dto.name, "Supplier name");
+    if let Some(email) = dto.email.as_deref().filter(|e| !e.trim().is_empty()) {
+        if !email.contains('@') {
+            errors.add("email", "Email address looks invalid");
+        }
+    }
+
+    errors.finish()
+}
+
+/// Errors name the 1-based line of the order.
+pub fn validate_purchase_order(dto: &CreatePurchaseOrderDto) -> Result<(), AppError> {
+    let mut errors = FieldErrors::default();
+
+    if dto.lines.is_empty() {
+        errors.add("lines", "A purchase order needs at least one line");
+    }
+    for (i, line) in dto.lines.iter().enumerate() {
+        let row = i + 1;
+        if line.quantity <= 0 {
+            errors.add("lines", format!("Line {}: quantity must be more than zero", row));
+        }
+        if line.unit_cost.is_some_and(|c| !c.is_finite() || c < 0.0) {
+            errors.add("lines", format!("Line {}: cost must be zero or more", row));
+        }
+        if dto.lines[..i].iter().any(|l| l.medication_id == line.medication_id) {
+            errors.add("lines", format!("Line {}: drug is already on this order", row));
+        }
+    }
+
+    errors.finish()
+}
+
+pub fn validate_po_receipt(dto: &ReceivePoLineDto) -> Result<(), AppError> {
+    let mut errors = FieldErrors::default();
+
+    errors.require("lot_number", &dto.lot_number, "Lot number");
+    if parse_date(&dto.expiration).is_none() {
+        errors.add("expiration", "Expiration must be a valid date (YYYY-MM-DD)");
+    }
+    if dto.quantity <= 0 {
+        errors.add("quantity", "Quantity received must be more than zero");
+    }
+    if !dto.unit_cost.is_finite() || dto.unit_cost < 0.0 {
+        errors.add("unit_cost", "Cost must be zero or more");
+    }
+
+    errors.finish()
+}
+
+pub fn validate_ingredients(tags: &[IngredientTag]) -> Result<(), AppError> {
+    let mut errors = FieldErrors::default();
+
+    for tag in tags {
+        if tag.name.trim().is_empty() {
+            errors.add("ingredients", "Ingredient names cannot be blank");
+        }
+        if tag.kind != "ingredient" && tag.kind != "class" {
+            errors.add("ingredients", format!("'{}' must be tagged as ingredient or class", tag.name));
+        }
+    }
+
+    errors.finish()
+}
+
+/// Errors name the 1-based row of the import file.
+pub fn validate_interactions(records: &[InteractionRecord]) -> Result<(), AppError> {
+    let mut errors = FieldErrors::default();
+
+    if records.is_empty() {
+        errors.add("records", "The import file contains no interactions");
+    }
+    for (i, r) in records.iter().enumerate() {
+        let row = i + 1;
+        if r.term_a.trim().is_empty() || r.term_b.trim().is_empty() {
+            errors.add("records", format!("Row {}: both drug/class names are required", row));
+        }
+        if !INTERACTION_SEVERITIES.contains(&r.severity.as_str()) {
+            errors.add("records", format!("Row {}: severity must be one of: {}", row, INTERACTION_SEVERITIES.join(", ")));
+        }
+        if r.monograph.trim().is_empty() {
+            errors.add("records", format!("Row {}: monograph text is required", row));
+        }
+    }
+
+    errors.finish()
+}
+
+pub fn validate_prescription(dto: &CreatePrescriptionDto) -> Result<(), AppError> {
+    let mut errors = FieldErrors::default();
+
+    errors.require("prescriber", &dto.prescriber, "Prescriber");
+    errors.require("sig", &dto.sig, "Directions (sig)");
+
+    if dto.quantity <= 0 {
+        errors.add("quantity", "Quantity must be greater than zero");
+    }
+    if dto.days_supply <= 0 {
+        errors.add("days_supply", "Days supply must be greater than zero");
+    }
+    if dto.refills < 0 {
+        errors.add("refills", "Refills cannot be negative");
+    }
+    match parse_date(&dto.date_filled) {
+        None => errors.add("date_filled", "Fill date must be a valid date (YYYY-MM-DD)"),
+        Some(d) if d > today() => errors.add("date_filled", "Fill date cannot be in the future"),
+        Some(_) => {}
+    }
+
+    errors.finish()
+}
+
+pub fn validate_audit_filter(filter: &AuditLogFilter) -> Result<(), AppError> {
+    let mut errors = FieldErrors::default();
+
+    let date = |value: &Option<String>| value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(parse_date);
+    let from = date(&filter.from_date);
+    let to = date(&filter.to_date);
+    if from.is_some_and(|d| d.is_none()) {
+        errors.add("from_date", "From date must be a valid date (YYYY-MM-DD)");
+    }
+    if to.is_some_and(|d| d.is_none()) {
+        errors.add("to_date", "To date must be a valid date (YYYY-MM-DD)");
+    }
+    if let (Some(Some(from)), Some(Some(to))) = (from, to) {
+        if from > to {
+            errors.add("to_date", "To date cannot be before the from date");
+        }
+    }
+
+    errors.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fields named in a validation error, in the order they were reported.
+    fn failed_fields(result: Result<(), AppError>) -> Vec<String> {
+        match result {
+            Ok(()) => vec![],
+            Err(AppError::Validation(errors)) => errors.into_iter().map(|e| e.field).collect(),
+            Err(other) => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn din_is_exactly_eight_digits() {
+        assert!(is_din("02238888"));
+        assert!(!is_din("2238888"));
+        assert!(!is_din("022388889"));
+        assert!(!is_din("0223888A"));
+        assert!(!is_din(" 02238888"));
+        assert!(!is_din(""));
+    }
+
+    #[test]
+    fn phone_accepts_north_american_numbers() {
+        for ok in ["416-555-0199", "(416) 555-0199", "416.555.0199", "4165550199", "+1 416 555 0199", "1-416-555-0199"] {
+            assert!(is_phone(ok), "{} should be accepted", ok);
+        }
+        for bad in ["555-0199", "2-416-555-0199", "416-555-01999", "416-555-O199", "ext 416 555 0199"] {
+            assert!(!is_phone(bad), "{} should be rejected", bad);
+        }
+    }
+
+    #[test]
+    fn dates_are_strict_iso() {
+        assert_eq!(parse_date("2024-02-29"), NaiveDate::from_ymd_opt(2024, 2, 29));
+        assert!(parse_date("2023-02-29").is_none());
+        assert!(parse_date("2024-13-01").is_none());
+        assert!(parse_date("2024-1-5").is_none());
+        assert!(parse_date("01/05/2024").is_none());
+        assert!(parse_date("2024-01-05T00:00").is_none());
+    }
+
+    #[test]
+    fn ndc_layouts() {
+        for ok in ["1234-5678-90", "12345-678-90", "12345-6789-0", "12345-6789-01", "1234567890", "12345678901"] {
+            assert!(is_ndc(ok), "{} should be accepted", ok);
+        }
+        for bad in ["123-4567-890", "12345--6789", "12345-678-9A", "123456789"] {
+            assert!(!is_ndc(bad), "{} should be rejected", bad);
+        }
+    }
+
+    #[test]
+    fn postal_code_must_match_province() {
+        assert!(is_canadian_postal_code("M5V 2T6", "ON"));
+        assert!(is_canadian_postal_code("m5v2t6", "ON"));
+        assert!(!is_canadian_postal_code("V6B 1A1", "ON"));
+        assert!(!is_canadian_postal_code("M5V 2T", "ON"));
+        assert!(is_zip_code("90210") && is_zip_code("90210-1234"));
+        assert!(!is_zip_code("9021") && !is_zip_code("90210-12"));
+    }
+
+    fn prescription(quantity: i32, days_supply: i32, refills: i32) -> CreatePrescriptionDto {
+        CreatePrescriptionDto {
+            patient_id: 1,
+            medication_id: 1,
+            prescriber: "Dr. Hibbert".to_string(),
+            sig: "Take 1 daily".to_string(),
+            quantity,
+            refills,
+            days_supply,
+            date_filled: "2024-01-01".to_string(),
+            override_reason: None,
+            early_fill_code: None,
+        }
+    }
+
+    #[test]
+    fn prescription_quantities() {
+        assert!(failed_fields(validate_prescription(&prescription(30, 30, 0))).is_empty());
+        assert_eq!(failed_fields(validate_prescription(&prescription(0, 30, 0))), ["quantity"]);
+        assert_eq!(failed_fields(validate_prescription(&prescription(30, -1, 0))), ["days_supply"]);
+        assert_eq!(failed_fields(validate_prescription(&prescription(-5, 0, -1))), ["quantity", "days_supply", "refills"]);
+    }
+
+    #[test]
+    fn prescription_cannot_be_filled_in_the_future() {
+        let mut rx = prescription(30, 30, 0);
+        rx.date_filled = (today() + chrono::Days::new(1)).format("%Y-%m-%d").to_string();
+        assert_eq!(failed_fields(validate_prescription(&rx)), ["date_filled"]);
+    }
+
+    #[test]
+    fn lot_quantity_must_be_positive() {
+        let lot = |quantity| ReceiveLotDto { medication_id: 1, lot_number: "A123".to_string(), expiration: "2030-01-01".to_string(), quantity };
+        assert!(failed_fields(validate_lot(&lot(10))).is_empty());
+        assert_eq!(failed_fields(validate_lot(&lot(0))), ["quantity"]);
+        assert_eq!(failed_fields(validate_lot(&lot(-3))), ["quantity"]);
+    }
+}
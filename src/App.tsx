@@ -1,4 +1,5 @@
-import { createSignal, Show } from "solid-js";
+import { createSignal, onCleanup, Show } from "solid-js";
+import { invoke } from "@tauri-apps/api/core";
 import "./App.css";
 import Dashboard from "./components/Dashboard";
 import PatientManager from "./components/PatientManager";
@@ -6,6 +7,8 @@ import Inventory from "./components/Inventory";
 import PrescriptionManager from "./components/PrescriptionManager";
 import Login from "./components/Login";
 import LogViewer from "./components/LogViewer";
+import Purchasing from "./components/Purchasing";
+import InventoryCount from "./components/InventoryCount";
 
 function App() {
   const [currentUser, setCurrentUser] = createSignal<{username: string, role: string} | null>(null);
@@ -15,11 +18,23 @@ function App() {
     setCurrentUser(user);
   };
 
-  const handleLogout = () => {
+  const handleLogout = async () => {
+    try { await invoke("logout"); } catch (e) { console.error(e); }
     setCurrentUser(null);
     setCurrentView("dashboard");
   };
 
+  // The backend drops idle sessions; poll so the UI returns to the login screen too
+  const sessionPoll = setInterval(async () => {
+    if (!currentUser()) return;
+    const session = await invoke<any>("get_session");
+    if (!session) {
+      setCurrentUser(null);
+      setCurrentView("dashboard");
+    }
+  }, 30000);
+  onCleanup(() => clearInterval(sessionPoll));
+
   return (
     <>
       <Show when={!currentUser()}>
@@ -34,6 +49,8 @@ function App() {
             <button class={currentView() === "dashboard" ? "nav-btn active" : "nav-btn"} onClick={() => setCurrentView("dashboard")}>Dashboard</button>
             <button class={currentView() === "patients" ? "nav-btn active" : "nav-btn"} onClick={() => setCurrentView("patients")}>Patients</button>
             <button class={currentView() === "inventory" ? "nav-btn active" : "nav-btn"} onClick={() => setCurrentView("inventory")}>Inventory</button>
+            <button class={currentView() === "purchasing" ? "nav-btn active" : "nav-btn"} onClick={() => setCurrentView("purchasing")}>Purchasing</button>
+            <button class={currentView() === "counts" ? "nav-btn active" : "nav-btn"} onClick={() => setCurrentView("counts")}>Counts</button>
             <button class={currentView() === "rx" ? "nav-btn active" : "nav-btn"} onClick={() => setCurrentView("rx")}>New Prescription</button>
             
             <Show when={currentUser()?.role === "admin"}>
@@ -59,6 +76,12 @@ function App() {
             <Show when={currentView() === "inventory"}>
                 <Inventory currentUser={currentUser()} />
             </Show>
+            <Show when={currentView() === "purchasing"}>
+                <Purchasing />
+            </Show>
+            <Show when={currentView() === "counts"}>
+                <InventoryCount currentUser={currentUser()} />
+            </Show>
             <Show when={currentView() === "rx"}>
                 <PrescriptionManager currentUser={currentUser()} />
             </Show>
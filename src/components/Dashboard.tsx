@@ -1,6 +1,7 @@
 import { createSignal, onMount, Show, For, type Component } from 'solid-js';
 import { invoke } from "@tauri-apps/api/core";
-import { setRefillQueue } from "../store"; 
+import { errorMessage } from "../errors";
+import { invokeWithOverrides } from "../overrides";
 
 interface DueRx {
   id: number;
@@ -14,21 +15,38 @@ interface DueRx {
   sig: string;
   days_supply: number;
   refills: number;
+  refills_remaining: number;
+  expires_on: string | null;
   prescriber: string;
 }
 
+interface ExpiringLot {
+  lot_id: number;
+  medication_name: string;
+  din: string;
+  lot_number: string;
+  expiration: string;
+  quantity: number;
+  expired: boolean;
+}
+
 interface DashboardProps {
   onNavigate?: (view: string) => void;
 }
 
-const Dashboard: Component<DashboardProps> = (props) => {
-  const [stats, setStats] = createSignal({ due_today: 0, due_soon: 0, low_stock: 0 });
+const Dashboard: Component<DashboardProps> = (_props) => {
+  const [stats, setStats] = createSignal({ due_today: 0, due_soon: 0, low_stock: 0, expiring: 0 });
   const [upcomingList, setUpcomingList] = createSignal<DueRx[]>([]); // <--- NEW STATE
   
   // Modal State
   const [isModalOpen, setModalOpen] = createSignal(false);
   const [modalTitle, setModalTitle] = createSignal("");
   const [dueList, setDueList] = createSignal<DueRx[]>([]);
+  const [refillMsg, setRefillMsg] = createSignal("");
+
+  // Expiry Modal State
+  const [isExpiryOpen, setExpiryOpen] = createSignal(false);
+  const [expiringList, setExpiringList] = createSignal<ExpiringLot[]>([]);
 
   async function loadData() {
     try {
@@ -57,16 +75,47 @@ const Dashboard: Component<DashboardProps> = (props) => {
     } catch (e) { console.error(e); }
   }
 
+  async function openExpiring() {
+    try {
+      setExpiringList(await invoke<ExpiringLot[]>("get_expiring_stock"));
+      setExpiryOpen(true);
+    } catch (e) { console.error(e); }
+  }
+
+  async function handleQuarantine() {
+    try {
+      setRefillMsg(await invoke<string>("quarantine_expired_stock"));
+      setExpiryOpen(false);
+      loadData();
+    } catch (err) {
+      setRefillMsg(`Quarantine failed: ${errorMessage(err)}`);
+    }
+  }
+
   // --- REFILL ACTION ---
-  function handleProcessRefill(rx: DueRx) {
-    setRefillQueue(rx);
+  async function handleProcessRefill(rx: DueRx) {
     setModalOpen(false);
-    if (props.onNavigate) props.onNavigate("rx"); 
+    try {
+      const msg = await invokeWithOverrides<string>("refill_prescription", (o) => ({
+        prescriptionId: rx.id, overrideReason: o.override_reason, earlyFillCode: o.early_fill_code,
+      }));
+      if (msg === null) {
+        setRefillMsg(`${rx.patient_name} – ${rx.medication_name}: not filled.`);
+        return;
+      }
+      setRefillMsg(`${rx.patient_name} – ${rx.medication_name}: ${msg}`);
+      loadData();
+    } catch (err) {
+      setRefillMsg(`Refill failed: ${errorMessage(err)}`);
+    }
   }
 
   return (
     <div class="p-content">
       <h2>Dashboard Overview</h2>
+      <Show when={refillMsg()}>
+        <div class="alert-box">{refillMsg()}</div>
+      </Show>
       
       <div class="stats-grid">
         <div 
@@ -90,6 +139,10 @@ const Dashboard: Component<DashboardProps> = (props) => {
             <h3>{stats().low_stock}</h3>
             <p>Stock Warnings</p>
         </div>
+        <div class="stat-card alert clickable" onClick={openExpiring}>
+            <h3>{stats().expiring}</h3>
+            <p>Expiring / Expired Lots</p>
+        </div>
       </div>
 
       {/* --- NEW SECTION: UPCOMING LIST --- */}
@@ -116,7 +169,7 @@ const Dashboard: Component<DashboardProps> = (props) => {
                                         {item.next_refill_date}
                                     </td>
                                     <td class="fw-bold">{item.patient_name}</td>
-                                    <td>{item.medication_name}</td>
+                                    <td>{item.medication_name} <span class="text-muted">({item.refills_remaining} refills left)</span></td>
                                     <td class="text-muted">{item.phone}</td>
                                     <td>
                                         <button class="btn-small" onClick={() => handleProcessRefill(item)}>
@@ -169,6 +222,43 @@ const Dashboard: Component<DashboardProps> = (props) => {
           </div>
         </div>
       </Show>
+
+      <Show when={isExpiryOpen()}>
+        <div class="modal-overlay" onClick={(e) => { if(e.target===e.currentTarget) setExpiryOpen(false) }}>
+          <div class="modal" style="width: 700px">
+            <div class="modal-header">
+              <h3>Expiring Stock</h3>
+              <button class="close-btn" onClick={() => setExpiryOpen(false)}>×</button>
+            </div>
+            <div class="modal-form">
+              <table class="patient-table">
+                <thead>
+                  <tr><th>Drug</th><th>Lot</th><th>Expiry</th><th>Qty</th></tr>
+                </thead>
+                <tbody>
+                  <For each={expiringList()}>
+                    {(lot) => (
+                      <tr>
+                        <td class="fw-bold">{lot.medication_name}</td>
+                        <td>{lot.lot_number}</td>
+                        <td style={lot.expired ? "color: #ef4444; font-weight: bold" : ""}>
+                          {lot.expiration}{lot.expired ? " (expired)" : ""}
+                        </td>
+                        <td>{lot.quantity}</td>
+                      </tr>
+                    )}
+                  </For>
+                </tbody>
+              </table>
+              <Show when={expiringList().some((l) => l.expired)}>
+                <div class="modal-footer">
+                  <button class="btn-primary" onClick={handleQuarantine}>Quarantine Expired Stock</button>
+                </div>
+              </Show>
+            </div>
+          </div>
+        </div>
+      </Show>
     </div>
   );
 };
@@ -1,5 +1,6 @@
 import { createSignal, onMount, For, Show, type Component } from "solid-js";
 import { invoke } from "@tauri-apps/api/core";
+import { errorMessage } from "../errors";
 
 // Match the Rust Struct exactly
 interface Medication {
@@ -11,13 +12,84 @@ interface Medication {
   stock: number;
   price: number;
   expiration: string;
+  controlled: boolean;
+  reorder_point: number;
+  reorder_qty: number;
+  auto_reorder: boolean;
+  schedule: string | null;
+}
+
+interface LowStockItem {
+  medication_id: number;
+  name: string;
+  din: string;
+  stock: number;
+  reorder_point: number;
+  reorder_qty: number;
+  auto_reorder: boolean;
+  daily_usage: number;
+  days_of_cover: number | null;
+  suggested_qty: number;
+}
+
+interface MedicationLot {
+  id: number;
+  lot_number: string;
+  expiration: string;
+  quantity: number;
+  received_at: string;
+}
+
+interface LotFill {
+  fill_id: number;
+  patient_name: string;
+  phone: string;
+  quantity: number;
+  date_filled: string;
+  voided_at?: string;
+}
+
+interface StockMovement {
+  id: number;
+  lot_number: string;
+  kind: string;
+  quantity: number;
+  reason_code: string;
+  username: string;
+  note?: string;
+  witness?: string;
+  created_at: string;
+  balance: number;
+}
+
+interface RegisterEntry {
+  id: number;
+  kind: string;
+  quantity: number;
+  balance: number;
+  lot_number: string | null;
+  reason_code: string;
+  recorded_by: string;
+  witness: string | null;
+  created_at: string;
+}
+
+interface RegisterReconciliation {
+  medication_id: number;
+  name: string;
+  din: string;
+  schedule: string;
+  stock: number;
+  register_balance: number | null;
+  last_entry_at: string | null;
+  discrepancy: boolean;
 }
 
 interface InventoryProps {
   currentUser: { username: string; role: string } | null;
 }
 
-const Inventory: Component<InventoryProps> = (props) => {
+const Inventory: Component<InventoryProps> = (_props) => {
   const [medList, setMedList] = createSignal<Medication[]>([]);
   
   // Modal State
@@ -33,6 +105,28 @@ const Inventory: Component<InventoryProps> = (props) => {
   const [stock, setStock] = createSignal<number | "">("");
   const [price, setPrice] = createSignal<number | "">("");
   const [exp, setExp] = createSignal("");
+  const [controlled, setControlled] = createSignal(false);
+  const [lotNumber, setLotNumber] = createSignal("");
+  const [reorderPoint, setReorderPoint] = createSignal<number | "">("");
+  const [reorderQty, setReorderQty] = createSignal<number | "">("");
+  const [autoReorder, setAutoReorder] = createSignal(false);
+  const [schedule, setSchedule] = createSignal("");
+  const [schedules, setSchedules] = createSignal<{ code: string; label: string }[]>([]);
+
+  // Reorder list (items at or below their reorder point)
+  const [lowStock, setLowStock] = createSignal<LowStockItem[] | null>(null);
+
+  // Lots (edit mode): stock and expiry are derived from these
+  const [lots, setLots] = createSignal<MedicationLot[]>([]);
+  const [newLot, setNewLot] = createSignal("");
+  const [newLotExp, setNewLotExp] = createSignal("");
+  const [newLotQty, setNewLotQty] = createSignal<number | "">("");
+  const [recall, setRecall] = createSignal<{ lot: string; fills: LotFill[] } | null>(null);
+  const [movements, setMovements] = createSignal<StockMovement[] | null>(null);
+
+  // Controlled substance register (scheduled drugs) and reconciliation report
+  const [register, setRegister] = createSignal<RegisterEntry[] | null>(null);
+  const [reconciliation, setReconciliation] = createSignal<RegisterReconciliation[] | null>(null);
 
   const [statusMsg, setStatusMsg] = createSignal("");
 
@@ -46,14 +140,117 @@ const Inventory: Component<InventoryProps> = (props) => {
     }
   }
 
-  onMount(fetchMeds);
+  onMount(async () => {
+    fetchMeds();
+    try {
+      setSchedules(await invoke("get_schedules"));
+    } catch (e) {
+      console.error(e);
+    }
+  });
+
+  async function openLowStock() {
+    try {
+      setLowStock(await invoke<LowStockItem[]>("get_low_stock_items"));
+    } catch (e) {
+      console.error(e);
+    }
+  }
+
+  async function fetchLots(medicationId: number) {
+    try {
+      setLots(await invoke("get_medication_lots", { medicationId }));
+    } catch (e) {
+      console.error(e);
+    }
+  }
+
+  async function receiveLot() {
+    const id = editingId();
+    if (id === null) return;
+    try {
+      setStatusMsg(await invoke("receive_lot", {
+        data: { medication_id: id, lot_number: newLot(), expiration: newLotExp(), quantity: Number(newLotQty()) }
+      }));
+      setNewLot(""); setNewLotExp(""); setNewLotQty("");
+      fetchLots(id);
+      fetchMeds();
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  async function adjustLot(lot: MedicationLot) {
+    const id = editingId();
+    if (id === null) return;
+    try {
+      const reasons = await invoke<{ code: string; label: string }[]>("get_adjustment_reasons");
+      const list = reasons.map((r, i) => `${i + 1}. ${r.label}`).join("\n");
+      const answer = prompt(`Lot ${lot.lot_number} has ${lot.quantity}. Reason number:\n${list}`)?.trim();
+      const picked = answer ? reasons[Number(answer) - 1] : undefined;
+      if (!picked) return;
+      const change = prompt("Quantity change (negative removes stock):");
+      if (!change) return;
+      const note = prompt("Note (optional):") || null;
+      // Removing a scheduled drug is witnessed (the backend requires it)
+      let witness = null;
+      if (medList().find((m) => m.id === id)?.schedule && Number(change) < 0) {
+        const username = prompt("Witness username:");
+        if (!username) return;
+        const password = prompt(`Password for ${username}:`) ?? "";
+        witness = { username, password };
+      }
+      setStatusMsg(await invoke("adjust_lot", { lotId: lot.id, change: Number(change), reasonCode: picked.code, note, witness }));
+      fetchLots(id);
+      fetchMeds();
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  async function showMovements() {
+    const id = editingId();
+    if (id === null) return;
+    try {
+      setMovements(await invoke<StockMovement[]>("get_stock_movements", { medicationId: id }));
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  async function showRegister() {
+    const id = editingId();
+    if (id === null) return;
+    try {
+      setRegister(await invoke<RegisterEntry[]>("get_controlled_register", { medicationId: id }));
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  async function openReconciliation() {
+    try {
+      setReconciliation(await invoke<RegisterReconciliation[]>("get_controlled_reconciliation"));
+    } catch (e) {
+      console.error(e);
+    }
+  }
+
+  async function showRecall(lot: MedicationLot) {
+    try {
+      setRecall({ lot: lot.lot_number, fills: await invoke("get_lot_fills", { lotId: lot.id }) });
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
 
   // Open "Add" Modal
   function openAdd() {
     setModalMode("add");
     setEditingId(null);
     // Clear form
-    setName(""); setDin(""); setNdc(""); setDesc(""); setStock(""); setPrice(""); setExp("");
+    setName(""); setDin(""); setNdc(""); setDesc(""); setStock(""); setPrice(""); setExp(""); setControlled(false); setLotNumber("");
+    setReorderPoint(""); setReorderQty(""); setAutoReorder(false); setSchedule("");
     setStatusMsg("");
     setModalOpen(true);
   }
@@ -70,7 +267,16 @@ const Inventory: Component<InventoryProps> = (props) => {
     setStock(med.stock);
     setPrice(med.price);
     setExp(med.expiration);
+    setControlled(med.controlled);
+    setReorderPoint(med.reorder_point);
+    setReorderQty(med.reorder_qty);
+    setAutoReorder(med.auto_reorder);
+    setSchedule(med.schedule ?? "");
     setStatusMsg("");
+    setRecall(null);
+    setMovements(null);
+    setRegister(null);
+    fetchLots(med.id);
     setModalOpen(true);
   }
 
@@ -80,26 +286,32 @@ const Inventory: Component<InventoryProps> = (props) => {
 
     const stockVal = Number(stock());
     const priceVal = Number(price());
+    const reorder = {
+      reorder_point: reorderPoint() === "" ? null : Number(reorderPoint()),
+      reorder_qty: reorderQty() === "" ? null : Number(reorderQty()),
+      auto_reorder: autoReorder(),
+      schedule: schedule(),
+    };
 
     try {
       if (modalMode() === "add") {
         // --- ADD LOGIC ---
         await invoke("add_medication", { 
             data: { 
-                logged_in_user: props.currentUser?.username || "unknown",
                 name: name(), din: din(), ndc: ndc() || null, description: desc() || null,
-                stock: stockVal, price: priceVal, expiration: exp()
+                stock: stockVal, price: priceVal, expiration: exp(), controlled: controlled(),
+                lot_number: lotNumber() || null, ...reorder
             } 
         });
       } else {
         // --- EDIT LOGIC ---
         await invoke("update_medication", {
             data: {
-                logged_in_user: props.currentUser?.username || "unknown",
                 id: editingId(),
-                stock: stockVal,
                 price: priceVal,
-                description: desc() || null
+                description: desc() || null,
+                controlled: controlled(),
+                ...reorder
                 // Name/DIN not sent to prevent identity changes
             }
         });
@@ -110,7 +322,7 @@ const Inventory: Component<InventoryProps> = (props) => {
       fetchMeds();
     } catch (err) {
       console.error(err);
-      setStatusMsg("Error: " + err);
+      setStatusMsg("Error: " + errorMessage(err));
     }
   }
 
@@ -118,7 +330,11 @@ const Inventory: Component<InventoryProps> = (props) => {
     <div class="p-content">
       <div class="header-row">
         <div><h2>Inventory</h2><p class="subtitle">{medList().length} Items</p></div>
-        <button class="btn-primary" onClick={openAdd}>+ Add Drug</button>
+        <div>
+          <button class="btn-small" onClick={openReconciliation}>Narcotic Reconciliation</button>
+          <button class="btn-small" onClick={openLowStock}>Reorder List</button>
+          <button class="btn-primary" onClick={openAdd}>+ Add Drug</button>
+        </div>
       </div>
 
       <div class="panel table-panel">
@@ -134,8 +350,8 @@ const Inventory: Component<InventoryProps> = (props) => {
                 {(med) => (
                   <tr>
                     <td class="text-muted">{med.din}</td>
-                    <td class="fw-bold">{med.name}</td>
-                    <td style={med.stock < 100 ? "color:red" : ""}>{med.stock}</td>
+                    <td class="fw-bold">{med.name}{med.schedule ? ` (${med.schedule.charAt(0).toUpperCase()})` : med.controlled ? " (C)" : ""}</td>
+                    <td style={med.stock <= med.reorder_point ? "color:red" : ""}>{med.stock}</td>
                     <td>${med.price.toFixed(2)}</td>
                     <td>
                       <button class="btn-small" onClick={() => openEdit(med)}>Edit</button>
@@ -151,6 +367,75 @@ const Inventory: Component<InventoryProps> = (props) => {
         </div>
       </div>
 
+      {/* REORDER LIST */}
+      <Show when={lowStock()}>
+        {(items) => (
+          <div class="modal-overlay" onClick={(e) => { if (e.target === e.currentTarget) setLowStock(null) }}>
+            <div class="modal" style="width: 700px">
+              <div class="modal-header">
+                <h3>Reorder List</h3>
+                <button class="close-btn" onClick={() => setLowStock(null)}>×</button>
+              </div>
+              <div class="modal-form">
+                <table class="patient-table">
+                  <thead>
+                    <tr><th>Drug</th><th>Stock</th><th>Reorder At</th><th>Per Day</th><th>Days Left</th><th>Order</th></tr>
+                  </thead>
+                  <tbody>
+                    <For each={items()} fallback={<tr><td colspan="6" class="empty-state">Nothing below its reorder point.</td></tr>}>
+                      {(item) => (
+                        <tr>
+                          <td class="fw-bold">{item.name}{item.auto_reorder ? " (auto)" : ""}</td>
+                          <td>{item.stock}</td>
+                          <td>{item.reorder_point}</td>
+                          <td>{item.daily_usage.toFixed(1)}</td>
+                          <td>{item.days_of_cover === null ? "—" : Math.floor(item.days_of_cover)}</td>
+                          <td>{item.suggested_qty}</td>
+                        </tr>
+                      )}
+                    </For>
+                  </tbody>
+                </table>
+              </div>
+            </div>
+          </div>
+        )}
+      </Show>
+
+      {/* CONTROLLED SUBSTANCE RECONCILIATION */}
+      <Show when={reconciliation()}>
+        {(rows) => (
+          <div class="modal-overlay" onClick={(e) => { if (e.target === e.currentTarget) setReconciliation(null) }}>
+            <div class="modal" style="width: 700px">
+              <div class="modal-header">
+                <h3>Narcotic Reconciliation</h3>
+                <button class="close-btn" onClick={() => setReconciliation(null)}>×</button>
+              </div>
+              <div class="modal-form">
+                <table class="patient-table">
+                  <thead>
+                    <tr><th>Drug</th><th>Schedule</th><th>Register</th><th>Stock</th><th>Last Entry</th></tr>
+                  </thead>
+                  <tbody>
+                    <For each={rows()} fallback={<tr><td colspan="5" class="empty-state">No scheduled drugs.</td></tr>}>
+                      {(r) => (
+                        <tr style={r.discrepancy ? "color:red" : ""}>
+                          <td class="fw-bold">{r.name}{r.discrepancy ? " ⚠" : ""}</td>
+                          <td>{r.schedule}</td>
+                          <td>{r.register_balance ?? "—"}</td>
+                          <td>{r.stock}</td>
+                          <td class="text-muted">{r.last_entry_at ?? "—"}</td>
+                        </tr>
+                      )}
+                    </For>
+                  </tbody>
+                </table>
+              </div>
+            </div>
+          </div>
+        )}
+      </Show>
+
       {/* MODAL FORM */}
       <Show when={isModalOpen()}>
         <div class="modal-overlay">
@@ -168,21 +453,136 @@ const Inventory: Component<InventoryProps> = (props) => {
                             <input value={din()} onInput={(e)=>setDin(e.currentTarget.value)} disabled={modalMode()==="edit"} required />
                         </label>
                         <label>Stock 
-                            <input type="number" value={stock()} onInput={(e)=>setStock(e.currentTarget.valueAsNumber)} required />
+                            <input type="number" value={stock()} onInput={(e)=>setStock(e.currentTarget.valueAsNumber)} disabled={modalMode()==="edit"} required />
                         </label>
                         <label>Price 
                             <input type="number" step="0.01" value={price()} onInput={(e)=>setPrice(e.currentTarget.valueAsNumber)} required />
                         </label>
                         <label>Expiration 
-                            <input type="date" value={exp()} onInput={(e)=>setExp(e.currentTarget.value)} required />
+                            <input type="date" value={exp()} onInput={(e)=>setExp(e.currentTarget.value)} disabled={modalMode()==="edit"} required />
                         </label>
+                        <Show when={modalMode()==="add"}>
+                            <label>Lot Number 
+                                <input value={lotNumber()} onInput={(e)=>setLotNumber(e.currentTarget.value)} />
+                            </label>
+                        </Show>
                         <label>Description 
                             <input value={desc()} onInput={(e)=>setDesc(e.currentTarget.value)} />
                         </label>
                         <label>NDC (Optional) 
                             <input value={ndc()} onInput={(e)=>setNdc(e.currentTarget.value)} disabled={modalMode()==="edit"} />
                         </label>
+                        <label>Reorder Point 
+                            <input type="number" value={reorderPoint()} onInput={(e)=>setReorderPoint(e.currentTarget.valueAsNumber)} disabled={autoReorder()} />
+                        </label>
+                        <label>Reorder Qty 
+                            <input type="number" value={reorderQty()} onInput={(e)=>setReorderQty(e.currentTarget.valueAsNumber)} disabled={autoReorder()} />
+                        </label>
+                        <label>Auto (from dispensing) 
+                            <input type="checkbox" checked={autoReorder()} onChange={(e)=>setAutoReorder(e.currentTarget.checked)} />
+                        </label>
+                        <label>Controlled Substance 
+                            <input type="checkbox" checked={controlled() || schedule() !== ""} onChange={(e)=>setControlled(e.currentTarget.checked)} disabled={schedule() !== ""} />
+                        </label>
+                        <label>Schedule 
+                            <select value={schedule()} onChange={(e)=>setSchedule(e.currentTarget.value)}>
+                                <option value="">Unscheduled</option>
+                                <For each={schedules()}>{(s) => <option value={s.code}>{s.label}</option>}</For>
+                            </select>
+                        </label>
                     </div>
+                    <Show when={modalMode()==="edit"}>
+                        <h4>Lots</h4>
+                        <table class="patient-table">
+                            <thead><tr><th>Lot</th><th>Expiry</th><th>Qty</th><th>Received</th><th></th></tr></thead>
+                            <tbody>
+                                <For each={lots()}>
+                                    {(lot) => (
+                                        <tr>
+                                            <td>{lot.lot_number}</td>
+                                            <td>{lot.expiration}</td>
+                                            <td>{lot.quantity}</td>
+                                            <td class="text-muted">{lot.received_at}</td>
+                                            <td>
+                                                <button type="button" class="btn-small" onClick={() => adjustLot(lot)}>Adjust</button>
+                                                <button type="button" class="btn-small" onClick={() => showRecall(lot)}>Fills</button>
+                                            </td>
+                                        </tr>
+                                    )}
+                                </For>
+                            </tbody>
+                        </table>
+                        <div class="form-grid">
+                            <label>New Lot <input value={newLot()} onInput={(e)=>setNewLot(e.currentTarget.value)} /></label>
+                            <label>Expiry <input type="date" value={newLotExp()} onInput={(e)=>setNewLotExp(e.currentTarget.value)} /></label>
+                            <label>Qty <input type="number" value={newLotQty()} onInput={(e)=>setNewLotQty(e.currentTarget.valueAsNumber)} /></label>
+                            <button type="button" class="btn-small" onClick={receiveLot}>Receive</button>
+                            <button type="button" class="btn-small" onClick={showMovements}>Stock History</button>
+                            <Show when={schedule() !== ""}>
+                                <button type="button" class="btn-small" onClick={showRegister}>Register</button>
+                            </Show>
+                        </div>
+                        <Show when={register()}>
+                            {(rows) => (
+                                <table class="patient-table">
+                                    <thead><tr><th>When</th><th>Lot</th><th>Type</th><th>Qty</th><th>Balance</th><th>Reason</th><th>By</th><th>Witness</th></tr></thead>
+                                    <tbody>
+                                        <For each={rows()}>
+                                            {(r) => (
+                                                <tr>
+                                                    <td class="text-muted">{r.created_at}</td>
+                                                    <td>{r.lot_number ?? "—"}</td>
+                                                    <td>{r.kind}</td>
+                                                    <td style={r.quantity < 0 ? "color:red" : ""}>{r.quantity > 0 ? `+${r.quantity}` : r.quantity}</td>
+                                                    <td>{r.balance}</td>
+                                                    <td>{r.reason_code.replace(/_/g, " ")}</td>
+                                                    <td>{r.recorded_by}</td>
+                                                    <td>{r.witness ?? "—"}</td>
+                                                </tr>
+                                            )}
+                                        </For>
+                                    </tbody>
+                                </table>
+                            )}
+                        </Show>
+                        <Show when={movements()}>
+                            {(rows) => (
+                                <table class="patient-table">
+                                    <thead><tr><th>When</th><th>Lot</th><th>Type</th><th>Qty</th><th>Balance</th><th>Reason</th><th>By</th></tr></thead>
+                                    <tbody>
+                                        <For each={rows()}>
+                                            {(m) => (
+                                                <tr>
+                                                    <td class="text-muted">{m.created_at}</td>
+                                                    <td>{m.lot_number}</td>
+                                                    <td>{m.kind}</td>
+                                                    <td style={m.quantity < 0 ? "color:red" : ""}>{m.quantity > 0 ? `+${m.quantity}` : m.quantity}</td>
+                                                    <td>{m.balance}</td>
+                                                    <td>{m.reason_code.replace(/_/g, " ")}{m.note ? ` — ${m.note}` : ""}</td>
+                                                    <td>{m.username}{m.witness ? ` / ${m.witness}` : ""}</td>
+                                                </tr>
+                                            )}
+                                        </For>
+                                    </tbody>
+                                </table>
+                            )}
+                        </Show>
+                        <Show when={recall()}>
+                            {(r) => (
+                                <div>
+                                    <h4>Fills from lot {r().lot}</h4>
+                                    <For each={r().fills} fallback={<p class="text-muted">Nothing dispensed from this lot.</p>}>
+                                        {(f) => (
+                                            <p style={f.voided_at ? "text-decoration:line-through" : ""}>
+                                                {f.date_filled} — {f.patient_name} ({f.phone}) × {f.quantity}
+                                            </p>
+                                        )}
+                                    </For>
+                                </div>
+                            )}
+                        </Show>
+                    </Show>
+                    <p class="text-muted">{statusMsg()}</p>
                     <div class="modal-footer">
                         <button type="submit" class="btn-primary">Save Changes</button>
                     </div>
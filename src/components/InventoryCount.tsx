@@ -0,0 +1,205 @@
+import { createSignal, onMount, For, Show, type Component } from "solid-js";
+import { invoke } from "@tauri-apps/api/core";
+import { errorMessage } from "../errors";
+
+interface CountSession {
+  id: number;
+  location: string | null;
+  status: string;
+  started_at: string;
+  started_by: string;
+  posted_at: string | null;
+  approved_by: string | null;
+  line_count: number;
+  counted_lines: number;
+}
+
+interface CountLine {
+  id: number;
+  medication_name: string;
+  din: string;
+  schedule: string | null;
+  location: string | null;
+  lot_number: string;
+  expiration: string;
+  expected: number;
+  counted: number | null;
+  variance: number | null;
+  variance_value: number | null;
+  counted_by: string | null;
+}
+
+interface InventoryCountProps {
+  currentUser: { username: string; role: string } | null;
+}
+
+const InventoryCount: Component<InventoryCountProps> = (props) => {
+  const [countSessions, setCountSessions] = createSignal<CountSession[]>([]);
+  const [locations, setLocations] = createSignal<string[]>([]);
+  const [location, setLocation] = createSignal("");
+  const [selected, setSelected] = createSignal<CountSession | null>(null);
+  const [lines, setLines] = createSignal<CountLine[]>([]);
+  const [statusMsg, setStatusMsg] = createSignal("");
+
+  const isPharmacist = () => props.currentUser?.role === "pharmacist";
+
+  async function loadData() {
+    try {
+      setCountSessions(await invoke<CountSession[]>("get_count_sessions"));
+      setLocations(await invoke<string[]>("get_count_locations"));
+    } catch (e) {
+      console.error(e);
+    }
+  }
+
+  onMount(loadData);
+
+  async function openSession(session: CountSession) {
+    setSelected(session);
+    try {
+      setLines(await invoke<CountLine[]>("get_count_lines", { sessionId: session.id }));
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  async function refreshSelected() {
+    await loadData();
+    const session = countSessions().find((s) => s.id === selected()?.id);
+    if (session) openSession(session);
+  }
+
+  async function startCount() {
+    try {
+      const id = await invoke<number>("start_count_session", { location: location() || null });
+      setStatusMsg(`Count #${id} started.`);
+      await loadData();
+      const session = countSessions().find((s) => s.id === id);
+      if (session) openSession(session);
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  async function saveCount(line: CountLine, value: number) {
+    if (Number.isNaN(value)) return;
+    try {
+      await invoke("record_count", { lineId: line.id, counted: value });
+      refreshSelected();
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  async function finish(command: "post_count_session" | "cancel_count_session") {
+    const session = selected();
+    if (!session) return;
+    // Variances on a scheduled drug are witnessed (the backend requires it)
+    let witness = null;
+    if (command === "post_count_session" && lines().some((l) => l.schedule && l.variance)) {
+      const username = prompt("Witness username:");
+      if (!username) return;
+      const password = prompt(`Password for ${username}:`) ?? "";
+      witness = { username, password };
+    }
+    try {
+      setStatusMsg(await invoke(command, { sessionId: session.id, witness }));
+      refreshSelected();
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  const totalValue = () => lines().reduce((sum, l) => sum + (l.variance_value ?? 0), 0);
+
+  return (
+    <div class="p-content">
+      <div class="header-row">
+        <div><h2>Inventory Counts</h2><p class="subtitle">{countSessions().length} Sessions</p></div>
+        <div>
+          <select value={location()} onChange={(e) => setLocation(e.currentTarget.value)}>
+            <option value="">Full store</option>
+            <For each={locations()}>{(loc) => <option value={loc}>{loc}</option>}</For>
+          </select>
+          <button class="btn-primary" onClick={startCount}>Start Count</button>
+        </div>
+      </div>
+
+      <Show when={statusMsg()}>
+        <div class="alert-box">{statusMsg()}</div>
+      </Show>
+
+      <div class="panel table-panel">
+        <div class="table-container">
+          <table class="patient-table">
+            <thead>
+              <tr><th>#</th><th>Scope</th><th>Status</th><th>Counted</th><th>Started</th><th>Approved</th><th></th></tr>
+            </thead>
+            <tbody>
+              <For each={countSessions()}>
+                {(s) => (
+                  <tr>
+                    <td class="fw-bold">{s.id}</td>
+                    <td>{s.location ?? "Full store"}</td>
+                    <td>{s.status}</td>
+                    <td>{s.counted_lines} / {s.line_count}</td>
+                    <td class="text-muted">{s.started_at} by {s.started_by}</td>
+                    <td class="text-muted">{s.approved_by ?? "—"}</td>
+                    <td><button class="btn-small" onClick={() => openSession(s)}>Open</button></td>
+                  </tr>
+                )}
+              </For>
+              <Show when={countSessions().length === 0}>
+                <tr><td colspan="7" class="empty-state">No counts yet.</td></tr>
+              </Show>
+            </tbody>
+          </table>
+        </div>
+      </div>
+
+      <Show when={selected()}>
+        {(session) => (
+          <div class="panel" style="margin-top: 20px;">
+            <h3>Count #{session().id} — {session().location ?? "Full store"} ({session().status})</h3>
+            <table class="patient-table">
+              <thead>
+                <tr><th>Location</th><th>Drug</th><th>Lot</th><th>Expected</th><th>Counted</th><th>Variance</th><th>Value</th></tr>
+              </thead>
+              <tbody>
+                <For each={lines()}>
+                  {(line) => (
+                    <tr>
+                      <td class="text-muted">{line.location}</td>
+                      <td class="fw-bold">{line.medication_name}</td>
+                      <td>{line.lot_number} <span class="text-muted">({line.expiration})</span></td>
+                      <td>{line.expected}</td>
+                      <td>
+                        <Show when={session().status === "open"} fallback={line.counted}>
+                          <input type="number" style="width: 80px" value={line.counted ?? ""}
+                            onChange={(e) => saveCount(line, e.currentTarget.valueAsNumber)} />
+                        </Show>
+                      </td>
+                      <td style={line.variance && line.variance < 0 ? "color:red" : ""}>{line.variance ?? "—"}</td>
+                      <td>{line.variance_value === null ? "—" : `$${line.variance_value.toFixed(2)}`}</td>
+                    </tr>
+                  )}
+                </For>
+              </tbody>
+            </table>
+            <p class="fw-bold">Net variance value: ${totalValue().toFixed(2)}</p>
+            <Show when={session().status === "open"}>
+              <div class="modal-footer">
+                <button class="btn-small" onClick={() => finish("cancel_count_session")}>Cancel Count</button>
+                <Show when={isPharmacist()} fallback={<span class="text-muted">A pharmacist must approve and post this count.</span>}>
+                  <button class="btn-primary" onClick={() => finish("post_count_session")}>Approve &amp; Post</button>
+                </Show>
+              </div>
+            </Show>
+          </div>
+        )}
+      </Show>
+    </div>
+  );
+};
+
+export default InventoryCount;
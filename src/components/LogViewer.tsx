@@ -1,5 +1,6 @@
-import { createSignal, onMount, For, type Component } from "solid-js";
+import { createSignal, onMount, For, Show, type Component } from "solid-js";
 import { invoke } from "@tauri-apps/api/core";
+import { errorMessage } from "../errors";
 
 interface LogItem {
   id: number;
@@ -7,25 +8,150 @@ interface LogItem {
   action: string;
   details: string;
   timestamp: string;
+  entity_type: string | null;
+  entity_id: number | null;
+  before_json: string | null;
+  after_json: string | null;
+}
+
+interface LogPage {
+  items: LogItem[];
+  next_cursor: number | null;
+}
+
+interface ChainReport {
+  rows_checked: number;
+  intact: boolean;
+  first_broken_id: number | null;
+  problem: string | null;
+  latest_hash: string | null;
 }
 
 const LogViewer: Component = () => {
   const [logs, setLogs] = createSignal<LogItem[]>([]);
+  const [chain, setChain] = createSignal<ChainReport | null>(null);
+  const [statusMsg, setStatusMsg] = createSignal("");
+  const [nextCursor, setNextCursor] = createSignal<number | null>(null);
+  // Set while showing one record's history instead of the filtered log
+  const [historyOf, setHistoryOf] = createSignal<{ type: string; id: number } | null>(null);
+
+  // Filters
+  const [actions, setActions] = createSignal<string[]>([]);
+  const [username, setUsername] = createSignal("");
+  const [action, setAction] = createSignal("");
+  const [fromDate, setFromDate] = createSignal("");
+  const [toDate, setToDate] = createSignal("");
+  const [text, setText] = createSignal("");
+
+  const filter = () => ({
+    username: username() || null,
+    action: action() || null,
+    from_date: fromDate() || null,
+    to_date: toDate() || null,
+    text: text() || null,
+  });
 
-  async function fetchLogs() {
+  // Loads the first page, or appends the next one when `more` is set
+  async function fetchLogs(more = false) {
+    setHistoryOf(null);
     try {
-      const data = await invoke<LogItem[]>("get_audit_logs");
-      setLogs(data);
+      const page = await invoke<LogPage>("get_audit_logs", {
+        filter: filter(),
+        beforeId: more ? nextCursor() : null,
+      });
+      setLogs(more ? [...logs(), ...page.items] : page.items);
+      setNextCursor(page.next_cursor);
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  onMount(async () => {
+    fetchLogs();
+    try {
+      setActions(await invoke<string[]>("get_audit_actions"));
     } catch (e) {
-      console.error("Failed to fetch logs:", e);
+      console.error("Failed to fetch actions:", e);
+    }
+  });
+
+  function applyFilter(e: Event) {
+    e.preventDefault();
+    setStatusMsg("");
+    fetchLogs();
+  }
+
+  async function showHistory(entityType: string, entityId: number) {
+    try {
+      setLogs(await invoke<LogItem[]>("get_entity_history", { entityType, entityId }));
+      setNextCursor(null);
+      setHistoryOf({ type: entityType, id: entityId });
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  async function exportLogs(format: "csv" | "json") {
+    try {
+      const path = await invoke<string>("export_audit_logs", { filter: filter(), format });
+      setStatusMsg(`Exported to ${path}`);
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
     }
   }
 
-  onMount(fetchLogs);
+  async function verifyChain() {
+    try {
+      setChain(await invoke<ChainReport>("verify_audit_chain"));
+      fetchLogs();
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
 
   return (
     <div class="p-content">
-      <h2>System Audit Logs</h2>
+      <div class="header-row">
+        <h2>System Audit Logs</h2>
+        <div>
+          <button class="btn-small" onClick={() => exportLogs("csv")}>Export CSV</button>
+          <button class="btn-small" onClick={() => exportLogs("json")}>Export JSON</button>
+          <button class="btn-small" onClick={verifyChain}>Verify Integrity</button>
+        </div>
+      </div>
+      <form class="form-grid" onSubmit={applyFilter}>
+        <label>User <input value={username()} onInput={(e) => setUsername(e.currentTarget.value)} /></label>
+        <label>Action
+          <select value={action()} onChange={(e) => setAction(e.currentTarget.value)}>
+            <option value="">All</option>
+            <For each={actions()}>{(a) => <option value={a}>{a}</option>}</For>
+          </select>
+        </label>
+        <label>From <input type="date" value={fromDate()} onInput={(e) => setFromDate(e.currentTarget.value)} /></label>
+        <label>To <input type="date" value={toDate()} onInput={(e) => setToDate(e.currentTarget.value)} /></label>
+        <label>Details <input value={text()} onInput={(e) => setText(e.currentTarget.value)} /></label>
+        <button type="submit" class="btn-small">Search</button>
+      </form>
+      <Show when={statusMsg()}>
+        <div class="alert-box">{statusMsg()}</div>
+      </Show>
+      <Show when={chain()}>
+        {(r) => (
+          <div class="alert-box" style={r().intact ? "" : "color:red"}>
+            {r().intact
+              ? `Chain intact: ${r().rows_checked} entries verified. Latest hash ${r().latest_hash ?? "—"}`
+              : `Chain broken after ${r().rows_checked} entries. ${r().problem}`}
+          </div>
+        )}
+      </Show>
+      <Show when={historyOf()}>
+        {(h) => (
+          <div class="alert-box">
+            History of {h().type.replace("_", " ")} #{h().id}{" "}
+            <button class="btn-small" onClick={() => fetchLogs()}>Back to Log</button>
+          </div>
+        )}
+      </Show>
       <div class="panel table-panel">
         <div class="table-container">
           <table class="patient-table">
@@ -34,23 +160,46 @@ const LogViewer: Component = () => {
                 <th style="width: 180px;">Timestamp</th>
                 <th style="width: 100px;">User</th>
                 <th style="width: 150px;">Action</th>
+                <th style="width: 140px;">Record</th>
                 <th>Details</th>
               </tr>
             </thead>
             <tbody>
               <For each={logs()}>
                 {(log) => (
-                  <tr>
+                  <tr style={chain()?.first_broken_id === log.id ? "background:#fee2e2" : ""}>
                     <td class="text-muted" style="font-size: 0.85rem;">{log.timestamp}</td>
                     <td class="fw-bold">{log.username}</td>
                     <td><span class="badge-gray">{log.action}</span></td>
-                    <td class="text-muted">{log.details}</td>
+                    <td>
+                      <Show when={log.entity_type !== null && log.entity_id !== null} fallback="—">
+                        <a href="#" onClick={(e) => { e.preventDefault(); showHistory(log.entity_type!, log.entity_id!); }}>
+                          {log.entity_type!.replace("_", " ")} #{log.entity_id}
+                        </a>
+                      </Show>
+                    </td>
+                    <td class="text-muted">
+                      {log.details}
+                      <Show when={log.before_json || log.after_json}>
+                        <details>
+                          <summary>Changes</summary>
+                          <Show when={log.before_json}><pre>Before: {log.before_json}</pre></Show>
+                          <Show when={log.after_json}><pre>After: {log.after_json}</pre></Show>
+                        </details>
+                      </Show>
+                    </td>
                   </tr>
                 )}
               </For>
+              <Show when={logs().length === 0}>
+                <tr><td colspan="5" class="empty-state">No entries match.</td></tr>
+              </Show>
             </tbody>
           </table>
         </div>
+        <Show when={nextCursor() !== null}>
+          <button class="btn-small" onClick={() => fetchLogs(true)}>Load More</button>
+        </Show>
       </div>
     </div>
   );
@@ -1,5 +1,6 @@
-import { createSignal, type Component } from "solid-js";
+import { createSignal, Show, type Component } from "solid-js";
 import { invoke } from "@tauri-apps/api/core";
+import { errorMessage } from "../errors";
 
 interface LoginProps {
   onLogin: (user: { username: string; role: string }) => void;
@@ -10,6 +11,11 @@ const Login: Component<LoginProps> = (props) => {
   const [password, setPassword] = createSignal("");
   const [error, setError] = createSignal("");
 
+  // Forced rotation for default / reset accounts
+  const [pendingUser, setPendingUser] = createSignal<{ username: string; role: string } | null>(null);
+  const [newPassword, setNewPassword] = createSignal("");
+  const [confirmPassword, setConfirmPassword] = createSignal("");
+
   async function handleLogin(e: Event) {
     e.preventDefault();
     try {
@@ -17,11 +23,30 @@ const Login: Component<LoginProps> = (props) => {
         creds: { username: username(), password: password() } 
       });
       
-      if (res.success) {
+      if (res.success && res.must_change_password) {
+        setError("");
+        setPendingUser({ username: res.username, role: res.role });
+      } else if (res.success) {
         props.onLogin({ username: res.username, role: res.role });
       }
     } catch (err) {
-      setError("Invalid username or password");
+      setError(errorMessage(err));
+    }
+  }
+
+  async function handleChangePassword(e: Event) {
+    e.preventDefault();
+    if (newPassword() !== confirmPassword()) {
+      setError("Passwords do not match");
+      return;
+    }
+    try {
+      await invoke("change_password", {
+        data: { current_password: password(), new_password: newPassword() }
+      });
+      props.onLogin(pendingUser()!);
+    } catch (err) {
+      setError(errorMessage(err));
     }
   }
 
@@ -29,6 +54,25 @@ const Login: Component<LoginProps> = (props) => {
     <div class="login-container">
       <div class="login-box">
         <h2>Blisstech Secure Login</h2>
+        <Show when={pendingUser()}>
+          <p>You must choose a new password before continuing.</p>
+          <form onSubmit={handleChangePassword}>
+            <input 
+              type="password" 
+              placeholder="New Password" 
+              value={newPassword()} 
+              onInput={(e) => setNewPassword(e.currentTarget.value)} 
+            />
+            <input 
+              type="password" 
+              placeholder="Confirm New Password" 
+              value={confirmPassword()} 
+              onInput={(e) => setConfirmPassword(e.currentTarget.value)} 
+            />
+            <button type="submit" class="btn-primary" style="width: 100%">Change Password</button>
+          </form>
+        </Show>
+        <Show when={!pendingUser()}>
         <form onSubmit={handleLogin}>
           <input 
             placeholder="Username" 
@@ -43,6 +87,7 @@ const Login: Component<LoginProps> = (props) => {
           />
           <button type="submit" class="btn-primary" style="width: 100%">Login</button>
         </form>
+        </Show>
         <p class="error-text">{error()}</p>
       </div>
     </div>
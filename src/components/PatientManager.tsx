@@ -1,5 +1,6 @@
 import { createSignal, onMount, For, Show, type Component } from "solid-js";
 import { invoke } from "@tauri-apps/api/core";
+import { errorMessage } from "../errors";
 
 // --- TYPES ---
 interface Patient {
@@ -20,18 +21,23 @@ interface Patient {
 
 interface HistoryItem {
   id: number;
+  prescription_id: number;
+  fill_number: number;
+  status: string;
   drug_name: string;
+  schedule: string | null;
   sig: string;
   quantity: number;
   date_filled: string;
   next_refill_date: string;
+  voided_at: string | null;
 }
 
 interface PatientManagerProps {
   currentUser: { username: string; role: string } | null;
 }
 
-const PatientManager: Component<PatientManagerProps> = (props) => {
+const PatientManager: Component<PatientManagerProps> = (_props) => {
   // --- STATE ---
   const [patientList, setPatientList] = createSignal<Patient[]>([]);
   const [isAddModalOpen, setAddModalOpen] = createSignal(false);
@@ -65,18 +71,66 @@ const PatientManager: Component<PatientManagerProps> = (props) => {
   onMount(fetchPatients);
 
   // --- FETCH DETAILS & HISTORY ---
-  async function openPatientDetails(patient: Patient) {
-    setSelectedPatient(patient);
-    setHistory([]); 
-    
+  async function loadHistory(patientId: number) {
     try {
-      const data = await invoke<HistoryItem[]>("get_patient_history", { patientId: patient.id });
+      const data = await invoke<HistoryItem[]>("get_patient_history", { patientId });
       setHistory(data);
     } catch (e) {
       console.error("Failed to load history:", e);
     }
   }
 
+  async function openPatientDetails(patient: Patient) {
+    setSelectedPatient(patient);
+    setHistory([]); 
+    await loadHistory(patient.id);
+  }
+
+  // History is newest first, so the first row per prescription carries its actions
+  const isLatestFill = (item: HistoryItem) =>
+    history().find((h) => h.prescription_id === item.prescription_id) === item;
+
+  async function voidFill(item: HistoryItem) {
+    const reason = window.prompt(`Void this fill of ${item.drug_name} (${item.date_filled})? Stock will be returned.\nReason:`);
+    if (!reason) return;
+    // Returning a scheduled drug to stock is witnessed (the backend requires it)
+    let witness = null;
+    if (item.schedule) {
+      const username = window.prompt("Witness username:");
+      if (!username) return;
+      const password = window.prompt(`Password for ${username}:`) ?? "";
+      witness = { username, password };
+    }
+    try {
+      setStatusMsg(await invoke<string>("void_fill", { fillId: item.id, reason, witness }));
+      await loadHistory(selectedPatient()!.id);
+    } catch (err) {
+      setStatusMsg(`Error: ${errorMessage(err)}`);
+    }
+  }
+
+  // --- PRESCRIPTION STATUS ---
+  async function changeRxStatus(item: HistoryItem, action: "hold" | "resume" | "cancel" | "transfer") {
+    const args: Record<string, unknown> = { prescriptionId: item.prescription_id };
+    if (action === "hold") {
+      args.reason = window.prompt("Reason for hold (optional):") ?? undefined;
+    } else if (action === "cancel") {
+      const reason = window.prompt("Reason for cancelling this prescription:");
+      if (!reason) return;
+      args.reason = reason;
+    } else if (action === "transfer") {
+      const pharmacy = window.prompt("Transfer to which pharmacy?");
+      if (!pharmacy) return;
+      args.pharmacy = pharmacy;
+    }
+    try {
+      setStatusMsg(await invoke<string>(`${action}_prescription`, args));
+      await loadHistory(selectedPatient()!.id);
+    } catch (err) {
+      setStatusMsg(`Error: ${errorMessage(err)}`);
+    }
+  }
+
   // --- SAVE NEW PATIENT ---
   async function handleSave(e: Event) {
     e.preventDefault();
@@ -85,9 +139,6 @@ const PatientManager: Component<PatientManagerProps> = (props) => {
     const formData = new FormData(e.target as HTMLFormElement);
     
     const payload = {
-      // AUDIT LOGGING: Pass the username
-      logged_in_user: props.currentUser?.username || "unknown",
-      
       name: formData.get("name") as string,
       birth_date: formData.get("birth_date") as string,
       phone: formData.get("phone") as string,
@@ -110,7 +161,7 @@ const PatientManager: Component<PatientManagerProps> = (props) => {
       (e.target as HTMLFormElement).reset();
     } catch (err) {
       console.error(err);
-      setStatusMsg("Error saving patient.");
+      setStatusMsg("Error saving patient: " + errorMessage(err));
     }
   }
 
@@ -256,12 +307,13 @@ const PatientManager: Component<PatientManagerProps> = (props) => {
                                       <th>Sig (Instructions)</th>
                                       <th>Qty</th>
                                       <th>Due</th>
+                                      <th>Status</th>
                                   </tr>
                               </thead>
                               <tbody>
                                   <For each={history()}>
                                       {(item) => (
-                                          <tr>
+                                          <tr style={item.voided_at ? "text-decoration: line-through; color: #94a3b8" : ""}>
                                               <td>{item.date_filled}</td>
                                               <td class="fw-bold">{item.drug_name}</td>
                                               <td class="text-truncate" style="max-width: 150px;" title={item.sig}>{item.sig}</td>
@@ -271,11 +323,29 @@ const PatientManager: Component<PatientManagerProps> = (props) => {
                                               <td style={ new Date(item.next_refill_date) <= new Date() ? "color: #ef4444; font-weight: bold" : "color: #10b981" }>
                                                   {item.next_refill_date}
                                               </td>
+                                              <td>
+                                                  <Show when={isLatestFill(item)}>
+                                                      <span class="text-muted">{item.status.replace("_", " ")}</span>
+                                                      <Show when={item.status === "active"}>
+                                                          <button class="btn-small" onClick={() => changeRxStatus(item, "hold")}>Hold</button>
+                                                      </Show>
+                                                      <Show when={item.status === "on_hold"}>
+                                                          <button class="btn-small" onClick={() => changeRxStatus(item, "resume")}>Resume</button>
+                                                      </Show>
+                                                      <Show when={item.status === "active" || item.status === "on_hold"}>
+                                                          <button class="btn-small" onClick={() => changeRxStatus(item, "cancel")}>Cancel</button>
+                                                          <button class="btn-small" onClick={() => changeRxStatus(item, "transfer")}>Transfer</button>
+                                                      </Show>
+                                                  </Show>
+                                                  <Show when={!item.voided_at}>
+                                                      <button class="btn-small" onClick={() => voidFill(item)}>Void</button>
+                                                  </Show>
+                                              </td>
                                           </tr>
                                       )}
                                   </For>
                                   <Show when={history().length === 0}>
-                                      <tr><td colspan="5" class="empty-state">No prescription history found.</td></tr>
+                                      <tr><td colspan="6" class="empty-state">No prescription history found.</td></tr>
                                   </Show>
                               </tbody>
                           </table>
@@ -284,6 +354,7 @@ const PatientManager: Component<PatientManagerProps> = (props) => {
                 </div>
 
                 <div class="modal-footer">
+                    <span class="status">{statusMsg()}</span>
                     <button class="btn-secondary" onClick={() => setSelectedPatient(null)}>Close</button>
                 </div>
             </div>
@@ -1,10 +1,12 @@
-import { createSignal, onMount, For, Show, type Component } from "solid-js";
+import { createSignal, createEffect, onMount, For, Show, type Component } from "solid-js";
 import { invoke } from "@tauri-apps/api/core";
-import { refillQueue, setRefillQueue } from "../store";
+import { errorMessage, type AppError } from "../errors";
+import { invokeWithOverrides } from "../overrides";
 
 // --- TYPES ---
 interface Patient { id: number; name: string; }
 interface Medication { id: number; name: string; stock: number; din: string; }
+type ClinicalAlert = NonNullable<AppError["alerts"]>[number];
 
 // Define Props Interface
 interface PrescriptionManagerProps {
@@ -26,6 +28,37 @@ const PrescriptionManager: Component<PrescriptionManagerProps> = (props) => {
 
   const [statusMsg, setStatusMsg] = createSignal("");
   const [isSuccess, setIsSuccess] = createSignal(false);
+  const [alerts, setAlerts] = createSignal<ClinicalAlert[]>([]);
+
+  // Re-screen whenever the patient/drug pair changes
+  createEffect(async () => {
+    const pid = selectedPid();
+    const mid = selectedMedId();
+    if (!pid || !mid) {
+      setAlerts([]);
+      return;
+    }
+    try {
+      setAlerts(await invoke<ClinicalAlert[]>("screen_prescription", { patientId: parseInt(pid), medicationId: parseInt(mid) }));
+    } catch (e) {
+      console.error("Screening failed:", e);
+    }
+  });
+
+  async function handleImportInteractions(e: Event) {
+    const input = e.currentTarget as HTMLInputElement;
+    const file = input.files?.[0];
+    if (!file) return;
+    try {
+      const records = JSON.parse(await file.text());
+      setStatusMsg(await invoke<string>("import_interactions", { records }));
+      setIsSuccess(true);
+    } catch (err) {
+      setStatusMsg(`Import Failed: ${errorMessage(err)}`);
+      setIsSuccess(false);
+    }
+    input.value = "";
+  }
 
   async function loadData() {
     try {
@@ -38,23 +71,7 @@ const PrescriptionManager: Component<PrescriptionManagerProps> = (props) => {
     }
   }
 
-  onMount(async () => {
-    await loadData();
-
-    const pending = refillQueue();
-    if (pending) {
-      console.log("Pre-filling from dashboard:", pending);
-      setSelectedPid(pending.patient_id.toString());
-      setSelectedMedId(pending.medication_id.toString());
-      setPrescriber(pending.prescriber);
-      setSig(pending.sig);
-      setQuantity(pending.quantity);
-      setDaysSupply(pending.days_supply);
-      setRefills(pending.refills > 0 ? pending.refills - 1 : 0);
-      setStatusMsg(`Loaded refill data for ${pending.patient_name}`);
-      setRefillQueue(null); 
-    }
-  });
+  onMount(loadData);
 
   async function handleFill(e: Event) {
     e.preventDefault();
@@ -70,9 +87,6 @@ const PrescriptionManager: Component<PrescriptionManagerProps> = (props) => {
     }
 
     const payload = {
-      // --- FIX: SEND THE USERNAME ---
-      logged_in_user: props.currentUser?.username || "system_unknown", 
-      // ------------------------------
       patient_id: parseInt(selectedPid()),
       medication_id: parseInt(selectedMedId()),
       prescriber: prescriber(),
@@ -84,7 +98,12 @@ const PrescriptionManager: Component<PrescriptionManagerProps> = (props) => {
     };
 
     try {
-      await invoke("create_prescription", { data: payload });
+      // Blocking alerts and too-soon fills only go through with a documented reason
+      const result = await invokeWithOverrides<string>("create_prescription", (o) => ({ data: { ...payload, ...o } }));
+      if (result === null) {
+        setStatusMsg("Not filled.");
+        return;
+      }
       setStatusMsg("✓ Prescription Filled & Inventory Updated");
       setIsSuccess(true);
       loadData(); 
@@ -98,7 +117,7 @@ const PrescriptionManager: Component<PrescriptionManagerProps> = (props) => {
       setRefills("");
     } catch (err) {
       console.error(err);
-      setStatusMsg(`Transaction Failed: ${err}`);
+      setStatusMsg(`Transaction Failed: ${errorMessage(err)}`);
     }
   }
 
@@ -187,6 +206,25 @@ const PrescriptionManager: Component<PrescriptionManagerProps> = (props) => {
           <div style="margin-top: 20px; font-size: 0.9em; color: #64748b;">
              <p><strong>Selected Patient:</strong> {selectedPid() ? patients().find(p => p.id.toString() === selectedPid())?.name : "None"}</p>
           </div>
+
+          <Show when={alerts().length > 0}>
+            <div class="alert-box" style="margin-top: 20px;">
+              <strong>Clinical Alerts</strong>
+              <ul class="guide-list">
+                <For each={alerts()}>
+                  {(a) => <li class={a.level === "warn" ? "" : "status-error"}>{a.level === "hard_stop" ? "⛔ " : "⚠️ "}{a.message}</li>}
+                </For>
+              </ul>
+            </div>
+          </Show>
+
+          <Show when={props.currentUser?.role === "pharmacist"}>
+            <div style="margin-top: 20px; font-size: 0.9em;">
+              <label>Import interaction table (JSON)
+                <input type="file" accept=".json,application/json" onChange={handleImportInteractions} />
+              </label>
+            </div>
+          </Show>
         </div>
       </div>
     </div>
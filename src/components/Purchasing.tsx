@@ -0,0 +1,256 @@
+import { createSignal, onMount, For, Show, type Component } from "solid-js";
+import { invoke } from "@tauri-apps/api/core";
+import { errorMessage } from "../errors";
+
+interface Supplier {
+  id: number;
+  name: string;
+  contact?: string;
+  phone?: string;
+  email?: string;
+  active: boolean;
+}
+
+interface PurchaseOrder {
+  id: number;
+  supplier_id: number;
+  supplier_name: string;
+  status: string;
+  notes?: string;
+  created_at: string;
+  created_by: string;
+  ordered_at?: string;
+  line_count: number;
+  units_ordered: number;
+  units_received: number;
+}
+
+interface PoLine {
+  id: number;
+  medication_name: string;
+  din: string;
+  quantity_ordered: number;
+  quantity_received: number;
+  unit_cost: number | null;
+}
+
+const Purchasing: Component = () => {
+  const [suppliers, setSuppliers] = createSignal<Supplier[]>([]);
+  const [orders, setOrders] = createSignal<PurchaseOrder[]>([]);
+  const [supplierId, setSupplierId] = createSignal<number | null>(null);
+  const [statusMsg, setStatusMsg] = createSignal("");
+
+  // New supplier form
+  const [supplierName, setSupplierName] = createSignal("");
+  const [supplierPhone, setSupplierPhone] = createSignal("");
+
+  // Selected order and the line being received
+  const [selected, setSelected] = createSignal<PurchaseOrder | null>(null);
+  const [lines, setLines] = createSignal<PoLine[]>([]);
+  const [receiving, setReceiving] = createSignal<PoLine | null>(null);
+  const [rcvQty, setRcvQty] = createSignal<number | "">("");
+  const [rcvLot, setRcvLot] = createSignal("");
+  const [rcvExp, setRcvExp] = createSignal("");
+  const [rcvCost, setRcvCost] = createSignal<number | "">("");
+
+  async function loadData() {
+    try {
+      const list = await invoke<Supplier[]>("get_suppliers");
+      setSuppliers(list);
+      if (supplierId() === null && list.length > 0) setSupplierId(list[0].id);
+      setOrders(await invoke<PurchaseOrder[]>("get_purchase_orders"));
+    } catch (e) {
+      console.error(e);
+    }
+  }
+
+  onMount(loadData);
+
+  async function openOrder(po: PurchaseOrder) {
+    setSelected(po);
+    setReceiving(null);
+    try {
+      setLines(await invoke<PoLine[]>("get_purchase_order_lines", { purchaseOrderId: po.id }));
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  async function refreshSelected() {
+    await loadData();
+    const po = orders().find((o) => o.id === selected()?.id);
+    if (po) openOrder(po);
+  }
+
+  async function addSupplier(e: Event) {
+    e.preventDefault();
+    try {
+      setStatusMsg(await invoke("add_supplier", {
+        data: { name: supplierName(), contact: null, phone: supplierPhone() || null, email: null }
+      }));
+      setSupplierName(""); setSupplierPhone("");
+      loadData();
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  async function draftFromReorderList() {
+    if (supplierId() === null) return;
+    try {
+      const id = await invoke<number>("create_reorder_purchase_order", { supplierId: supplierId() });
+      setStatusMsg(`Drafted PO #${id} from the reorder list.`);
+      await loadData();
+      const po = orders().find((o) => o.id === id);
+      if (po) openOrder(po);
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  async function changeStatus(command: "submit_purchase_order" | "cancel_purchase_order", po: PurchaseOrder) {
+    try {
+      setStatusMsg(await invoke(command, { purchaseOrderId: po.id }));
+      refreshSelected();
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  function startReceive(line: PoLine) {
+    setReceiving(line);
+    setRcvQty(line.quantity_ordered - line.quantity_received);
+    setRcvLot(""); setRcvExp("");
+    setRcvCost(line.unit_cost ?? "");
+  }
+
+  async function handleReceive(e: Event) {
+    e.preventDefault();
+    const line = receiving();
+    if (!line) return;
+    try {
+      setStatusMsg(await invoke("receive_purchase_order_line", {
+        data: { line_id: line.id, quantity: Number(rcvQty()), lot_number: rcvLot(), expiration: rcvExp(), unit_cost: Number(rcvCost()) }
+      }));
+      refreshSelected();
+    } catch (err) {
+      setStatusMsg("Error: " + errorMessage(err));
+    }
+  }
+
+  const canReceive = () => ["ordered", "partially_received"].includes(selected()?.status ?? "");
+
+  return (
+    <div class="p-content">
+      <div class="header-row">
+        <div><h2>Purchasing</h2><p class="subtitle">{orders().length} Purchase Orders</p></div>
+        <div>
+          <select value={supplierId() ?? ""} onChange={(e) => setSupplierId(Number(e.currentTarget.value))}>
+            <For each={suppliers().filter((s) => s.active)}>
+              {(s) => <option value={s.id}>{s.name}</option>}
+            </For>
+          </select>
+          <button class="btn-primary" onClick={draftFromReorderList}>Draft PO from Reorder List</button>
+        </div>
+      </div>
+
+      <Show when={statusMsg()}>
+        <div class="alert-box">{statusMsg()}</div>
+      </Show>
+
+      <div class="panel table-panel">
+        <div class="table-container">
+          <table class="patient-table">
+            <thead>
+              <tr><th>PO</th><th>Supplier</th><th>Status</th><th>Received</th><th>Created</th><th>Action</th></tr>
+            </thead>
+            <tbody>
+              <For each={orders()}>
+                {(po) => (
+                  <tr>
+                    <td class="fw-bold">#{po.id}</td>
+                    <td>{po.supplier_name}</td>
+                    <td>{po.status.replace("_", " ")}</td>
+                    <td>{po.units_received} / {po.units_ordered}</td>
+                    <td class="text-muted">{po.created_at} by {po.created_by}</td>
+                    <td>
+                      <button class="btn-small" onClick={() => openOrder(po)}>Open</button>
+                      <Show when={po.status === "draft"}>
+                        <button class="btn-small" onClick={() => changeStatus("submit_purchase_order", po)}>Mark Ordered</button>
+                      </Show>
+                      <Show when={po.status === "draft" || po.status === "ordered"}>
+                        <button class="btn-small" onClick={() => changeStatus("cancel_purchase_order", po)}>Cancel</button>
+                      </Show>
+                    </td>
+                  </tr>
+                )}
+              </For>
+              <Show when={orders().length === 0}>
+                <tr><td colspan="6" class="empty-state">No purchase orders yet.</td></tr>
+              </Show>
+            </tbody>
+          </table>
+        </div>
+      </div>
+
+      <Show when={selected()}>
+        {(po) => (
+          <div class="panel" style="margin-top: 20px;">
+            <h3>PO #{po().id} — {po().supplier_name} ({po().status.replace("_", " ")})</h3>
+            <table class="patient-table">
+              <thead>
+                <tr><th>DIN</th><th>Drug</th><th>Ordered</th><th>Received</th><th>Cost</th><th></th></tr>
+              </thead>
+              <tbody>
+                <For each={lines()}>
+                  {(line) => (
+                    <tr>
+                      <td class="text-muted">{line.din}</td>
+                      <td class="fw-bold">{line.medication_name}</td>
+                      <td>{line.quantity_ordered}</td>
+                      <td>{line.quantity_received}</td>
+                      <td>{line.unit_cost === null ? "—" : `$${line.unit_cost.toFixed(2)}`}</td>
+                      <td>
+                        <Show when={canReceive() && line.quantity_received < line.quantity_ordered}>
+                          <button class="btn-small" onClick={() => startReceive(line)}>Receive</button>
+                        </Show>
+                      </td>
+                    </tr>
+                  )}
+                </For>
+              </tbody>
+            </table>
+
+            <Show when={receiving()}>
+              {(line) => (
+                <form onSubmit={handleReceive} class="modal-form">
+                  <h4>Receive {line().medication_name}</h4>
+                  <div class="form-grid">
+                    <label>Quantity <input type="number" value={rcvQty()} onInput={(e) => setRcvQty(e.currentTarget.valueAsNumber)} required /></label>
+                    <label>Lot Number <input value={rcvLot()} onInput={(e) => setRcvLot(e.currentTarget.value)} required /></label>
+                    <label>Expiry <input type="date" value={rcvExp()} onInput={(e) => setRcvExp(e.currentTarget.value)} required /></label>
+                    <label>Unit Cost <input type="number" step="0.01" value={rcvCost()} onInput={(e) => setRcvCost(e.currentTarget.valueAsNumber)} required /></label>
+                  </div>
+                  <div class="modal-footer">
+                    <button type="submit" class="btn-primary">Book Receipt</button>
+                  </div>
+                </form>
+              )}
+            </Show>
+          </div>
+        )}
+      </Show>
+
+      <div class="panel" style="margin-top: 20px;">
+        <h3>Add Supplier</h3>
+        <form onSubmit={addSupplier} class="form-grid">
+          <label>Name <input value={supplierName()} onInput={(e) => setSupplierName(e.currentTarget.value)} required /></label>
+          <label>Phone <input value={supplierPhone()} onInput={(e) => setSupplierPhone(e.currentTarget.value)} /></label>
+          <button type="submit" class="btn-small">Add</button>
+        </form>
+      </div>
+    </div>
+  );
+};
+
+export default Purchasing;
@@ -0,0 +1,22 @@
+// Shape of every error returned by a Rust command (see src-tauri/src/error.rs)
+export interface AppError {
+  code: string;
+  message: string;
+  fields?: { field: string; message: string }[];
+  alerts?: { kind: string; level: "warn" | "override" | "hard_stop"; message: string }[];
+}
+
+export function isAppError(err: unknown, code: string): err is AppError {
+  return !!err && typeof err === "object" && (err as AppError).code === code;
+}
+
+export function errorMessage(err: unknown): string {
+  if (err && typeof err === "object" && "message" in err) {
+    const e = err as AppError;
+    if (e.fields && e.fields.length > 1) {
+      return e.fields.map((f) => `${f.field}: ${f.message}`).join("; ");
+    }
+    return e.message;
+  }
+  return String(err);
+}
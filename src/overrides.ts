@@ -0,0 +1,52 @@
+import { invoke } from "@tauri-apps/api/core";
+import { isAppError, type AppError } from "./errors";
+
+// Fill commands can be refused for a clinical alert or a too-soon date. Each
+// refusal is shown to the user, who can supply the override and retry.
+export interface FillOverrides {
+  override_reason?: string;
+  early_fill_code?: string;
+}
+
+// Lists the alerts and asks for an override reason; null when cancelled or blank.
+function promptOverrideReason(err: AppError): string | null {
+  const alerts = (err.alerts ?? []).map((a) => `• ${a.message}`).join("\n");
+  const reason = window.prompt(`${alerts}\n\nEnter an override reason to fill anyway:`);
+  return reason && reason.trim() ? reason.trim() : null;
+}
+
+// Asks for an early-fill reason by number or code; null when cancelled or unknown.
+async function promptEarlyFillCode(err: AppError): Promise<string | null> {
+  const reasons = await invoke<{ code: string; label: string }[]>("get_early_fill_reasons");
+  const list = reasons.map((r, i) => `${i + 1}. ${r.label}`).join("\n");
+  const answer = window.prompt(`${err.message}\n\nTo fill early, enter a reason number:\n${list}`)?.trim();
+  if (!answer) return null;
+  const picked = reasons[Number(answer) - 1] ?? reasons.find((r) => r.code === answer);
+  return picked ? picked.code : null;
+}
+
+// Runs a fill command, prompting for overrides as needed. `buildArgs` places the
+// overrides wherever that command expects them. Resolves to null if the user backs out.
+export async function invokeWithOverrides<T>(
+  command: string,
+  buildArgs: (overrides: FillOverrides) => Record<string, unknown>,
+): Promise<T | null> {
+  const overrides: FillOverrides = {};
+  for (;;) {
+    try {
+      return await invoke<T>(command, buildArgs(overrides));
+    } catch (err) {
+      if (isAppError(err, "CLINICAL_ALERT") && !overrides.override_reason) {
+        const reason = promptOverrideReason(err);
+        if (!reason) return null;
+        overrides.override_reason = reason;
+      } else if (isAppError(err, "TOO_SOON") && !overrides.early_fill_code) {
+        const code = await promptEarlyFillCode(err);
+        if (!code) return null;
+        overrides.early_fill_code = code;
+      } else {
+        throw err;
+      }
+    }
+  }
+}